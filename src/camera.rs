@@ -1,9 +1,12 @@
 use std;
 use std::collections::HashSet;
 use std::time::Duration;
+use core::num::NonZeroU32;
 use winit::event::{MouseButton, VirtualKeyCode};
 use boolinator::Boolinator;
+use gltf::camera::Projection as GltfProjection;
 use ammolite_math::*;
+use crate::CameraTransforms;
 
 pub trait Camera: std::fmt::Debug {
     fn get_view_translation_matrix(&self) -> Mat4;
@@ -168,6 +171,183 @@ impl Camera for PitchYawCamera3 {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        /// Uses the aspect ratio of the viewport if `None`
+        aspect_ratio: Option<f32>,
+        /// The vertical field of view, in radians
+        yfov: f32,
+        znear: f32,
+        /// An infinite projection is used if `None`
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        /// Half of the horizontal extent of the view
+        xmag: f32,
+        /// Half of the vertical extent of the view
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl CameraProjection {
+    pub fn get_projection_matrix(&self, dimensions: [NonZeroU32; 2]) -> Mat4 {
+        match *self {
+            CameraProjection::Perspective { aspect_ratio, yfov, znear, zfar } => {
+                let aspect_ratio = aspect_ratio.unwrap_or_else(|| {
+                    dimensions[0].get() as f32 / dimensions[1].get() as f32
+                });
+                let angle_up = yfov / 2.0;
+                let angle_right = (angle_up.tan() * aspect_ratio).atan();
+
+                if let Some(zfar) = zfar {
+                    construct_perspective_projection_matrix_asymmetric(
+                        znear, zfar,
+                        angle_right, angle_up, -angle_right, -angle_up,
+                    )
+                } else {
+                    // The limit of the finite projection as the far plane approaches infinity
+                    let mut matrix = construct_perspective_projection_matrix_asymmetric(
+                        znear, 1.0,
+                        angle_right, angle_up, -angle_right, -angle_up,
+                    );
+
+                    matrix[2][2] = -1.0;
+                    matrix[3][2] = -znear;
+
+                    matrix
+                }
+            },
+            CameraProjection::Orthographic { xmag, ymag, znear, zfar } => {
+                // Cameras look in the direction of the negative Z axis, map `[-znear; -zfar]` to
+                // `[0; 1]`.
+                mat4!([1.0 / xmag,        0.0,                   0.0,                      0.0,
+                              0.0, 1.0 / ymag,                   0.0,                      0.0,
+                              0.0,        0.0, -1.0 / (zfar - znear), -znear / (zfar - znear),
+                              0.0,        0.0,                   0.0,                      1.0])
+            },
+        }
+    }
+}
+
+/// A camera defined in a glTF document, placed in the world by the node it is attached to.
+///
+/// The camera does not react to user input. To follow an animated node, create a new `GltfCamera`
+/// from the current pose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfCamera {
+    name: Option<String>,
+    world_matrix: Mat4,
+    projection: CameraProjection,
+}
+
+impl GltfCamera {
+    /// Creates a camera from the camera of a node, given the world matrix of the node.
+    pub fn from_gltf(camera: &gltf::Camera, world_matrix: Mat4) -> Self {
+        let projection = match camera.projection() {
+            GltfProjection::Perspective(perspective) => CameraProjection::Perspective {
+                aspect_ratio: perspective.aspect_ratio(),
+                yfov: perspective.yfov(),
+                znear: perspective.znear(),
+                zfar: perspective.zfar(),
+            },
+            GltfProjection::Orthographic(orthographic) => CameraProjection::Orthographic {
+                xmag: orthographic.xmag(),
+                ymag: orthographic.ymag(),
+                znear: orthographic.znear(),
+                zfar: orthographic.zfar(),
+            },
+        };
+
+        Self {
+            name: camera.name().map(String::from),
+            world_matrix,
+            projection,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    pub fn world_matrix(&self) -> &Mat4 {
+        &self.world_matrix
+    }
+
+    pub fn projection(&self) -> &CameraProjection {
+        &self.projection
+    }
+
+    /// The rotation of the node, without scale
+    fn get_rotation_matrix(&self) -> Mat4 {
+        let mut rotation = Mat4::IDENTITY;
+
+        for column in 0..3 {
+            let mut axis = Vec3([
+                self.world_matrix[column][0],
+                self.world_matrix[column][1],
+                self.world_matrix[column][2],
+            ]);
+
+            axis.normalize_mut();
+
+            for row in 0..3 {
+                rotation[column][row] = axis[row];
+            }
+        }
+
+        rotation
+    }
+
+    /// The transforms to return from `MediumData::get_camera_transforms`.
+    pub fn get_camera_transforms(&self, dimensions: [NonZeroU32; 2]) -> CameraTransforms {
+        CameraTransforms {
+            position: self.get_position(),
+            view_matrix: self.get_view_matrix(),
+            projection_matrix: self.projection.get_projection_matrix(dimensions),
+        }
+    }
+}
+
+impl Camera for GltfCamera {
+    fn get_view_rotation_matrix(&self) -> Mat4 {
+        // The inverse of a rotation matrix is its transpose
+        self.get_rotation_matrix().transpose()
+    }
+
+    fn get_view_translation_matrix(&self) -> Mat4 {
+        Mat4::translation(&-self.get_position())
+    }
+
+    fn get_position(&self) -> Vec3 {
+        Vec3([self.world_matrix[3][0], self.world_matrix[3][1], self.world_matrix[3][2]])
+    }
+
+    fn get_direction(&self) -> Vec3 {
+        let rotation = self.get_rotation_matrix();
+
+        -Vec3([rotation[2][0], rotation[2][1], rotation[2][2]])
+    }
+
+    fn get_rotation_axis_angles(&self) -> Vec3 {
+        // Decompose the rotation into `roll * yaw * pitch`, see `PitchYawCamera3::update`
+        let rotation = self.get_rotation_matrix();
+        let pitch = rotation[1][2].atan2(rotation[2][2]);
+        let yaw = (-rotation[0][2]).max(-1.0).min(1.0).asin();
+        let roll = rotation[0][1].atan2(rotation[0][0]);
+
+        [pitch, yaw, roll].into()
+    }
+
+    fn update(&mut self,
+              _delta_time: &Duration,
+              _cursor_delta: &[f64; 2],
+              _pressed_keys: &HashSet<VirtualKeyCode>,
+              _pressed_mouse_buttons: &HashSet<MouseButton>) {}
+}
+
 pub fn construct_model_matrix(scale: f32, translation: &Vec3, rotation: &Vec3) -> Mat4 {
     Mat4::translation(translation)
         * Mat4::rotation_roll(rotation[2])
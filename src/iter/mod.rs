@@ -97,6 +97,9 @@ pub struct ByteBufferIterator<'a, T: PodTransmutable> {
     _phantom_data: PhantomData<T>,
 }
 
+/// Large enough for the largest accessor element, a 4x4 matrix of `f32`s
+pub(crate) static ZERO_ELEMENT: [u8; 64] = [0; 64];
+
 impl<'a, T: PodTransmutable> ByteBufferIterator<'a, T> {
     pub fn new(bytes: &'a [u8], stride: usize, items: usize) -> Self {
         assert!(items == 0 || (items - 1) * stride + mem::size_of::<T>() <= bytes.len());
@@ -114,7 +117,12 @@ impl<'a, T: PodTransmutable> ByteBufferIterator<'a, T> {
         buffer_data_array: &'a [gltf::buffer::Data],
         accessor: &Accessor,
     ) -> Self {
-        let view = accessor.view();
+        let view = if let Some(view) = accessor.view() {
+            view
+        } else {
+            // The elements of accessors without a buffer view are zeros
+            return Self::new(&ZERO_ELEMENT[..], 0, accessor.count());
+        };
         let stride = view.stride().unwrap_or_else(|| accessor.size());
         let slice_offset = view.offset() + accessor.offset();
         let slice_len = stride * accessor.count();
@@ -3,15 +3,16 @@
 //! * Window/HMD event handling separation
 //! * Use secondary command buffers to parallelize their creation
 //! * Mip Mapping
-//! * Instancing
-//! * Animations
-//! * Morph primitives
+//! * Decoding of `EXT_meshopt_compression` and `KHR_draco_mesh_compression`
+//! * KTX2/Basis Universal textures (`KHR_texture_basisu`)
+//! * Float/HDR textures
 
 #![feature(core_intrinsics)]
 
 pub mod buffer;
 pub mod camera;
 pub mod iter;
+pub mod light;
 pub mod model;
 pub mod pipeline;
 pub mod sampler;
@@ -20,10 +21,11 @@ pub mod swapchain;
 pub mod vertex;
 
 use std::borrow::Cow;
+use std::mem;
 use std::cmp::Ordering;
 use std::marker::PhantomData;
-use std::path::Path;
-use std::collections::{VecDeque, HashSet};
+use std::path::{Path, PathBuf};
+use std::collections::{HashSet, HashMap};
 use std::sync::{Arc, RwLock};
 use std::time::{Instant, Duration};
 use std::rc::Rc;
@@ -36,7 +38,7 @@ use vulkano;
 use vulkano::VulkanObject;
 use vulkano::swapchain::ColorSpace;
 use vulkano::instance::RawInstanceExtensions;
-use vulkano::buffer::TypedBufferAccess;
+use vulkano::buffer::BufferUsage;
 use vulkano::buffer::cpu_pool::CpuBufferPool;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
 use vulkano::device::{Device, RawDeviceExtensions, DeviceExtensions, Queue, Features};
@@ -52,22 +54,31 @@ use winit::window::{WindowBuilder, Window};
 use winit::event::{ElementState, MouseButton, Event, DeviceEvent, WindowEvent, KeyboardInput, VirtualKeyCode};
 use winit::dpi::{PhysicalSize, LogicalPosition};
 use smallvec::SmallVec;
+use gltf::mesh::Semantic;
+use failure::Error;
 use openxr::{View as XrView, FrameState as XrFrameState, FrameWaiter as XrFrameWaiter};
 
 use ammolite_math::matrix::*;
 use ammolite_math::vector::*;
 use crate::model::FramebufferWithClearValues;
 use crate::model::Model;
+use crate::model::bounds::Bounds;
+use crate::model::bvh::{MeshInstance, PrimitiveTriangle, build_mesh_instance_bvh};
 use crate::model::DrawContext;
 use crate::model::InstanceDrawContext;
 use crate::model::HelperResources;
+use crate::model::pose::Pose;
+use crate::model::loading::{ModelLoadingHandle, ModelSource};
+use crate::model::error::ModelImportError;
 use crate::model::resource::UninitializedResource;
+use crate::light::{self, Light};
 use crate::camera::*;
 use crate::pipeline::GltfGraphicsPipeline;
 use crate::pipeline::GraphicsPipelineSetCache;
-use crate::pipeline::DescriptorSetMap;
+use crate::vertex::GltfInstanceMatrix;
 use crate::iter::ArrayIterator;
 use crate::swapchain::{Swapchain, VkSwapchain, XrSwapchain};
+use crate::shaders::MAX_LIGHTS;
 
 pub use crate::shaders::gltf_opaque_frag::ty::*;
 pub use openxr::Instance as XrInstance;
@@ -108,6 +119,29 @@ fn into_raw_u32_xr(version: openxr::Version) -> u32 {
 pub struct WorldSpaceModel<'a> {
     pub model: &'a Model,
     pub matrix: Mat4,
+    /// The pose to render the model in, the rest pose of the model is used if `None`
+    pub pose: Option<&'a Pose>,
+    /// The scene of the model to render, the default scene of the model is used if `None`.
+    /// Instances with an invalid scene index are not rendered.
+    pub scene_index: Option<usize>,
+}
+
+impl<'a> WorldSpaceModel<'a> {
+    /// The index of the rendered scene, `None` if it does not exist in the model
+    pub fn scene_index(&self) -> Option<usize> {
+        let scene_index = self.scene_index.unwrap_or_else(|| self.model.default_scene_index());
+
+        if scene_index < self.model.document().scenes().len() {
+            Some(scene_index)
+        } else {
+            None
+        }
+    }
+
+    /// The world space bounds of the rendered scene, see `Model::scene_bounds`
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.model.scene_bounds(self.scene_index()?, &self.matrix, self.pose)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -144,10 +178,94 @@ impl<'a, 'b> std::ops::Mul<&'b Mat4> for &'a HomogeneousRay {
 
 #[derive(Debug, Clone)]
 pub struct RayIntersection {
+    /// The distance along the ray, in multiples of the length of its direction
     pub distance: f32,
     pub node_index: usize,
     pub mesh_index: usize,
     pub primitive_index: usize,
+    /// The index of the triangle within the primitive, in the order of its indices
+    pub triangle_index: usize,
+    /// The intersection point, in world space
+    pub point: Vec3,
+    /// The weights of the vertices of the triangle at the intersection point
+    pub barycentric_coordinates: Vec3,
+    /// The interpolated vertex normal, or the triangle normal if the primitive has no normals,
+    /// normalized, in world space
+    pub normal: Vec3,
+    /// The interpolated `TEXCOORD_0` texture coordinates, if any
+    pub uv: Option<Vec2>,
+    pub material_index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RaytraceOptions {
+    /// Whether to ignore the back faces of triangles with single sided materials
+    pub cull_back_faces: bool,
+    /// The maximum distance along the ray, in multiples of the length of its direction
+    pub max_distance: f32,
+    /// Whether to return the first intersection found instead of the closest one, useful for
+    /// occlusion tests
+    pub any_hit: bool,
+}
+
+impl Default for RaytraceOptions {
+    fn default() -> Self {
+        Self {
+            cull_back_faces: false,
+            max_distance: std::f32::INFINITY,
+            any_hit: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TriangleIntersection {
+    pub distance: f32,
+    pub barycentric_coordinates: Vec3,
+    /// Whether the ray hits the side of the triangle with counter-clockwise winding
+    pub front_face: bool,
+}
+
+/// Intersects the ray with a triangle, using the Möller–Trumbore algorithm.
+/// Rays parallel to the plane of the triangle and degenerate triangles are never hit.
+pub fn intersect_triangle(triangle: &[Vec3; 3], origin: &Vec3, direction: &Vec3) -> Option<TriangleIntersection> {
+    let edge_1 = &triangle[1] - &triangle[0];
+    let edge_2 = &triangle[2] - &triangle[0];
+    let p = direction.cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+
+    // The determinant scales with the lengths of the edges and of the direction, so the
+    // tolerance does too, in order not to miss small triangles or triangles far from the origin
+    if determinant.abs() <= std::f32::EPSILON * edge_1.norm() * edge_2.norm() * direction.norm() {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let t = origin - &triangle[0];
+    let u = t.dot(&p) * inverse_determinant;
+
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+
+    let q = t.cross(&edge_1);
+    let v = direction.dot(&q) * inverse_determinant;
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge_2.dot(&q) * inverse_determinant;
+
+    if distance < 0.0 {
+        return None;
+    }
+
+    Some(TriangleIntersection {
+        distance,
+        barycentric_coordinates: Vec3([1.0 - u - v, u, v]),
+        front_face: determinant > 0.0,
+    })
 }
 
 pub fn intersect_convex_polygon(polygon: &[Vec3], ray: &HomogeneousRay) -> Option<f32> {
@@ -190,47 +308,121 @@ pub fn intersect_convex_polygon(polygon: &[Vec3], ray: &HomogeneousRay) -> Optio
     None
 }
 
+/// Finds the closest intersection of the ray with the visible triangles of the rendered scene of
+/// the model instance, see `raytrace`.
 pub fn raytrace_distance(wsm: &WorldSpaceModel, ray: &Ray) -> Option<RayIntersection> {
-    let instance_matrix_inverse = wsm.matrix.inverse();
-    let ray: HomogeneousRay = ray.clone().into();
-    let model = wsm.model;
-    let scene = model.document().scenes().nth(0 /*TODO*/).unwrap();
-    let mut closest: Option<RayIntersection> = None;
-    let mut node_queue = VecDeque::new();
+    raytrace(wsm, ray, &RaytraceOptions::default())
+}
 
-    for node in scene.nodes() {
-        node_queue.push_back(node.index());
-    }
+/// Intersects the ray with the visible triangles of the rendered scene of the model instance.
+/// Mesh instances of the rest pose are looked up in the BVH cached by the model, see
+/// `Model::scene_mesh_instance_bvh`, those of posed instances in a BVH built for each query.
+/// Their triangles are looked up in the BVHs of primitives, see `Model::primitive_bvh`. Skinning
+/// and morph targets are not taken into account.
+pub fn raytrace(wsm: &WorldSpaceModel, ray: &Ray, options: &RaytraceOptions) -> Option<RayIntersection> {
+    let model = wsm.model;
+    let scene_index = wsm.scene_index()?;
+    // The cached BVH is in the coordinate space of the model, the ray is transformed into it
+    let (mesh_instance_bvh, inverse_instance_matrix) = if wsm.pose.is_some() {
+        (Arc::new(build_mesh_instance_bvh(model, scene_index, &wsm.matrix, wsm.pose)?), Mat4::IDENTITY)
+    } else {
+        // Degenerate instances cannot be hit
+        if wsm.matrix.determinant() == 0.0 {
+            return None;
+        }
 
-    while let Some(node_index) = node_queue.pop_front() {
-        let node = model.document().nodes().nth(node_index).unwrap();
-        let node_transform_matrix = &model.node_transform_matrices()[node.index()];
-        let ray_transform_matrix = node_transform_matrix.inverse();
-        let transformed_ray = &(&ray * &instance_matrix_inverse) * &ray_transform_matrix;
+        (model.scene_mesh_instance_bvh(scene_index), wsm.matrix.inverse())
+    };
+    let homogeneous_ray = &HomogeneousRay::from(ray.clone()) * &inverse_instance_matrix;
+    let bvh_origin = homogeneous_ray.origin.into_projected();
+    let bvh_direction = Vec3([homogeneous_ray.direction[0], homogeneous_ray.direction[1], homogeneous_ray.direction[2]]);
+    let mirrored_instance = inverse_instance_matrix.determinant() < 0.0;
+
+    let (distance, (mesh_instance, primitive_index, triangle, intersection)) = mesh_instance_bvh.intersect_ray(
+        &bvh_origin,
+        &bvh_direction,
+        options.max_distance,
+        options.any_hit,
+        |mesh_instance, max_distance| {
+            // The distances along transformed rays are preserved by affine transformations
+            let transformed_ray = &homogeneous_ray * &mesh_instance.inverse_matrix;
+            let transformed_origin = transformed_ray.origin.into_projected();
+            let transformed_direction = Vec3([transformed_ray.direction[0], transformed_ray.direction[1], transformed_ray.direction[2]]);
+            // Mirroring transformations swap the front and back faces
+            let mirrored = (mesh_instance.inverse_matrix.determinant() < 0.0) != mirrored_instance;
+            let mesh = model.document().meshes().nth(mesh_instance.mesh_index).unwrap();
+            let mut closest: Option<(f32, (MeshInstance, usize, PrimitiveTriangle, TriangleIntersection))> = None;
 
-        if let Some(mesh) = node.mesh() {
             for primitive in mesh.primitives() {
-                for face in model.primitive_faces_iter(&primitive) {
-                    if let Some(distance) = intersect_convex_polygon(&face[..], &transformed_ray) {
-                        if closest.is_none() || distance < closest.as_ref().unwrap().distance {
-                            closest = Some(RayIntersection {
-                                distance,
-                                node_index: node.index(),
-                                mesh_index: mesh.index(),
-                                primitive_index: primitive.index(),
-                            });
+                if model.primitive_bounds(mesh.index(), primitive.index()).is_none() {
+                    continue;
+                }
+
+                let cull_back_faces = options.cull_back_faces && !primitive.material().double_sided();
+                let max_distance = closest.as_ref().map(|(distance, _)| *distance).unwrap_or(max_distance);
+                let primitive_bvh = model.primitive_bvh(mesh.index(), primitive.index());
+                let primitive_intersection = primitive_bvh.intersect_ray(
+                    &transformed_origin,
+                    &transformed_direction,
+                    max_distance,
+                    options.any_hit,
+                    |triangle, _| {
+                        let intersection = intersect_triangle(&triangle.positions, &transformed_origin, &transformed_direction)?;
+
+                        if cull_back_faces && intersection.front_face == mirrored {
+                            return None;
                         }
+
+                        Some((intersection.distance, (triangle.clone(), intersection)))
+                    },
+                );
+
+                if let Some((distance, (triangle, intersection))) = primitive_intersection {
+                    closest = Some((distance, (mesh_instance.clone(), primitive.index(), triangle, intersection)));
+
+                    if options.any_hit {
+                        break;
                     }
                 }
             }
-        }
 
-        for child in node.children() {
-            node_queue.push_back(child.index());
-        }
+            closest
+        },
+    )?;
+
+    // The attributes are only computed for the resulting intersection
+    let mesh = model.document().meshes().nth(mesh_instance.mesh_index).unwrap();
+    let primitive = mesh.primitives().nth(primitive_index).unwrap();
+    let barycentric_coordinates = intersection.barycentric_coordinates;
+    let mut normal = Vec3::ZERO;
+
+    if !model.interpolate_vertex_attribute(&primitive, &Semantic::Normals, &triangle.vertex_indices, &barycentric_coordinates, &mut normal.0[..]) {
+        normal = (&triangle.positions[1] - &triangle.positions[0])
+            .cross(&(&triangle.positions[2] - &triangle.positions[0]));
     }
 
-    closest
+    // Normals are transformed by the inverse transpose of the transformation matrix
+    let inverse_matrix = &mesh_instance.inverse_matrix * &inverse_instance_matrix;
+    let normal = (&inverse_matrix.transpose() * &normal.into_homogeneous_direction()).into_projected().normalize();
+    let mut uv = Vec2::ZERO;
+    let uv = if model.interpolate_vertex_attribute(&primitive, &Semantic::TexCoords(0), &triangle.vertex_indices, &barycentric_coordinates, &mut uv.0[..]) {
+        Some(uv)
+    } else {
+        None
+    };
+
+    Some(RayIntersection {
+        distance,
+        node_index: mesh_instance.node_index,
+        mesh_index: mesh_instance.mesh_index,
+        primitive_index,
+        triangle_index: triangle.index,
+        point: &ray.origin + &ray.direction * distance,
+        barycentric_coordinates,
+        normal,
+        uv,
+        material_index: primitive.material().index(),
+    })
 }
 
 pub struct ViewSwapchain {
@@ -714,6 +906,7 @@ impl<'a, MD: MediumData> Medium<MD> for XrMedium<MD> {
 }
 
 
+#[derive(Clone)]
 pub struct ChosenQueues {
     pub graphics: Arc<Queue>,
     pub transfer: Arc<Queue>,
@@ -774,6 +967,7 @@ pub struct AmmoliteBuilder<'a, MD: MediumData, A: OpenXrInitializedTrait, B: Vul
     vk_queues: Option<ChosenQueues>,
     uninitialized_window_mediums: Option<ArrayVec<[UninitializedWindowMedium<MD>; 1]>>,
     window_mediums: ArrayVec<[WindowMedium<MD>; 1]>,
+    max_anisotropy: f32,
     _marker: PhantomData<(A, B, C, D)>,
 }
 
@@ -791,6 +985,7 @@ impl<'a, MD: MediumData> AmmoliteBuilder<'a, MD, OpenXrInitialized::False, Vulka
             vk_queues: None,
             uninitialized_window_mediums: None,
             window_mediums: ArrayVec::new(),
+            max_anisotropy: 1.0,
             _marker: PhantomData,
         }
     }
@@ -816,9 +1011,19 @@ impl<'a, MD: MediumData, A: OpenXrInitializedTrait, B: VulkanInitializedTrait, C
             vk_queues: self.vk_queues,
             uninitialized_window_mediums: self.uninitialized_window_mediums,
             window_mediums: self.window_mediums,
+            max_anisotropy: self.max_anisotropy,
             _marker: PhantomData,
         }
     }
+
+    /**
+     * Sets the maximum anisotropy of texture samplers, clamped to what the device supports.
+     * Defaults to 1.0, which disables anisotropic filtering.
+     */
+    pub fn with_max_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
 }
 
 impl<'a, MD: MediumData, B: VulkanInitializedTrait> AmmoliteBuilder<'a, MD, OpenXrInitialized::False, B, WindowsAdded::False, HmdsAdded::False> {
@@ -1136,6 +1341,7 @@ impl<'a, MD: MediumData, A: OpenXrInitializedTrait> AmmoliteBuilder<'a, MD, A, V
             Device::new(physical_device,
                         &Features {
                             independent_blend: true,
+                            sampler_anisotropy: physical_device.supported_features().sampler_anisotropy,
                             .. Features::none()
                         },
                         device_extensions,
@@ -1319,6 +1525,7 @@ impl<'a, MD: MediumData> AmmoliteBuilder<'a, MD, OpenXrInitialized::True, Vulkan
             vk_device,
             vk_queues,
             window_mediums,
+            max_anisotropy,
             ..
         } = self;
         let XrContext {
@@ -1344,6 +1551,7 @@ impl<'a, MD: MediumData> AmmoliteBuilder<'a, MD, OpenXrInitialized::True, Vulkan
         let (init_command_buffer_builder, helper_resources) = HelperResources::new(
             &vk_device,
             vk_queues.families(),
+            max_anisotropy,
         ).unwrap()
             .initialize_resource(&vk_device, vk_queues.graphics.family(), init_command_buffer_builder).unwrap();
         let pipeline_cache = {
@@ -1380,7 +1588,18 @@ impl<'a, MD: MediumData> AmmoliteBuilder<'a, MD, OpenXrInitialized::True, Vulkan
             window_mediums,
             // view_swapchains,
             synchronization: Some(synchronization),
-            buffer_pool_uniform_instance: CpuBufferPool::uniform_buffer(vk_device),
+            buffer_pool_vertex_instance_matrices: CpuBufferPool::vertex_buffer(vk_device.clone()),
+            buffer_pool_uniform_node: CpuBufferPool::uniform_buffer(vk_device.clone()),
+            buffer_pool_storage_joint_matrices: CpuBufferPool::new(vk_device.clone(), BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::none()
+            }),
+            buffer_pool_storage_morph_weights: CpuBufferPool::new(vk_device, BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::none()
+            }),
+            lights: Vec::new(),
+            dropped_light_count: 0,
         }
     }
 }
@@ -1416,7 +1635,20 @@ pub struct Ammolite<MD: MediumData> {
     // pub view_swapchains: Arc<ViewSwapchains>,
     pub synchronization: Option<Box<dyn GpuFuture>>,
     // TODO Consider moving to SharedGltfGraphicsPipelineResources
-    pub buffer_pool_uniform_instance: CpuBufferPool<InstanceUBO>,
+    /// Instance matrices of groups of instances, recreated every frame
+    pub buffer_pool_vertex_instance_matrices: CpuBufferPool<GltfInstanceMatrix>,
+    /// Node UBOs of posed instances, recreated every frame
+    pub buffer_pool_uniform_node: CpuBufferPool<NodeUBO>,
+    /// Joint matrices of posed instances, recreated every frame
+    pub buffer_pool_storage_joint_matrices: CpuBufferPool<[[f32; 4]; 4]>,
+    /// Morph target weights of posed instances, recreated every frame
+    pub buffer_pool_storage_morph_weights: CpuBufferPool<f32>,
+    /// Lights added by the application, in world space. Lights of the rendered models are added
+    /// to these every frame. If there are no lights at all, `light::default_lights` are used.
+    /// At most `MAX_LIGHTS` lights are rendered, the rest are ignored, see
+    /// `Ammolite::dropped_light_count`.
+    pub lights: Vec<Light>,
+    dropped_light_count: usize,
 }
 
 impl<MD: MediumData> Ammolite<MD> {
@@ -1495,56 +1727,91 @@ impl<MD: MediumData> Ammolite<MD> {
             .map(Medium::views)
     }
 
-    pub fn load_model_path(&mut self, path: impl AsRef<Path>) -> Model {
-        let init_command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.vk_queues.graphics.family()).unwrap();
-        let (init_command_buffer_builder, model) = {
-            Model::import_path(
-                &self.device,
-                self.vk_queues.families(),
-                &self.pipeline_cache,
-                &self.helper_resources,
-                path,
-            ).unwrap().initialize_resource(
-                &self.device,
-                self.vk_queues.graphics.family().clone(),
-                init_command_buffer_builder
-            ).unwrap()
-        };
-        let init_command_buffer = init_command_buffer_builder.build().unwrap();
+    pub fn load_model_path(&mut self, path: impl AsRef<Path>) -> Result<Model, ModelImportError> {
+        let uninitialized_model = Model::import_path(
+            &self.device,
+            self.vk_queues.families(),
+            &self.pipeline_cache,
+            &self.helper_resources,
+            path,
+        )?;
 
-        self.synchronization = Some(Box::new(self.synchronization.take().unwrap()
-            .then_execute(self.vk_queues.graphics.clone(), init_command_buffer).unwrap()
-            // .then_signal_fence()
-            // .then_execute_same_queue(init_unsafe_command_buffer).unwrap()
-            .then_signal_fence_and_flush().unwrap()));
-
-        model
-    }
-
-    pub fn load_model_slice(&mut self, slice: impl AsRef<[u8]>) -> Model {
-        let init_command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.vk_queues.graphics.family()).unwrap();
-        let (init_command_buffer_builder, model) = {
-            Model::import_slice(
-                &self.device,
-                self.vk_queues.families(),
-                &self.pipeline_cache,
-                &self.helper_resources,
-                slice,
-            ).unwrap().initialize_resource(
-                &self.device,
-                self.vk_queues.graphics.family().clone(),
-                init_command_buffer_builder
-            ).unwrap()
-        };
-        let init_command_buffer = init_command_buffer_builder.build().unwrap();
+        Ok(self.initialize_model(uninitialized_model)?)
+    }
+
+    pub fn load_model_slice(&mut self, slice: impl AsRef<[u8]>) -> Result<Model, ModelImportError> {
+        let uninitialized_model = Model::import_slice(
+            &self.device,
+            self.vk_queues.families(),
+            &self.pipeline_cache,
+            &self.helper_resources,
+            slice,
+        )?;
+
+        Ok(self.initialize_model(uninitialized_model)?)
+    }
+
+    fn initialize_model(&mut self, uninitialized_model: impl UninitializedResource<Model>) -> Result<Model, Error> {
+        let init_command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.vk_queues.graphics.family())?;
+        let (init_command_buffer_builder, model) = uninitialized_model.initialize_resource(
+            &self.device,
+            self.vk_queues.graphics.family().clone(),
+            init_command_buffer_builder
+        )?;
+        let init_command_buffer = init_command_buffer_builder.build()?;
 
         self.synchronization = Some(Box::new(self.synchronization.take().unwrap()
-            .then_execute(self.vk_queues.graphics.clone(), init_command_buffer).unwrap()
+            .then_execute(self.vk_queues.graphics.clone(), init_command_buffer)?
             // .then_signal_fence()
             // .then_execute_same_queue(init_unsafe_command_buffer).unwrap()
-            .then_signal_fence_and_flush().unwrap()));
+            .then_signal_fence_and_flush()?));
+
+        Ok(model)
+    }
+
+    /// Loads a model on a background thread. The returned handle reports the loading progress
+    /// and provides the model once it is ready to be rendered.
+    pub fn load_model_path_async(&self, path: impl Into<PathBuf>) -> ModelLoadingHandle {
+        ModelLoadingHandle::spawn(
+            self.device.clone(),
+            self.vk_queues.clone(),
+            self.pipeline_cache.clone(),
+            self.helper_resources.clone(),
+            ModelSource::Path(path.into()),
+        )
+    }
+
+    /// Loads a model on a background thread, see `load_model_path_async`.
+    pub fn load_model_slice_async(&self, slice: impl Into<Vec<u8>>) -> ModelLoadingHandle {
+        ModelLoadingHandle::spawn(
+            self.device.clone(),
+            self.vk_queues.clone(),
+            self.pipeline_cache.clone(),
+            self.helper_resources.clone(),
+            ModelSource::Slice(slice.into()),
+        )
+    }
+
+    /// Releases the device resources of a model. Waits for all submitted frames to finish first,
+    /// as they may still reference the model. Pipelines no longer used by any model are evicted
+    /// from the pipeline cache.
+    pub fn unload_model(&mut self, model: Model) -> Result<(), FlushError> {
+        let result = self.synchronization.take().unwrap()
+            .then_signal_fence_and_flush()
+            .and_then(|future| future.wait(None));
+
+        self.synchronization = Some(Box::new(vulkano::sync::now(self.device.clone())));
+
+        mem::drop(model);
+        self.pipeline_cache.evict_unused();
+
+        result
+    }
 
-        model
+    /// The number of lights ignored by the last call to `render`, as at most `MAX_LIGHTS` lights
+    /// are rendered.
+    pub fn dropped_light_count(&self) -> usize {
+        self.dropped_light_count
     }
 
     pub fn render<'a>(&mut self, elapsed: &Duration, model_provider: impl FnOnce() -> &'a [WorldSpaceModel<'a>]) {
@@ -1555,6 +1822,22 @@ impl<MD: MediumData> Ammolite<MD> {
         self.synchronization.as_mut().unwrap().cleanup_finished();
 
         let world_space_models = model_provider();
+        let lights_ubo = {
+            let mut lights: Vec<LightData> = self.lights.iter().cloned()
+                .chain(world_space_models.iter().flat_map(|world_space_model| {
+                    world_space_model.model.lights(&world_space_model.matrix, world_space_model.pose)
+                }))
+                .map(|light| light.to_light_data())
+                .collect();
+
+            if lights.is_empty() {
+                lights.extend(light::default_lights().iter().map(Light::to_light_data));
+            }
+
+            self.dropped_light_count = lights.len().saturating_sub(MAX_LIGHTS);
+
+            LightsUBO::new(&lights[..])
+        };
         let view_swapchains_len = Self::view_swapchains(&self.xr.stereo_hmd_mediums,
                                                        &self.window_mediums).count();
 
@@ -1672,6 +1955,14 @@ impl<MD: MediumData> Ammolite<MD> {
                                 self.pipeline_cache.shared_resources.scene_ubo_buffer.staging_buffer().clone(),
                                 self.pipeline_cache.shared_resources.scene_ubo_buffer.device_buffer().clone()
                             ).unwrap()
+                            .update_buffer(
+                                self.pipeline_cache.shared_resources.lights_ubo_buffer.staging_buffer().clone(),
+                                lights_ubo.clone()
+                            ).unwrap()
+                            .copy_buffer(
+                                self.pipeline_cache.shared_resources.lights_ubo_buffer.staging_buffer().clone(),
+                                self.pipeline_cache.shared_resources.lights_ubo_buffer.device_buffer().clone()
+                            ).unwrap()
                             .build().unwrap();
 
 
@@ -1699,7 +1990,10 @@ impl<MD: MediumData> Ammolite<MD> {
                             view_swapchain_index,
                             view_swapchain: &view_swapchain,
                             vk_queues: &self.vk_queues,
-                            buffer_pool_uniform_instance: &self.buffer_pool_uniform_instance,
+                            buffer_pool_vertex_instance_matrices: &self.buffer_pool_vertex_instance_matrices,
+                            buffer_pool_uniform_node: &self.buffer_pool_uniform_node,
+                            buffer_pool_storage_joint_matrices: &self.buffer_pool_storage_joint_matrices,
+                            buffer_pool_storage_morph_weights: &self.buffer_pool_storage_morph_weights,
                         };
 
                         self.synchronization = Some(Self::render_instances(
@@ -1792,29 +2086,44 @@ impl<MD: MediumData> Ammolite<MD> {
         //     view_swapchain,
         // };
 
-        let instances = world_space_models.iter()
-            .map(|WorldSpaceModel { model, matrix }| {
-                let instance_ubo = InstanceUBO::new(matrix.clone());
-                let instance_buffer: Arc<dyn TypedBufferAccess<Content=InstanceUBO> + Send + Sync>
-                    = Arc::new(draw_context.buffer_pool_uniform_instance.next(instance_ubo).unwrap());
-                let used_layouts = model.get_used_pipelines_layouts(&draw_context.pipeline_cache);
-                let descriptor_set_map = DescriptorSetMap::new(
-                    &used_layouts[..],
-                    |layout_dependent_resources| {
-                        layout_dependent_resources.descriptor_set_pool_instance.clone()
-                    },
-                    {
-                        let instance_buffer_ref = &instance_buffer;
-                        move |set_builder| {
-                            Arc::new(set_builder.add_buffer(instance_buffer_ref.clone()).unwrap()
-                                     .build().unwrap())
-                        }
-                    },
-                );
+        // Instances of the same model and scene in the rest pose are drawn with a single instanced
+        // draw call per primitive. Posed instances require their own node descriptor sets.
+        let mut instance_groups: Vec<(&Model, usize, Option<&Pose>, Vec<Mat4>)> = Vec::new();
+        let mut rest_pose_group_indices: HashMap<(*const Model, usize), usize> = HashMap::new();
+
+        for world_space_model in world_space_models {
+            let &WorldSpaceModel { model, ref matrix, pose, .. } = world_space_model;
+            let scene_index = if let Some(scene_index) = world_space_model.scene_index() {
+                scene_index
+            } else {
+                continue;
+            };
+
+            if pose.is_none() {
+                if let Some(&group_index) = rest_pose_group_indices.get(&(model as *const Model, scene_index)) {
+                    instance_groups[group_index].3.push(matrix.clone());
+                    continue;
+                }
+
+                rest_pose_group_indices.insert((model as *const Model, scene_index), instance_groups.len());
+            }
+
+            instance_groups.push((model, scene_index, pose, vec![matrix.clone()]));
+        }
+
+        let instances = instance_groups.into_iter()
+            .map(|(model, scene_index, pose, instance_matrices)| {
+                let instance_buffers = model.create_instance_buffers(&draw_context, &instance_matrices[..], pose);
+                let descriptor_set_maps_node = pose.map(|pose| {
+                    model.create_pose_descriptor_set_maps(&draw_context, pose)
+                });
 
                 (
                     model,
-                    descriptor_set_map,
+                    instance_buffers,
+                    descriptor_set_maps_node,
+                    scene_index,
+                    pose,
                 )
             })
             .collect::<Vec<_>>();
@@ -1835,10 +2144,12 @@ impl<MD: MediumData> Ammolite<MD> {
             }
 
             for (_index, world_space_model) in instances.iter().enumerate() {
-                let &(ref model, ref descriptor_set_map_instance) = world_space_model;
+                let &(ref model, ref instance_buffers, ref descriptor_set_maps_node, scene_index, pose) = world_space_model;
                 let instance_context = InstanceDrawContext {
                     draw_context: &draw_context,
-                    descriptor_set_map_instance: &descriptor_set_map_instance,
+                    instance_buffers,
+                    descriptor_set_maps_node: descriptor_set_maps_node.as_ref().map(|maps| &maps[..]),
+                    node_visibility: pose.map(Pose::world_visibility),
                 };
 
                 command_buffer = model.draw_scene(
@@ -1846,7 +2157,7 @@ impl<MD: MediumData> Ammolite<MD> {
                     instance_context,
                     alpha_mode,
                     subpass_index as u8,
-                    0, // TODO
+                    scene_index,
                 ).unwrap();
             }
         }
@@ -1858,3 +2169,86 @@ impl<MD: MediumData> Ammolite<MD> {
                  .then_execute_same_queue(command_buffer.build().unwrap()).unwrap())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [Vec3; 3] = [
+        Vec3([0.0, 0.0, 0.0]),
+        Vec3([1.0, 0.0, 0.0]),
+        Vec3([0.0, 1.0, 0.0]),
+    ];
+
+    fn scale_triangle(triangle: &[Vec3; 3], scale: f32) -> [Vec3; 3] {
+        [&triangle[0] * scale, &triangle[1] * scale, &triangle[2] * scale]
+    }
+
+    #[test]
+    fn intersect_triangle_hit() {
+        let intersection = intersect_triangle(&TRIANGLE, &Vec3([0.25, 0.5, 2.0]), &Vec3([0.0, 0.0, -1.0])).unwrap();
+
+        assert_eq!(intersection.distance, 2.0);
+        assert_eq!(intersection.barycentric_coordinates, Vec3([0.25, 0.25, 0.5]));
+
+        // The distance is in multiples of the length of the direction
+        let intersection = intersect_triangle(&TRIANGLE, &Vec3([0.25, 0.5, 2.0]), &Vec3([0.0, 0.0, -4.0])).unwrap();
+
+        assert_eq!(intersection.distance, 0.5);
+    }
+
+    #[test]
+    fn intersect_triangle_miss() {
+        assert!(intersect_triangle(&TRIANGLE, &Vec3([0.75, 0.75, 2.0]), &Vec3([0.0, 0.0, -1.0])).is_none());
+        assert!(intersect_triangle(&TRIANGLE, &Vec3([-0.25, 0.5, 2.0]), &Vec3([0.0, 0.0, -1.0])).is_none());
+        // Behind the origin
+        assert!(intersect_triangle(&TRIANGLE, &Vec3([0.25, 0.5, 2.0]), &Vec3([0.0, 0.0, 1.0])).is_none());
+    }
+
+    #[test]
+    fn intersect_triangle_parallel() {
+        assert!(intersect_triangle(&TRIANGLE, &Vec3([-1.0, 0.25, 0.0]), &Vec3([1.0, 0.0, 0.0])).is_none());
+        assert!(intersect_triangle(&TRIANGLE, &Vec3([-1.0, 0.25, 1.0]), &Vec3([1.0, 0.0, 0.0])).is_none());
+    }
+
+    #[test]
+    fn intersect_triangle_degenerate() {
+        let triangle = [Vec3([0.0, 0.0, 0.0]), Vec3([1.0, 0.0, 0.0]), Vec3([2.0, 0.0, 0.0])];
+
+        assert!(intersect_triangle(&triangle, &Vec3([0.5, 0.0, 1.0]), &Vec3([0.0, 0.0, -1.0])).is_none());
+    }
+
+    #[test]
+    fn intersect_triangle_scale_independent() {
+        for &scale in &[1e-4, 1e-2, 1e2, 1e4] {
+            let triangle = scale_triangle(&TRIANGLE, scale);
+            let origin = &Vec3([0.25, 0.5, 2.0]) * scale;
+
+            for &direction_scale in &[1e-4, 1.0, 1e4] {
+                let direction = Vec3([0.0, 0.0, -direction_scale]);
+                let intersection = intersect_triangle(&triangle, &origin, &direction)
+                    .expect("The triangle should be hit regardless of its scale.");
+
+                assert!((intersection.distance * direction_scale - 2.0 * scale).abs() <= 1e-5 * scale);
+            }
+        }
+    }
+
+    #[test]
+    fn intersect_triangle_front_face() {
+        let origin = Vec3([0.25, 0.25, 1.0]);
+        let direction = Vec3([0.0, 0.0, -1.0]);
+        let reversed = [TRIANGLE[0], TRIANGLE[2], TRIANGLE[1]];
+
+        // Counter-clockwise when viewed from the origin
+        assert!(intersect_triangle(&TRIANGLE, &origin, &direction).unwrap().front_face);
+        assert!(!intersect_triangle(&reversed, &origin, &direction).unwrap().front_face);
+
+        // The back faces are hit from the other side
+        let origin = Vec3([0.25, 0.25, -1.0]);
+        let direction = Vec3([0.0, 0.0, 1.0]);
+
+        assert!(!intersect_triangle(&TRIANGLE, &origin, &direction).unwrap().front_face);
+        assert!(intersect_triangle(&reversed, &origin, &direction).unwrap().front_face);
+    }
+}
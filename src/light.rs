@@ -0,0 +1,144 @@
+use gltf::khr_lights_punctual::{self, Kind};
+use ammolite_math::*;
+use crate::shaders::LightData;
+
+pub const LIGHT_KIND_DIRECTIONAL: u32 = 0;
+pub const LIGHT_KIND_POINT: u32 = 1;
+pub const LIGHT_KIND_SPOT: u32 = 2;
+
+/// The positions the default lights shine from, towards the origin
+const DEFAULT_LIGHT_POSITIONS: [[f32; 3]; 3] = [
+    [1.0, 1.5, 2.0],
+    [-1.0, -1.5, 2.0],
+    [-1.0, 1.5, -2.0],
+];
+
+/// The lights used for frames without any lights, supplied neither by the application nor by
+/// the rendered models, so that models without lights are not rendered black.
+/// Three white directional lights of unit intensity, shining towards the origin.
+pub fn default_lights() -> Vec<Light> {
+    DEFAULT_LIGHT_POSITIONS.iter()
+        .map(|position| Light::directional(-Vec3(*position), [1.0, 1.0, 1.0].into(), 1.0))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        /// The angle from the center of the cone at which the falloff begins, in radians
+        inner_cone_angle: f32,
+        /// The angle from the center of the cone at which the falloff ends, in radians
+        outer_cone_angle: f32,
+    },
+}
+
+/// A punctual light source in world space, as specified by `KHR_lights_punctual`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB color
+    pub color: Vec3,
+    /// Luminous intensity in candela for point and spot lights, illuminance in lux for
+    /// directional lights
+    pub intensity: f32,
+    /// The distance at which the light reaches zero intensity, `None` for an infinite range
+    pub range: Option<f32>,
+    /// Ignored by directional lights
+    pub position: Vec3,
+    /// The direction the light is pointing in, ignored by point lights
+    pub direction: Vec3,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            range: None,
+            position: Vec3::ZERO,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn point(position: Vec3, color: Vec3, intensity: f32, range: Option<f32>) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            position,
+            direction: [0.0, 0.0, -1.0].into(),
+        }
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: Option<f32>,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color,
+            intensity,
+            range,
+            position,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// Places a light of a glTF document in the world, using the world matrix of the node it is
+    /// attached to. Lights point in the direction of the negative Z axis of their node.
+    pub fn from_gltf(light: &khr_lights_punctual::Light, world_matrix: &Mat4) -> Self {
+        let kind = match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot { inner_cone_angle, outer_cone_angle } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        };
+        let position = (world_matrix * &Vec4([0.0, 0.0, 0.0, 1.0])).into_projected();
+        let direction = (world_matrix * &Vec4([0.0, 0.0, -1.0, 0.0])).into_projected().normalize();
+
+        Self {
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+            position,
+            direction,
+        }
+    }
+
+    pub fn to_light_data(&self) -> LightData {
+        let (kind, inner_cone_cos, outer_cone_cos) = match self.kind {
+            LightKind::Directional => (LIGHT_KIND_DIRECTIONAL, 1.0, 0.0),
+            LightKind::Point => (LIGHT_KIND_POINT, 1.0, 0.0),
+            LightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                (LIGHT_KIND_SPOT, inner_cone_angle.cos(), outer_cone_angle.cos())
+            },
+        };
+
+        LightData::new(
+            kind,
+            self.position,
+            self.direction,
+            // A range of 0 is interpreted as an infinite range by the shaders
+            self.range.unwrap_or(0.0),
+            self.color,
+            self.intensity,
+            inner_cone_cos,
+            outer_cone_cos,
+        )
+    }
+}
@@ -0,0 +1,504 @@
+use std::cmp::Ordering;
+use std::time::Duration;
+use gltf::{self, Document};
+use gltf::animation::{Interpolation, Property};
+use ammolite_math::*;
+use crate::model::{Model, AccessorDetails};
+use crate::model::pose::Pose;
+
+/// A single animated property of a node, sampled on the CPU.
+#[derive(Clone, Debug)]
+pub struct AnimationChannel {
+    node_index: usize,
+    property: Property,
+    interpolation: Interpolation,
+    /// Keyframe times, in seconds
+    inputs: Vec<f32>,
+    /// Flattened keyframe values, each consisting of `components` components.
+    /// Keyframes of cubic spline channels consist of an in-tangent, a value and an out-tangent.
+    outputs: Vec<f32>,
+    components: usize,
+}
+
+impl AnimationChannel {
+    pub fn node_index(&self) -> usize {
+        self.node_index
+    }
+
+    pub fn property(&self) -> Property {
+        self.property
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.inputs.last().cloned().unwrap_or(0.0)
+    }
+
+    fn keyframe_stride(&self) -> usize {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.components * 3,
+            _ => self.components,
+        }
+    }
+
+    fn keyframe_slice(&self, keyframe_index: usize, element_index: usize) -> &[f32] {
+        let offset = keyframe_index * self.keyframe_stride() + element_index * self.components;
+
+        &self.outputs[offset..(offset + self.components)]
+    }
+
+    fn value(&self, keyframe_index: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.keyframe_slice(keyframe_index, 1),
+            _ => self.keyframe_slice(keyframe_index, 0),
+        }
+    }
+
+    /// Samples the channel at the given time, writing `components` values into `output`.
+    /// A time of NaN samples the first keyframe.
+    pub fn sample(&self, time: f32, output: &mut [f32]) {
+        let output = &mut output[..self.components];
+        let last_keyframe_index = self.inputs.len() - 1;
+
+        if time.is_nan() || time <= self.inputs[0] {
+            output.copy_from_slice(self.value(0));
+            return;
+        }
+
+        if time >= self.inputs[last_keyframe_index] {
+            output.copy_from_slice(self.value(last_keyframe_index));
+            return;
+        }
+
+        // Keyframe times of NaN are not ordered, the search may only end up at a wrong keyframe
+        let next_index = match self.inputs.binary_search_by(|input| input.partial_cmp(&time).unwrap_or(Ordering::Less)) {
+            Ok(index) => {
+                output.copy_from_slice(self.value(index));
+                return;
+            },
+            Err(index) => index.max(1).min(last_keyframe_index),
+        };
+        let previous_index = next_index - 1;
+        let previous_time = self.inputs[previous_index];
+        let keyframe_delta = self.inputs[next_index] - previous_time;
+        let t = (time - previous_time) / keyframe_delta;
+
+        match self.interpolation {
+            Interpolation::Step => {
+                output.copy_from_slice(self.value(previous_index));
+            },
+            Interpolation::Linear => {
+                let previous = self.value(previous_index);
+                let next = self.value(next_index);
+
+                if self.property == Property::Rotation {
+                    let result = slerp(&vec4_from_slice(previous), &vec4_from_slice(next), t);
+
+                    output.copy_from_slice(&result.0[..]);
+                } else {
+                    for (index, component) in output.iter_mut().enumerate() {
+                        *component = previous[index] * (1.0 - t) + next[index] * t;
+                    }
+                }
+            },
+            Interpolation::CubicSpline => {
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let previous = self.value(previous_index);
+                let previous_out_tangent = self.keyframe_slice(previous_index, 2);
+                let next = self.value(next_index);
+                let next_in_tangent = self.keyframe_slice(next_index, 0);
+
+                for (index, component) in output.iter_mut().enumerate() {
+                    *component = (2.0 * t3 - 3.0 * t2 + 1.0) * previous[index]
+                        + (t3 - 2.0 * t2 + t) * keyframe_delta * previous_out_tangent[index]
+                        + (-2.0 * t3 + 3.0 * t2) * next[index]
+                        + (t3 - t2) * keyframe_delta * next_in_tangent[index];
+                }
+
+                if self.property == Property::Rotation {
+                    let result = vec4_from_slice(output).normalize();
+
+                    output.copy_from_slice(&result.0[..]);
+                }
+            },
+        }
+    }
+}
+
+fn vec3_from_slice(slice: &[f32]) -> Vec3 {
+    Vec3([slice[0], slice[1], slice[2]])
+}
+
+fn vec4_from_slice(slice: &[f32]) -> Vec4 {
+    Vec4([slice[0], slice[1], slice[2], slice[3]])
+}
+
+/// Spherical linear interpolation of unit quaternions
+fn slerp(from: &Vec4, to: &Vec4, t: f32) -> Vec4 {
+    let mut to = *to;
+    let mut cos_angle = from.dot(&to);
+
+    // Take the shorter path
+    if cos_angle < 0.0 {
+        to = -to;
+        cos_angle = -cos_angle;
+    }
+
+    // Fall back to linear interpolation for nearly identical rotations
+    if cos_angle > 0.9995 {
+        return (from * (1.0 - t) + to * t).normalize();
+    }
+
+    let angle = cos_angle.acos();
+    let sin_angle = angle.sin();
+    let from_coefficient = ((1.0 - t) * angle).sin() / sin_angle;
+    let to_coefficient = (t * angle).sin() / sin_angle;
+
+    from * from_coefficient + to * to_coefficient
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    name: Option<String>,
+    channels: Vec<AnimationChannel>,
+    duration: f32,
+}
+
+impl Animation {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    pub fn channels(&self) -> &[AnimationChannel] {
+        &self.channels[..]
+    }
+
+    /// The time of the last keyframe of all channels, in seconds
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Samples all channels at the given time and writes the results into the pose.
+    pub fn apply(&self, time: f32, pose: &mut Pose) {
+        let mut sample = Vec::new();
+
+        for channel in &self.channels {
+            sample.resize(channel.components, 0.0);
+            channel.sample(time, &mut sample[..]);
+
+            match channel.property {
+                Property::Translation => {
+                    pose.local_transform_mut(channel.node_index).translation = vec3_from_slice(&sample[..]);
+                },
+                Property::Rotation => {
+                    pose.local_transform_mut(channel.node_index).rotation = vec4_from_slice(&sample[..]);
+                },
+                Property::Scale => {
+                    pose.local_transform_mut(channel.node_index).scale = vec3_from_slice(&sample[..]);
+                },
+                Property::MorphTargetWeights => {
+                    let weights = pose.morph_weights_mut(channel.node_index);
+                    let len = weights.len().min(sample.len());
+
+                    weights[..len].copy_from_slice(&sample[..len]);
+                },
+            }
+        }
+    }
+}
+
+/// Decodes all animations of the document, so that they can be sampled without accessing the
+/// glTF buffers.
+pub fn import_animations(document: &Document, buffer_data_array: &[gltf::buffer::Data]) -> Vec<Animation> {
+    document.animations().map(|animation| {
+        let channels: Vec<AnimationChannel> = animation.channels().map(|channel| {
+            let sampler = channel.sampler();
+            let interpolation = sampler.interpolation();
+            let property = channel.target().property();
+            let input_accessor_details = AccessorDetails::from(buffer_data_array, sampler.input());
+            let output_accessor_details = AccessorDetails::from(buffer_data_array, sampler.output());
+            let inputs: Vec<f32> = (0..input_accessor_details.accessor.count())
+                .map(|item_index| input_accessor_details.read_component_f32(item_index, 0))
+                .collect();
+            let output_dimensions = output_accessor_details.accessor.dimensions().multiplicity();
+            let outputs: Vec<f32> = (0..output_accessor_details.accessor.count())
+                .flat_map(|item_index| {
+                    let output_accessor_details = &output_accessor_details;
+
+                    (0..output_dimensions).map(move |component_index| {
+                        output_accessor_details.read_component_f32(item_index, component_index)
+                    })
+                })
+                .collect();
+            let components = match property {
+                Property::Translation | Property::Scale => 3,
+                Property::Rotation => 4,
+                // Each keyframe holds one weight per morph target
+                Property::MorphTargetWeights => {
+                    let values_per_keyframe = outputs.len() / inputs.len().max(1);
+
+                    match interpolation {
+                        Interpolation::CubicSpline => values_per_keyframe / 3,
+                        _ => values_per_keyframe,
+                    }
+                },
+            };
+
+            AnimationChannel {
+                node_index: channel.target().node().index(),
+                property,
+                interpolation,
+                inputs,
+                outputs,
+                components,
+            }
+        }).filter(|channel| !channel.inputs.is_empty() && channel.components > 0).collect();
+        let duration = channels.iter()
+            .map(AnimationChannel::duration)
+            .fold(0.0, f32::max);
+
+        Animation {
+            name: animation.name().map(String::from),
+            channels,
+            duration,
+        }
+    }).collect()
+}
+
+/// Controls the playback of a single animation of a model instance.
+///
+/// The player only keeps track of time, call `AnimationPlayer::apply` to write the sampled
+/// animation into the `Pose` of the model instance.
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    animation_index: usize,
+    time: f32,
+    speed: f32,
+    playing: bool,
+    looping: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation_index: usize) -> Self {
+        Self {
+            animation_index,
+            time: 0.0,
+            speed: 1.0,
+            playing: false,
+            looping: false,
+        }
+    }
+
+    pub fn animation_index(&self) -> usize {
+        self.animation_index
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Sets the playback speed, negative values play the animation backwards.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Jumps to the given time, in seconds.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Advances the playback time. Non-looping animations stop at either end.
+    pub fn update(&mut self, model: &Model, delta_time: &Duration) {
+        if !self.playing {
+            return;
+        }
+
+        let duration = model.animations()[self.animation_index].duration();
+        let delta_seconds = ((delta_time.as_nanos() as f64) / 1.0e9) as f32;
+
+        self.time += delta_seconds * self.speed;
+
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else if self.time >= duration {
+            self.time = duration;
+            self.playing = false;
+        } else if self.time <= 0.0 {
+            self.time = 0.0;
+            self.playing = false;
+        }
+    }
+
+    /// Samples the animation at the current time and writes the result into the pose.
+    pub fn apply(&self, model: &Model, pose: &mut Pose) {
+        model.animations()[self.animation_index].apply(self.time, pose);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts;
+    use super::*;
+
+    fn channel(property: Property, interpolation: Interpolation, inputs: Vec<f32>, outputs: Vec<f32>, components: usize) -> AnimationChannel {
+        AnimationChannel {
+            node_index: 0,
+            property,
+            interpolation,
+            inputs,
+            outputs,
+            components,
+        }
+    }
+
+    fn sample(channel: &AnimationChannel, time: f32) -> Vec<f32> {
+        let mut output = vec![0.0; channel.components];
+
+        channel.sample(time, &mut output[..]);
+        output
+    }
+
+    fn assert_approx_eq(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+
+        for (a_component, b_component) in a.iter().zip(b.iter()) {
+            assert!((a_component - b_component).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn sample_linear() {
+        let channel = channel(Property::Translation, Interpolation::Linear,
+                              vec![0.0, 1.0, 3.0],
+                              vec![0.0, 0.0, 0.0,
+                                   1.0, 2.0, 3.0,
+                                   3.0, 2.0, 1.0],
+                              3);
+
+        assert_approx_eq(&sample(&channel, -1.0), &[0.0, 0.0, 0.0]);
+        assert_approx_eq(&sample(&channel, 0.5), &[0.5, 1.0, 1.5]);
+        assert_approx_eq(&sample(&channel, 1.0), &[1.0, 2.0, 3.0]);
+        assert_approx_eq(&sample(&channel, 2.0), &[2.0, 2.0, 2.0]);
+        assert_approx_eq(&sample(&channel, 4.0), &[3.0, 2.0, 1.0]);
+        assert_eq!(channel.duration(), 3.0);
+    }
+
+    #[test]
+    fn sample_step() {
+        let channel = channel(Property::Scale, Interpolation::Step,
+                              vec![0.0, 1.0],
+                              vec![1.0, 1.0, 1.0,
+                                   2.0, 2.0, 2.0],
+                              3);
+
+        assert_approx_eq(&sample(&channel, 0.99), &[1.0, 1.0, 1.0]);
+        assert_approx_eq(&sample(&channel, 1.0), &[2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn sample_cubic_spline() {
+        // In-tangent, value and out-tangent of each keyframe
+        let channel = channel(Property::MorphTargetWeights, Interpolation::CubicSpline,
+                              vec![0.0, 2.0],
+                              vec![0.5, 0.0, 0.5,
+                                   0.5, 1.0, 0.5],
+                              1);
+
+        // With tangents matching the slope, the spline is a line
+        assert_approx_eq(&sample(&channel, 0.5), &[0.25]);
+        assert_approx_eq(&sample(&channel, 1.0), &[0.5]);
+        assert_approx_eq(&sample(&channel, 2.0), &[1.0]);
+    }
+
+    #[test]
+    fn sample_nan() {
+        let channel = channel(Property::Translation, Interpolation::Linear,
+                              vec![0.0, 1.0, 2.0],
+                              vec![0.0, 0.0, 0.0,
+                                   1.0, 1.0, 1.0,
+                                   2.0, 2.0, 2.0],
+                              3);
+
+        assert_approx_eq(&sample(&channel, f32::NAN), &[0.0, 0.0, 0.0]);
+
+        let channel = AnimationChannel {
+            inputs: vec![0.0, f32::NAN, 2.0],
+            ..channel
+        };
+
+        // Must not panic
+        sample(&channel, 0.5);
+        sample(&channel, 1.5);
+    }
+
+    #[test]
+    fn sample_rotation() {
+        let half_sqrt = consts::FRAC_1_SQRT_2;
+        let channel = channel(Property::Rotation, Interpolation::Linear,
+                              vec![0.0, 1.0],
+                              vec![0.0, 0.0, 0.0, 1.0,
+                                   0.0, 0.0, half_sqrt, half_sqrt],
+                              4);
+        let angle = consts::FRAC_PI_8;
+
+        assert_approx_eq(&sample(&channel, 0.5), &[0.0, 0.0, angle.sin(), angle.cos()]);
+    }
+
+    #[test]
+    fn slerp_endpoints_and_midpoint() {
+        let from = Vec4([0.0, 0.0, 0.0, 1.0]);
+        let to = Vec4([1.0, 0.0, 0.0, 0.0]);
+        let half_sqrt = consts::FRAC_1_SQRT_2;
+
+        assert_approx_eq(&slerp(&from, &to, 0.0).0[..], &from.0[..]);
+        assert_approx_eq(&slerp(&from, &to, 1.0).0[..], &to.0[..]);
+        assert_approx_eq(&slerp(&from, &to, 0.5).0[..], &[half_sqrt, 0.0, 0.0, half_sqrt]);
+    }
+
+    #[test]
+    fn slerp_shorter_path() {
+        let from = Vec4([0.0, 0.0, 0.0, 1.0]);
+        // The same rotation as `from`
+        let to = Vec4([0.0, 0.0, 0.0, -1.0]);
+
+        assert_approx_eq(&slerp(&from, &to, 0.5).0[..], &from.0[..]);
+    }
+
+    #[test]
+    fn slerp_nearly_identical() {
+        let from = Vec4([0.0, 0.0, 0.0, 1.0]);
+        let to = Vec4([0.0, 0.0, 0.001, 1.0]).normalize();
+        let result = slerp(&from, &to, 0.5);
+
+        assert!((result.norm() - 1.0).abs() < 1e-5);
+        assert!(result[2] > 0.0 && result[2] < to[2]);
+    }
+}
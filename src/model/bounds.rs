@@ -0,0 +1,337 @@
+//! Axis-aligned bounding boxes and bounding spheres of primitives, nodes, scenes and model
+//! instances.
+
+use gltf::Document;
+use gltf::accessor::Accessor;
+use gltf::mesh::{Primitive, Semantic};
+use ammolite_math::*;
+use crate::model::AccessorDetails;
+
+/// An axis-aligned bounding box
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// The smallest box containing all of the points, `None` if there are no points
+    pub fn from_points(points: impl IntoIterator<Item=Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Aabb { min: first, max: first }, |aabb, point| {
+            Aabb {
+                min: aabb.min.min(&point),
+                max: aabb.max.max(&point),
+            }
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (&self.min + &self.max) * 0.5
+    }
+
+    /// The half of the size of the box along each axis
+    pub fn half_extents(&self) -> Vec3 {
+        (&self.max - &self.min) * 0.5
+    }
+
+    /// The eight corners of the box
+    pub fn corners(&self) -> [Vec3; 8] {
+        let mut corners = [Vec3::ZERO; 8];
+
+        for (corner_index, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                corner[axis] = if corner_index & (1 << axis) == 0 {
+                    self.min[axis]
+                } else {
+                    self.max[axis]
+                };
+            }
+        }
+
+        corners
+    }
+
+    pub fn contains_point(&self, point: &Vec3) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    /// The smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    /// The smallest box containing this box transformed by the matrix
+    pub fn transform(&self, matrix: &Mat4) -> Aabb {
+        Aabb::from_points(self.corners().iter().map(|corner| {
+            (matrix * &corner.into_homogeneous_position()).into_projected()
+        })).unwrap()
+    }
+
+    /// The smallest sphere containing this box
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.center(),
+            radius: self.half_extents().norm(),
+        }
+    }
+}
+
+/// A bounding sphere
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn contains_point(&self, point: &Vec3) -> bool {
+        self.center.distance_to_squared(point) <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        let radius_sum = self.radius + other.radius;
+
+        self.center.distance_to_squared(&other.center) <= radius_sum * radius_sum
+    }
+
+    /// The smallest sphere containing both spheres
+    pub fn union(&self, other: &BoundingSphere) -> BoundingSphere {
+        let distance = self.center.distance_to(&other.center);
+
+        if distance + other.radius <= self.radius {
+            return self.clone();
+        }
+
+        if distance + self.radius <= other.radius {
+            return other.clone();
+        }
+
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        let direction = (&other.center - &self.center) * (1.0 / distance);
+
+        BoundingSphere {
+            center: &self.center + direction * (radius - self.radius),
+            radius,
+        }
+    }
+
+    /// A sphere containing this sphere transformed by the matrix. The radius is scaled by the
+    /// largest scale of the matrix along any axis.
+    pub fn transform(&self, matrix: &Mat4) -> BoundingSphere {
+        let center = (matrix * &self.center.into_homogeneous_position()).into_projected();
+        let scale = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].iter()
+            .map(|axis| (matrix * &Vec3(*axis).into_homogeneous_direction()).norm())
+            .fold(0.0, f32::max);
+
+        BoundingSphere {
+            center,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Both bounding volumes of the same geometry
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            aabb: self.aabb.union(&other.aabb),
+            sphere: self.sphere.union(&other.sphere),
+        }
+    }
+
+    pub fn transform(&self, matrix: &Mat4) -> Bounds {
+        Bounds {
+            aabb: self.aabb.transform(matrix),
+            sphere: self.sphere.transform(matrix),
+        }
+    }
+}
+
+/// Computes the bounds of a primitive, in the coordinate space of its mesh.
+/// The bounds contain the primitive with any morph target weights between 0 and 1.
+/// Returns `None` for primitives without positions.
+fn compute_primitive_bounds(buffer_data_array: &[gltf::buffer::Data], primitive: &Primitive) -> Option<Bounds> {
+    let read_positions = |accessor: Accessor| {
+        let accessor_details = AccessorDetails::from(buffer_data_array, accessor);
+
+        (0..accessor_details.accessor.count()).map(|item_index| {
+            let mut position = Vec3::ZERO;
+
+            accessor_details.read_element_f32(item_index, &mut position.0[..]);
+            position
+        }).collect::<Vec<_>>()
+    };
+    let positions = read_positions(primitive.get(&Semantic::Positions)?);
+    let mut aabb = Aabb::from_points(positions.iter().cloned())?;
+    let center = aabb.center();
+    let mut radius = positions.iter()
+        .map(|position| center.distance_to(position))
+        .fold(0.0, f32::max);
+
+    for target in primitive.morph_targets() {
+        if let Some(target_aabb) = target.positions()
+            .and_then(|accessor| Aabb::from_points(read_positions(accessor))) {
+            aabb.min = &aabb.min + target_aabb.min.min(&Vec3::ZERO);
+            aabb.max = &aabb.max + target_aabb.max.max(&Vec3::ZERO);
+            radius += target_aabb.min.abs().max(&target_aabb.max.abs()).norm();
+        }
+    }
+
+    Some(Bounds {
+        sphere: BoundingSphere {
+            center,
+            radius,
+        },
+        aabb,
+    })
+}
+
+/// Computes the bounds of all primitives of each mesh, see `compute_primitive_bounds`.
+pub(crate) fn compute_mesh_primitive_bounds(document: &Document, buffer_data_array: &[gltf::buffer::Data]) -> Vec<Vec<Option<Bounds>>> {
+    document.meshes().map(|mesh| {
+        mesh.primitives()
+            .map(|primitive| compute_primitive_bounds(buffer_data_array, &primitive))
+            .collect()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_aabb() -> Aabb {
+        Aabb {
+            min: Vec3([-1.0, -1.0, -1.0]),
+            max: Vec3([1.0, 1.0, 1.0]),
+        }
+    }
+
+    #[test]
+    fn aabb_from_points() {
+        assert_eq!(Aabb::from_points(Vec::new()), None);
+        assert_eq!(
+            Aabb::from_points(vec![Vec3([1.0, -2.0, 3.0]), Vec3([-1.0, 2.0, 0.0]), Vec3([0.0, 0.0, -3.0])]),
+            Some(Aabb {
+                min: Vec3([-1.0, -2.0, -3.0]),
+                max: Vec3([1.0, 2.0, 3.0]),
+            }),
+        );
+    }
+
+    #[test]
+    fn aabb_union() {
+        let other = Aabb {
+            min: Vec3([0.0, 2.0, -0.5]),
+            max: Vec3([3.0, 4.0, 0.5]),
+        };
+
+        assert_eq!(unit_aabb().union(&other), Aabb {
+            min: Vec3([-1.0, -1.0, -1.0]),
+            max: Vec3([3.0, 4.0, 1.0]),
+        });
+        assert_eq!(unit_aabb().union(&other), other.union(&unit_aabb()));
+        assert_eq!(unit_aabb().union(&unit_aabb()), unit_aabb());
+    }
+
+    #[test]
+    fn aabb_corners() {
+        let aabb = unit_aabb();
+        let corners = aabb.corners();
+
+        for corner in &corners {
+            assert!(aabb.contains_point(corner));
+            assert!((0..3).all(|axis| corner[axis].abs() == 1.0));
+        }
+
+        for (index, corner) in corners.iter().enumerate() {
+            assert!(corners[(index + 1)..].iter().all(|other| other != corner));
+        }
+    }
+
+    #[test]
+    fn aabb_transform() {
+        assert_eq!(unit_aabb().transform(&Mat4::IDENTITY), unit_aabb());
+        assert_eq!(unit_aabb().transform(&Mat4::translation(&Vec3([1.0, 2.0, 3.0]))), Aabb {
+            min: Vec3([0.0, 1.0, 2.0]),
+            max: Vec3([2.0, 3.0, 4.0]),
+        });
+        assert_eq!(unit_aabb().transform(&Mat4::scale(2.0)), Aabb {
+            min: Vec3([-2.0, -2.0, -2.0]),
+            max: Vec3([2.0, 2.0, 2.0]),
+        });
+        // Mirroring swaps the corners
+        assert_eq!(unit_aabb().transform(&Mat4::scale(-1.0)), unit_aabb());
+    }
+
+    #[test]
+    fn bounding_sphere_union_contained() {
+        let outer = BoundingSphere {
+            center: Vec3::ZERO,
+            radius: 4.0,
+        };
+        let inner = BoundingSphere {
+            center: Vec3([1.0, 0.0, 0.0]),
+            radius: 1.0,
+        };
+
+        assert_eq!(outer.union(&inner), outer);
+        assert_eq!(inner.union(&outer), outer);
+        assert_eq!(outer.union(&outer), outer);
+    }
+
+    #[test]
+    fn bounding_sphere_union_disjoint() {
+        let a = BoundingSphere {
+            center: Vec3([-2.0, 0.0, 0.0]),
+            radius: 1.0,
+        };
+        let b = BoundingSphere {
+            center: Vec3([4.0, 0.0, 0.0]),
+            radius: 2.0,
+        };
+        let union = BoundingSphere {
+            center: Vec3([1.5, 0.0, 0.0]),
+            radius: 4.5,
+        };
+
+        assert_eq!(a.union(&b), union);
+        assert_eq!(b.union(&a), union);
+    }
+
+    #[test]
+    fn bounding_sphere_transform() {
+        let sphere = BoundingSphere {
+            center: Vec3([1.0, 0.0, 0.0]),
+            radius: 2.0,
+        };
+
+        assert_eq!(sphere.transform(&Mat4::translation(&Vec3([0.0, 1.0, 0.0]))), BoundingSphere {
+            center: Vec3([1.0, 1.0, 0.0]),
+            radius: 2.0,
+        });
+        assert_eq!(sphere.transform(&Mat4::scale(3.0)), BoundingSphere {
+            center: Vec3([3.0, 0.0, 0.0]),
+            radius: 6.0,
+        });
+        assert_eq!(sphere.transform(&Mat4::scale(-0.5)), BoundingSphere {
+            center: Vec3([-0.5, 0.0, 0.0]),
+            radius: 1.0,
+        });
+    }
+}
@@ -0,0 +1,502 @@
+//! Bounding volume hierarchies used to accelerate ray queries.
+
+use std::ops::Range;
+use std::cmp::Ordering;
+use ammolite_math::*;
+use gltf::Node;
+use crate::model::Model;
+use crate::model::bounds::Aabb;
+use crate::model::pose::Pose;
+
+/// Nodes with at most this many items are not split any further
+const MAX_LEAF_ITEMS: usize = 4;
+
+#[derive(Clone, Debug)]
+enum BvhNodeContent {
+    Leaf {
+        items: Range<usize>,
+    },
+    Branch {
+        children: [usize; 2],
+    },
+}
+
+#[derive(Clone, Debug)]
+struct BvhNode {
+    aabb: Aabb,
+    content: BvhNodeContent,
+}
+
+/// A bounding volume hierarchy of axis-aligned bounding boxes over arbitrary items.
+/// The hierarchy is built by recursively splitting the items at the median of their centroids
+/// along the longest axis.
+#[derive(Clone, Debug)]
+pub struct Bvh<T> {
+    nodes: Vec<BvhNode>,
+    items: Vec<T>,
+}
+
+impl<T> Bvh<T> {
+    pub fn new(items: Vec<(Aabb, T)>) -> Self {
+        let mut items = items;
+        let mut nodes = Vec::with_capacity(2 * (items.len() / MAX_LEAF_ITEMS + 1));
+
+        if !items.is_empty() {
+            Self::build_node(&mut nodes, &mut items[..], 0);
+        }
+
+        Bvh {
+            nodes,
+            items: items.into_iter().map(|(_, item)| item).collect(),
+        }
+    }
+
+    /// Appends the subtree of the items to `nodes`, returns the index of its root node.
+    fn build_node(nodes: &mut Vec<BvhNode>, items: &mut [(Aabb, T)], items_offset: usize) -> usize {
+        let aabb = items[1..].iter()
+            .fold(items[0].0.clone(), |aabb, (item_aabb, _)| aabb.union(item_aabb));
+        let node_index = nodes.len();
+
+        nodes.push(BvhNode {
+            aabb,
+            content: BvhNodeContent::Leaf {
+                items: items_offset..(items_offset + items.len()),
+            },
+        });
+
+        if items.len() <= MAX_LEAF_ITEMS {
+            return node_index;
+        }
+
+        let centroid_aabb = Aabb::from_points(items.iter().map(|(item_aabb, _)| item_aabb.center())).unwrap();
+        let extents = centroid_aabb.half_extents();
+        let axis = (1..3).fold(0, |axis, current_axis| {
+            if extents[current_axis] > extents[axis] { current_axis } else { axis }
+        });
+
+        if extents[axis] == 0.0 {
+            return node_index;
+        }
+
+        items.sort_unstable_by(|(a, _), (b, _)| {
+            a.center()[axis].partial_cmp(&b.center()[axis]).unwrap_or(Ordering::Equal)
+        });
+
+        let split_index = items.len() / 2;
+        let (items_left, items_right) = items.split_at_mut(split_index);
+        let left = Self::build_node(nodes, items_left, items_offset);
+        let right = Self::build_node(nodes, items_right, items_offset + split_index);
+
+        nodes[node_index].content = BvhNodeContent::Branch {
+            children: [left, right],
+        };
+
+        node_index
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items[..]
+    }
+
+    /// The bounds of all items, `None` if there are no items
+    pub fn aabb(&self) -> Option<&Aabb> {
+        self.nodes.first().map(|node| &node.aabb)
+    }
+
+    /// Finds the closest intersection of the ray `origin + distance * direction` with the items,
+    /// where `0 <= distance <= max_distance`. Only items whose bounding boxes are hit by the ray
+    /// are passed to `intersect_item`, along with the distance of the closest intersection found
+    /// so far. It returns the distance of the intersection with the item, if any.
+    /// If `any_hit` is `true`, the first intersection found is returned instead.
+    pub fn intersect_ray<R>(
+        &self,
+        origin: &Vec3,
+        direction: &Vec3,
+        max_distance: f32,
+        any_hit: bool,
+        mut intersect_item: impl FnMut(&T, f32) -> Option<(f32, R)>,
+    ) -> Option<(f32, R)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = Vec3([1.0 / direction[0], 1.0 / direction[1], 1.0 / direction[2]]);
+        let mut closest: Option<(f32, R)> = None;
+        let mut node_stack = vec![0];
+
+        while let Some(node_index) = node_stack.pop() {
+            let node = &self.nodes[node_index];
+            let current_max_distance = closest.as_ref().map(|(distance, _)| *distance).unwrap_or(max_distance);
+
+            if intersect_ray_aabb(&node.aabb, origin, &inverse_direction, current_max_distance).is_none() {
+                continue;
+            }
+
+            match node.content {
+                BvhNodeContent::Leaf { ref items } => {
+                    for item in &self.items[items.clone()] {
+                        let current_max_distance = closest.as_ref().map(|(distance, _)| *distance).unwrap_or(max_distance);
+
+                        if let Some((distance, result)) = intersect_item(item, current_max_distance) {
+                            if distance >= 0.0 && distance <= max_distance && closest.as_ref().map(|(closest_distance, _)| distance < *closest_distance).unwrap_or(true) {
+                                closest = Some((distance, result));
+
+                                if any_hit {
+                                    return closest;
+                                }
+                            }
+                        }
+                    }
+                },
+                BvhNodeContent::Branch { children } => {
+                    let distances = [
+                        intersect_ray_aabb(&self.nodes[children[0]].aabb, origin, &inverse_direction, current_max_distance),
+                        intersect_ray_aabb(&self.nodes[children[1]].aabb, origin, &inverse_direction, current_max_distance),
+                    ];
+
+                    // Visit the closer child first, so that the farther one may get culled
+                    let (near, far) = match distances {
+                        [Some(a), Some(b)] if b < a => (1, 0),
+                        [None, Some(_)] => (1, 0),
+                        _ => (0, 1),
+                    };
+
+                    if distances[far].is_some() {
+                        node_stack.push(children[far]);
+                    }
+
+                    if distances[near].is_some() {
+                        node_stack.push(children[near]);
+                    }
+                },
+            }
+        }
+
+        closest
+    }
+}
+
+/// The distance at which the ray enters the box, `None` if the ray misses the box or enters it
+/// farther than `max_distance`. Rays starting within the box enter it at distance 0.
+fn intersect_ray_aabb(aabb: &Aabb, origin: &Vec3, inverse_direction: &Vec3, max_distance: f32) -> Option<f32> {
+    let mut distance_min = 0.0f32;
+    let mut distance_max = max_distance;
+
+    for axis in 0..3 {
+        // Rays parallel to the slab either stay within it or never enter it. Handled separately,
+        // as the distances below would be NaN for rays on the slab boundary.
+        if inverse_direction[axis].is_infinite() {
+            if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] {
+                return None;
+            }
+
+            continue;
+        }
+
+        let distance_a = (aabb.min[axis] - origin[axis]) * inverse_direction[axis];
+        let distance_b = (aabb.max[axis] - origin[axis]) * inverse_direction[axis];
+
+        distance_min = distance_min.max(distance_a.min(distance_b));
+        distance_max = distance_max.min(distance_a.max(distance_b));
+    }
+
+    if distance_min <= distance_max {
+        Some(distance_min)
+    } else {
+        None
+    }
+}
+
+/// A triangle of a primitive, with positions in the coordinate space of the mesh
+#[derive(Clone, Debug)]
+pub(crate) struct PrimitiveTriangle {
+    /// The index of the triangle within the primitive, see `get_primitive_triangles`
+    pub index: usize,
+    pub vertex_indices: [usize; 3],
+    pub positions: [Vec3; 3],
+}
+
+/// Builds a BVH over the triangles of a primitive.
+pub(crate) fn build_triangle_bvh(triangles: impl Iterator<Item=PrimitiveTriangle>) -> Bvh<PrimitiveTriangle> {
+    Bvh::new(triangles.filter_map(|triangle| {
+        Aabb::from_points(triangle.positions.iter().cloned()).map(|aabb| (aabb, triangle))
+    }).collect())
+}
+
+/// An instance of the mesh of a node, either the node itself or one of its
+/// `EXT_mesh_gpu_instancing` instances
+#[derive(Clone, Debug)]
+pub(crate) struct MeshInstance {
+    pub node_index: usize,
+    pub mesh_index: usize,
+    /// The inverse of the transformation from the coordinate space of the mesh to world space
+    pub inverse_matrix: Mat4,
+}
+
+/// Builds a BVH over the mesh instances of all visible nodes of a scene of an instance of a
+/// model. The BVH of the rest pose is cached by the model, see `Model::scene_mesh_instance_bvh`,
+/// BVHs of other poses are meant to be rebuilt for each query.
+pub(crate) fn build_mesh_instance_bvh(model: &Model, scene_index: usize, instance_matrix: &Mat4, pose: Option<&Pose>) -> Option<Bvh<MeshInstance>> {
+    let scene = model.document.scenes().nth(scene_index)?;
+    let world_matrices = pose.map(Pose::world_matrices)
+        .unwrap_or(&model.node_transform_matrices[..]);
+    let mut items = Vec::new();
+    let mut node_stack: Vec<Node> = scene.nodes().collect();
+
+    while let Some(node) = node_stack.pop() {
+        let node_index = node.index();
+
+        if !pose.map(|pose| pose.world_visibility()[node_index]).unwrap_or(true) {
+            continue;
+        }
+
+        node_stack.extend(node.children());
+
+        let mesh_index = if let Some(mesh) = node.mesh() {
+            mesh.index()
+        } else {
+            continue;
+        };
+        let mesh_bounds = if let Some(mesh_bounds) = model.mesh_bounds(mesh_index) {
+            mesh_bounds
+        } else {
+            continue;
+        };
+        let node_matrix = instance_matrix * &world_matrices[node_index];
+        let matrices = if let Some(ref node_instance_matrices) = model.node_instance_matrices[node_index] {
+            node_instance_matrices.iter()
+                .map(|node_instance_matrix| &node_matrix * node_instance_matrix)
+                .collect()
+        } else {
+            vec![node_matrix]
+        };
+
+        for matrix in matrices {
+            // Degenerate instances cannot be hit
+            if matrix.determinant() == 0.0 {
+                continue;
+            }
+
+            items.push((mesh_bounds.aabb.transform(&matrix), MeshInstance {
+                node_index,
+                mesh_index,
+                inverse_matrix: matrix.inverse(),
+            }));
+        }
+    }
+
+    Some(Bvh::new(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_aabb() -> Aabb {
+        Aabb {
+            min: Vec3::ZERO,
+            max: Vec3([1.0, 1.0, 1.0]),
+        }
+    }
+
+    fn inverse(direction: &Vec3) -> Vec3 {
+        Vec3([1.0 / direction[0], 1.0 / direction[1], 1.0 / direction[2]])
+    }
+
+    /// A row of unit boxes along the X axis, the item is the index of the box
+    fn box_row(count: usize) -> Bvh<usize> {
+        Bvh::new((0..count).map(|index| {
+            let offset = Vec3([2.0 * index as f32, 0.0, 0.0]);
+
+            (Aabb {
+                min: &unit_aabb().min + &offset,
+                max: &unit_aabb().max + &offset,
+            }, index)
+        }).collect())
+    }
+
+    /// Intersects the ray with the box of the item within the row of boxes
+    fn intersect_box(origin: &Vec3, direction: &Vec3, index: usize, max_distance: f32) -> Option<(f32, usize)> {
+        let offset = Vec3([2.0 * index as f32, 0.0, 0.0]);
+        let aabb = Aabb {
+            min: &unit_aabb().min + &offset,
+            max: &unit_aabb().max + &offset,
+        };
+
+        intersect_ray_aabb(&aabb, origin, &inverse(direction), max_distance)
+            .map(|distance| (distance, index))
+    }
+
+    #[test]
+    fn ray_aabb_hit_and_miss() {
+        let aabb = unit_aabb();
+
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.5, 0.5]), &inverse(&Vec3([1.0, 0.0, 0.0])), 10.0), Some(2.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.5, 0.5]), &inverse(&Vec3([1.0, 0.0, 0.0])), 1.0), None);
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 2.0, 0.5]), &inverse(&Vec3([1.0, 0.0, 0.0])), 10.0), None);
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-1.0, -1.0, -1.0]), &inverse(&Vec3([1.0, 1.0, 1.0])), 10.0), Some(1.0));
+    }
+
+    #[test]
+    fn ray_aabb_behind_origin() {
+        let aabb = unit_aabb();
+
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([2.0, 0.5, 0.5]), &inverse(&Vec3([1.0, 0.0, 0.0])), 10.0), None);
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([2.0, 0.5, 0.5]), &inverse(&Vec3([-1.0, 0.0, 0.0])), 10.0), Some(1.0));
+    }
+
+    #[test]
+    fn ray_aabb_origin_inside() {
+        let aabb = unit_aabb();
+
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([0.5, 0.5, 0.5]), &inverse(&Vec3([1.0, 0.0, 0.0])), 10.0), Some(0.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([0.5, 0.5, 0.5]), &inverse(&Vec3([-1.0, 0.0, 0.0])), 0.0), Some(0.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([0.0, 0.0, 0.0]), &inverse(&Vec3([0.0, 0.0, -1.0])), 10.0), Some(0.0));
+    }
+
+    #[test]
+    fn ray_aabb_parallel_to_slab() {
+        let aabb = unit_aabb();
+        let inverse_direction = inverse(&Vec3([1.0, 0.0, 0.0]));
+
+        // Within the slabs of the Y and Z axes
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.5, 0.5]), &inverse_direction, 10.0), Some(2.0));
+        // On the boundaries of the slabs
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.0, 0.5]), &inverse_direction, 10.0), Some(2.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 1.0, 0.5]), &inverse_direction, 10.0), Some(2.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 1.0, 0.0]), &inverse_direction, 10.0), Some(2.0));
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.5, -0.0]), &inverse(&Vec3([1.0, -0.0, 0.0])), 10.0), Some(2.0));
+        // Outside of the slabs
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, -0.5, 0.5]), &inverse_direction, 10.0), None);
+        assert_eq!(intersect_ray_aabb(&aabb, &Vec3([-2.0, 0.5, 1.5]), &inverse_direction, 10.0), None);
+    }
+
+    #[test]
+    fn empty_bvh() {
+        let bvh: Bvh<usize> = Bvh::new(Vec::new());
+
+        assert_eq!(bvh.aabb(), None);
+        assert!(bvh.items().is_empty());
+        assert_eq!(bvh.intersect_ray(&Vec3::ZERO, &Vec3([1.0, 0.0, 0.0]), 10.0, false, |_, _| -> Option<(f32, ())> {
+            panic!("No items to intersect.");
+        }), None);
+    }
+
+    #[test]
+    fn bvh_contains_all_items() {
+        let bvh = box_row(10);
+        let mut items = bvh.items().to_vec();
+
+        items.sort();
+
+        assert_eq!(items, (0..10).collect::<Vec<_>>());
+        assert_eq!(bvh.aabb(), Some(&Aabb {
+            min: Vec3::ZERO,
+            max: Vec3([19.0, 1.0, 1.0]),
+        }));
+    }
+
+    #[test]
+    fn bvh_closest_hit() {
+        let bvh = box_row(10);
+        let direction = Vec3([1.0, 0.0, 0.0]);
+        let origin = Vec3([-5.0, 0.5, 0.5]);
+
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 100.0, false, |index, max_distance| {
+            intersect_box(&origin, &direction, *index, max_distance)
+        }), Some((5.0, 0)));
+
+        let direction = Vec3([-1.0, 0.0, 0.0]);
+        let origin = Vec3([25.0, 0.5, 0.5]);
+
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 100.0, false, |index, max_distance| {
+            intersect_box(&origin, &direction, *index, max_distance)
+        }), Some((6.0, 9)));
+
+        let origin = Vec3([8.5, 0.5, 0.5]);
+
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 100.0, false, |index, max_distance| {
+            intersect_box(&origin, &direction, *index, max_distance)
+        }), Some((0.0, 4)));
+    }
+
+    #[test]
+    fn bvh_max_distance() {
+        let bvh = box_row(10);
+        let direction = Vec3([1.0, 0.0, 0.0]);
+        let origin = Vec3([-5.0, 0.5, 0.5]);
+
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 4.0, false, |index, max_distance| {
+            intersect_box(&origin, &direction, *index, max_distance)
+        }), None);
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 5.0, false, |index, max_distance| {
+            intersect_box(&origin, &direction, *index, max_distance)
+        }), Some((5.0, 0)));
+
+        // Intersections reported past the maximum distance or behind the origin are ignored
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 10.0, false, |index, _| {
+            Some((if *index == 0 { 11.0 } else { -1.0 }, *index))
+        }), None);
+    }
+
+    #[test]
+    fn bvh_passes_closest_distance() {
+        let bvh = box_row(10);
+        let direction = Vec3([1.0, 0.0, 0.0]);
+        let origin = Vec3([-5.0, 0.5, 0.5]);
+        let mut closest_distance = 100.0;
+
+        bvh.intersect_ray(&origin, &direction, 100.0, false, |index, max_distance| {
+            assert!(max_distance <= closest_distance);
+
+            let result = intersect_box(&origin, &direction, *index, max_distance);
+
+            if let Some((distance, _)) = result {
+                closest_distance = closest_distance.min(distance);
+            }
+
+            result
+        });
+
+        assert_eq!(closest_distance, 5.0);
+    }
+
+    #[test]
+    fn bvh_any_hit() {
+        let bvh = box_row(10);
+        let direction = Vec3([1.0, 0.0, 0.0]);
+        let origin = Vec3([-5.0, 0.5, 0.5]);
+        let mut intersection_count = 0;
+        let result = bvh.intersect_ray(&origin, &direction, 100.0, true, |index, max_distance| {
+            intersection_count += 1;
+            intersect_box(&origin, &direction, *index, max_distance)
+        });
+
+        // Every box along the ray is hit, so the first one tested is returned
+        assert!(result.is_some());
+        assert_eq!(intersection_count, 1);
+
+        let mut intersection_count = 0;
+
+        bvh.intersect_ray(&origin, &direction, 100.0, false, |index, max_distance| {
+            intersection_count += 1;
+            intersect_box(&origin, &direction, *index, max_distance)
+        });
+
+        assert!(intersection_count > 1);
+    }
+
+    #[test]
+    fn bvh_coincident_items() {
+        let bvh = Bvh::new((0..10).map(|index| (unit_aabb(), index)).collect());
+        let direction = Vec3([0.0, 1.0, 0.0]);
+        let origin = Vec3([0.5, -1.0, 0.5]);
+        let mut intersection_count = 0;
+
+        assert_eq!(bvh.intersect_ray(&origin, &direction, 100.0, false, |index, _| {
+            intersection_count += 1;
+            Some((1.0 + *index as f32, *index))
+        }), Some((1.0, 0)));
+        assert_eq!(intersection_count, 10);
+    }
+}
@@ -1,4 +1,5 @@
-use failure::Fail;
+use std::io;
+use failure::{Fail, Error, Compat};
 
 #[derive(Debug, Fail)]
 pub enum ModelInitializationError {
@@ -6,6 +7,154 @@ pub enum ModelInitializationError {
     ModelAlreadyInitialized,
 }
 
+#[derive(Debug, Fail)]
+pub enum ModelImportError {
+    #[fail(display = "Failed to read the glTF document: {}", _0)]
+    Gltf(#[cause] gltf::Error),
+    #[fail(display = "Failed to create the device resources of the model: {}", _0)]
+    Device(#[cause] Compat<Error>),
+    #[fail(display = "The model requires the unsupported extension: {}", name)]
+    UnsupportedExtension {
+        name: String,
+    },
+    #[fail(display = "Image {} has the unsupported float encoding: {}", image_index, mime_type)]
+    UnsupportedFloatImage {
+        image_index: usize,
+        mime_type: String,
+    },
+    #[fail(display = "Sparse accessor {} references element {} out of {}", accessor_index, element_index, element_count)]
+    SparseIndexOutOfBounds {
+        accessor_index: usize,
+        element_index: usize,
+        element_count: usize,
+    },
+    #[fail(display = "Sparse accessor {} has indices of an invalid component type", accessor_index)]
+    InvalidSparseIndexType {
+        accessor_index: usize,
+    },
+    #[fail(display = "Accessor {} has an invalid component type or type", accessor_index)]
+    InvalidAccessorType {
+        accessor_index: usize,
+    },
+    #[fail(display = "Buffer view {} exceeds the bounds of buffer {}", view_index, buffer_index)]
+    BufferViewOutOfBounds {
+        view_index: usize,
+        buffer_index: usize,
+    },
+    #[fail(display = "Accessor {} exceeds the bounds of buffer view {}", accessor_index, view_index)]
+    AccessorOutOfBounds {
+        accessor_index: usize,
+        view_index: usize,
+    },
+    #[fail(display = "Primitive {} of mesh {} has no POSITION attribute", primitive_index, mesh_index)]
+    MissingPositions {
+        mesh_index: usize,
+        primitive_index: usize,
+    },
+    #[fail(display = "Accessor {} of primitive {} of mesh {} has {} elements, while the primitive has {} vertices", accessor_index, primitive_index, mesh_index, count, vertex_count)]
+    VertexCountMismatch {
+        mesh_index: usize,
+        primitive_index: usize,
+        accessor_index: usize,
+        count: usize,
+        vertex_count: usize,
+    },
+    #[fail(display = "Primitive {} of mesh {} references vertex {} out of {}", primitive_index, mesh_index, vertex_index, vertex_count)]
+    VertexIndexOutOfBounds {
+        mesh_index: usize,
+        primitive_index: usize,
+        vertex_index: usize,
+        vertex_count: usize,
+    },
+    #[fail(display = "Index accessor {} of primitive {} of mesh {} has the unsupported type {:?}", accessor_index, primitive_index, mesh_index, data_type)]
+    UnsupportedIndexType {
+        mesh_index: usize,
+        primitive_index: usize,
+        accessor_index: usize,
+        data_type: gltf::accessor::DataType,
+    },
+    #[fail(display = "Vertex attribute accessor {} of primitive {} of mesh {} has the unsupported type {:?}", accessor_index, primitive_index, mesh_index, dimensions)]
+    UnsupportedVertexAttribute {
+        mesh_index: usize,
+        primitive_index: usize,
+        accessor_index: usize,
+        dimensions: gltf::accessor::Dimensions,
+    },
+    #[fail(display = "The sampler of channel {} of animation {} has {} output values for {} keyframes", channel_index, animation_index, output_value_count, keyframe_count)]
+    AnimationOutputCountMismatch {
+        animation_index: usize,
+        channel_index: usize,
+        keyframe_count: usize,
+        output_value_count: usize,
+    },
+    #[fail(display = "Skin {} has {} inverse bind matrices for {} joints", skin_index, count, joint_count)]
+    InverseBindMatrixCountMismatch {
+        skin_index: usize,
+        count: usize,
+        joint_count: usize,
+    },
+    #[fail(display = "Primitive {} of mesh {} references joint {} out of {} of skin {}", primitive_index, mesh_index, joint_index, joint_count, skin_index)]
+    JointIndexOutOfBounds {
+        mesh_index: usize,
+        primitive_index: usize,
+        skin_index: usize,
+        joint_index: usize,
+        joint_count: usize,
+    },
+    #[fail(display = "Node {} has invalid EXT_mesh_gpu_instancing attributes", node_index)]
+    InvalidMeshGpuInstancing {
+        node_index: usize,
+    },
+    #[fail(display = "Image {} has invalid dimensions: {}x{}", image_index, width, height)]
+    InvalidImageDimensions {
+        image_index: usize,
+        width: u32,
+        height: u32,
+    },
+    #[fail(display = "The model loading thread panicked: {}", message)]
+    Panicked {
+        message: String,
+    },
+}
+
+impl From<gltf::Error> for ModelImportError {
+    fn from(error: gltf::Error) -> Self {
+        ModelImportError::Gltf(error)
+    }
+}
+
+impl From<Error> for ModelImportError {
+    fn from(error: Error) -> Self {
+        match error.downcast::<ModelImportError>() {
+            Ok(error) => error,
+            Err(error) => match error.downcast::<gltf::Error>() {
+                Ok(error) => ModelImportError::Gltf(error),
+                Err(error) => ModelImportError::Device(error.compat()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ModelExportError {
+    #[fail(display = "Failed to write the exported model: {}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "Failed to serialize the glTF document: {}", _0)]
+    Json(#[cause] gltf::json::Error),
+}
+
+impl From<io::Error> for ModelExportError {
+    fn from(error: io::Error) -> Self {
+        ModelExportError::Io(error)
+    }
+}
+
+impl From<gltf::json::Error> for ModelExportError {
+    fn from(error: gltf::json::Error) -> Self {
+        ModelExportError::Json(error)
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum ModelDrawError {
     #[fail(display = "Trying to draw the default scene while no default scene is specified")]
@@ -14,4 +163,20 @@ pub enum ModelDrawError {
     InvalidSceneIndex {
         index: usize,
     },
+    #[fail(display = "Accessor {} has no buffer view to draw from", accessor_index)]
+    MissingBufferView {
+        accessor_index: usize,
+    },
+    #[fail(display = "Buffer {} was not uploaded to the device", buffer_index)]
+    MissingDeviceBuffer {
+        buffer_index: usize,
+    },
+    #[fail(display = "The blend descriptor set of view swapchain {} was not created", view_swapchain_index)]
+    MissingBlendDescriptorSet {
+        view_swapchain_index: usize,
+    },
+    #[fail(display = "No converted index buffer was created for accessor {}", accessor_index)]
+    MissingConvertedIndexBuffer {
+        accessor_index: usize,
+    },
 }
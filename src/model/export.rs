@@ -0,0 +1,532 @@
+//! Export of models and scenes of model instances to glTF and GLB files.
+//!
+//! Exported documents store all binary data in a single buffer, including the images, which are
+//! embedded on import. Images in data URIs are kept as they are.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::fs;
+use byteorder::{LittleEndian, WriteBytesExt};
+use gltf::json;
+use crate::WorldSpaceModel;
+use crate::model::Model;
+use crate::model::pose::Pose;
+use crate::model::error::ModelExportError;
+use crate::model::import::EXT_MESH_GPU_INSTANCING;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_TYPE_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_TYPE_BIN: u32 = 0x004E4942;
+
+fn offset_index<T>(index: json::Index<T>, offset: usize) -> json::Index<T> {
+    json::Index::new((index.value() + offset) as u32)
+}
+
+fn pad_to_4_bytes(data: &mut Vec<u8>, padding: u8) {
+    while data.len() % 4 != 0 {
+        data.push(padding);
+    }
+}
+
+/// The offsets of the indices of the resources of a model within the exported document
+#[derive(Clone, Debug, Default)]
+struct ResourceOffsets {
+    buffer_views: usize,
+    accessors: usize,
+    images: usize,
+    samplers: usize,
+    textures: usize,
+    materials: usize,
+    meshes: usize,
+    cameras: usize,
+    lights: usize,
+}
+
+/// Gathers the resources and nodes of models into a single glTF document.
+#[derive(Default)]
+struct DocumentBuilder {
+    root: json::Root,
+    binary: Vec<u8>,
+    /// The `EXT_mesh_gpu_instancing` extension objects of exported nodes, added to the
+    /// serialized document, as they cannot be represented by `json::Node`
+    node_instancing_extensions: Vec<(usize, json::Value)>,
+}
+
+impl DocumentBuilder {
+    fn add_extension_names(&mut self, source: &json::Root) {
+        for extension in &source.extensions_used {
+            if !self.root.extensions_used.contains(extension) {
+                self.root.extensions_used.push(extension.clone());
+            }
+        }
+
+        for extension in &source.extensions_required {
+            if !self.root.extensions_required.contains(extension) {
+                self.root.extensions_required.push(extension.clone());
+            }
+        }
+    }
+
+    /// Adds the resources of a model, which may be referenced by the nodes of any number of its
+    /// instances. Returns the JSON of the model along with the offsets of its resources.
+    fn add_resources(&mut self, model: &Model) -> (json::Root, ResourceOffsets) {
+        let source = model.document.clone().into_json();
+        let offsets = ResourceOffsets {
+            buffer_views: self.root.buffer_views.len(),
+            accessors: self.root.accessors.len(),
+            images: self.root.images.len(),
+            samplers: self.root.samplers.len(),
+            textures: self.root.textures.len(),
+            materials: self.root.materials.len(),
+            meshes: self.root.meshes.len(),
+            cameras: self.root.cameras.len(),
+            lights: self.root.extensions.as_ref()
+                .and_then(|extensions| extensions.khr_lights_punctual.as_ref())
+                .map(|khr_lights_punctual| khr_lights_punctual.lights.len())
+                .unwrap_or(0),
+        };
+
+        self.add_extension_names(&source);
+
+        // All buffers are merged into the single buffer of the exported document
+        let buffer_offsets: Vec<usize> = model.buffer_data.iter()
+            .map(|buffer_data| {
+                pad_to_4_bytes(&mut self.binary, 0);
+
+                let buffer_offset = self.binary.len();
+
+                self.binary.extend_from_slice(&buffer_data.0[..]);
+                buffer_offset
+            })
+            .collect();
+
+        for view in &source.buffer_views {
+            let mut view = view.clone();
+
+            view.byte_offset = Some((view.byte_offset.unwrap_or(0) as usize + buffer_offsets[view.buffer.value()]) as u32);
+            view.buffer = json::Index::new(0);
+            self.root.buffer_views.push(view);
+        }
+
+        for accessor in &source.accessors {
+            let mut accessor = accessor.clone();
+
+            accessor.buffer_view = accessor.buffer_view.map(|view| offset_index(view, offsets.buffer_views));
+
+            if let Some(ref mut sparse) = accessor.sparse {
+                sparse.indices.buffer_view = offset_index(sparse.indices.buffer_view, offsets.buffer_views);
+                sparse.values.buffer_view = offset_index(sparse.values.buffer_view, offsets.buffer_views);
+            }
+
+            self.root.accessors.push(accessor);
+        }
+
+        for image in &source.images {
+            let mut image = image.clone();
+
+            image.buffer_view = image.buffer_view.map(|view| offset_index(view, offsets.buffer_views));
+            self.root.images.push(image);
+        }
+
+        self.root.samplers.extend(source.samplers.iter().cloned());
+
+        for texture in &source.textures {
+            let mut texture = texture.clone();
+
+            texture.sampler = texture.sampler.map(|sampler| offset_index(sampler, offsets.samplers));
+            texture.source = offset_index(texture.source, offsets.images);
+            self.root.textures.push(texture);
+        }
+
+        for material in &source.materials {
+            let mut material = material.clone();
+            let offset_texture_info = |info: &mut Option<json::texture::Info>| {
+                if let Some(ref mut info) = *info {
+                    info.index = offset_index(info.index, offsets.textures);
+                }
+            };
+
+            offset_texture_info(&mut material.pbr_metallic_roughness.base_color_texture);
+            offset_texture_info(&mut material.pbr_metallic_roughness.metallic_roughness_texture);
+            offset_texture_info(&mut material.emissive_texture);
+
+            if let Some(ref mut normal_texture) = material.normal_texture {
+                normal_texture.index = offset_index(normal_texture.index, offsets.textures);
+            }
+
+            if let Some(ref mut occlusion_texture) = material.occlusion_texture {
+                occlusion_texture.index = offset_index(occlusion_texture.index, offsets.textures);
+            }
+
+            self.root.materials.push(material);
+        }
+
+        for mesh in &source.meshes {
+            let mut mesh = mesh.clone();
+
+            for primitive in &mut mesh.primitives {
+                for accessor in primitive.attributes.values_mut() {
+                    *accessor = offset_index(*accessor, offsets.accessors);
+                }
+
+                primitive.indices = primitive.indices.map(|indices| offset_index(indices, offsets.accessors));
+                primitive.material = primitive.material.map(|material| offset_index(material, offsets.materials));
+
+                if let Some(ref mut targets) = primitive.targets {
+                    for target in targets {
+                        target.positions = target.positions.map(|positions| offset_index(positions, offsets.accessors));
+                        target.normals = target.normals.map(|normals| offset_index(normals, offsets.accessors));
+                        target.tangents = target.tangents.map(|tangents| offset_index(tangents, offsets.accessors));
+                    }
+                }
+            }
+
+            self.root.meshes.push(mesh);
+        }
+
+        self.root.cameras.extend(source.cameras.iter().cloned());
+
+        if let Some(source_lights) = source.extensions.as_ref().and_then(|extensions| extensions.khr_lights_punctual.as_ref()) {
+            self.root.extensions.get_or_insert_with(Default::default)
+                .khr_lights_punctual
+                .get_or_insert_with(|| json::extensions::root::KhrLightsPunctual {
+                    lights: Vec::new(),
+                })
+                .lights
+                .extend(source_lights.lights.iter().cloned());
+        }
+
+        (source, offsets)
+    }
+
+    /// Adds a copy of all nodes, skins and animations of a model, referencing the resources
+    /// added by `DocumentBuilder::add_resources`. The local transformations, morph target
+    /// weights and visibility of the nodes are taken from the pose, if provided. Hidden nodes are
+    /// kept without their contents and children, so that their subtrees are not rendered.
+    /// Returns the index of the first added node.
+    fn add_nodes(&mut self, source: &json::Root, model: &Model, offsets: &ResourceOffsets, pose: Option<&Pose>) -> usize {
+        let node_offset = self.root.nodes.len();
+        let skin_offset = self.root.skins.len();
+
+        for (node_index, node) in source.nodes.iter().enumerate() {
+            let mut node = node.clone();
+
+            node.camera = node.camera.map(|camera| offset_index(camera, offsets.cameras));
+            node.mesh = node.mesh.map(|mesh| offset_index(mesh, offsets.meshes));
+            node.skin = node.skin.map(|skin| offset_index(skin, skin_offset));
+
+            if let Some(ref mut children) = node.children {
+                for child in children {
+                    *child = offset_index(*child, node_offset);
+                }
+            }
+
+            if let Some(ref mut light) = node.extensions.as_mut().and_then(|extensions| extensions.khr_lights_punctual.as_mut()) {
+                light.light = offset_index(light.light, offsets.lights);
+            }
+
+            if let Some(pose) = pose {
+                let transform = &pose.local_transforms()[node_index];
+                let morph_weights = pose.morph_weights(node_index);
+
+                node.matrix = None;
+                node.translation = Some(transform.translation.0);
+                node.rotation = Some(json::scene::UnitQuaternion(transform.rotation.0));
+                node.scale = Some(transform.scale.0);
+                node.weights = if morph_weights.is_empty() {
+                    None
+                } else {
+                    Some(morph_weights.to_vec())
+                };
+
+                if !pose.is_visible(node_index) {
+                    node.camera = None;
+                    node.mesh = None;
+                    node.skin = None;
+                    node.weights = None;
+                    node.children = None;
+
+                    if let Some(ref mut extensions) = node.extensions {
+                        extensions.khr_lights_punctual = None;
+                    }
+
+                    self.root.nodes.push(node);
+                    continue;
+                }
+            }
+
+            if let Some(ref extension) = model.node_instancing_extensions[node_index] {
+                let mut extension = extension.clone();
+
+                if let Some(attributes) = extension["attributes"].as_object_mut() {
+                    for accessor_index in attributes.values_mut() {
+                        if let Some(index) = accessor_index.as_u64() {
+                            *accessor_index = (index as usize + offsets.accessors).into();
+                        }
+                    }
+                }
+
+                self.node_instancing_extensions.push((node_offset + node_index, extension));
+            }
+
+            self.root.nodes.push(node);
+        }
+
+        for skin in &source.skins {
+            let mut skin = skin.clone();
+
+            skin.inverse_bind_matrices = skin.inverse_bind_matrices
+                .map(|inverse_bind_matrices| offset_index(inverse_bind_matrices, offsets.accessors));
+            skin.skeleton = skin.skeleton.map(|skeleton| offset_index(skeleton, node_offset));
+
+            for joint in &mut skin.joints {
+                *joint = offset_index(*joint, node_offset);
+            }
+
+            self.root.skins.push(skin);
+        }
+
+        for animation in &source.animations {
+            let mut animation = animation.clone();
+
+            for channel in &mut animation.channels {
+                channel.target.node = offset_index(channel.target.node, node_offset);
+            }
+
+            for sampler in &mut animation.samplers {
+                sampler.input = offset_index(sampler.input, offsets.accessors);
+                sampler.output = offset_index(sampler.output, offsets.accessors);
+            }
+
+            self.root.animations.push(animation);
+        }
+
+        node_offset
+    }
+
+    fn finish(mut self) -> Result<ExportedGltf, ModelExportError> {
+        self.root.asset.generator = Some("ammolite".to_string());
+
+        if !self.binary.is_empty() {
+            pad_to_4_bytes(&mut self.binary, 0);
+            self.root.buffers.push(json::Buffer {
+                byte_length: self.binary.len() as u32,
+                name: None,
+                uri: None,
+                extensions: Default::default(),
+                extras: Default::default(),
+            });
+        }
+
+        let mut value = json::serialize::to_value(&self.root)?;
+
+        for (node_index, extension) in self.node_instancing_extensions {
+            value["nodes"][node_index]["extensions"][EXT_MESH_GPU_INSTANCING] = extension;
+        }
+
+        // Only list the extensions of the source models which are present in the exported
+        // document, extensions not supported by the `gltf` crate are lost on export
+        let mut written_extensions = HashSet::new();
+
+        collect_extension_names(&value, &mut written_extensions);
+
+        for key in &["extensionsUsed", "extensionsRequired"] {
+            if let Some(object) = value.as_object_mut() {
+                let is_empty = object.get_mut(*key)
+                    .and_then(json::Value::as_array_mut)
+                    .map(|extensions| {
+                        extensions.retain(|extension| {
+                            extension.as_str().map(|extension| written_extensions.contains(extension)).unwrap_or(false)
+                        });
+                        extensions.is_empty()
+                    })
+                    .unwrap_or(false);
+
+                if is_empty {
+                    object.remove(*key);
+                }
+            }
+        }
+
+        Ok(ExportedGltf {
+            json: value,
+            binary: self.binary,
+        })
+    }
+}
+
+/// Collects the names of the extensions used within the JSON value, i.e. the keys of all
+/// `extensions` objects.
+fn collect_extension_names(value: &json::Value, names: &mut HashSet<String>) {
+    match value {
+        json::Value::Object(object) => {
+            for (key, value) in object {
+                if key == "extensions" {
+                    if let Some(extensions) = value.as_object() {
+                        names.extend(extensions.keys().cloned());
+                    }
+                }
+
+                collect_extension_names(value, names);
+            }
+        },
+        json::Value::Array(array) => {
+            for value in array {
+                collect_extension_names(value, names);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// An exported glTF document, with its binary data in a single buffer.
+#[derive(Clone, Debug)]
+pub struct ExportedGltf {
+    json: json::Value,
+    binary: Vec<u8>,
+}
+
+impl ExportedGltf {
+    /// The binary data of the only buffer of the document, empty if the document has no buffers
+    pub fn binary(&self) -> &[u8] {
+        &self.binary[..]
+    }
+
+    /// Encodes the document as a glTF JSON file, with the buffer referenced by `buffer_uri`.
+    pub fn to_gltf(&self, buffer_uri: &str) -> Result<Vec<u8>, ModelExportError> {
+        let mut value = self.json.clone();
+
+        if !self.binary.is_empty() {
+            value["buffers"][0]["uri"] = buffer_uri.into();
+        }
+
+        Ok(json::serialize::to_vec_pretty(&value)?)
+    }
+
+    /// Encodes the document as a GLB file, with the buffer stored in the binary chunk.
+    pub fn to_glb(&self) -> Result<Vec<u8>, ModelExportError> {
+        let mut json_chunk = json::serialize::to_vec(&self.json)?;
+
+        pad_to_4_bytes(&mut json_chunk, b' ');
+
+        let mut length = 12 + 8 + json_chunk.len();
+
+        if !self.binary.is_empty() {
+            length += 8 + self.binary.len();
+        }
+
+        let mut result: Vec<u8> = Vec::with_capacity(length);
+
+        result.extend_from_slice(GLB_MAGIC);
+        result.write_u32::<LittleEndian>(GLB_VERSION).unwrap();
+        result.write_u32::<LittleEndian>(length as u32).unwrap();
+        result.write_u32::<LittleEndian>(json_chunk.len() as u32).unwrap();
+        result.write_u32::<LittleEndian>(GLB_CHUNK_TYPE_JSON).unwrap();
+        result.extend(json_chunk);
+
+        if !self.binary.is_empty() {
+            result.write_u32::<LittleEndian>(self.binary.len() as u32).unwrap();
+            result.write_u32::<LittleEndian>(GLB_CHUNK_TYPE_BIN).unwrap();
+            result.extend_from_slice(&self.binary[..]);
+        }
+
+        Ok(result)
+    }
+
+    /// Writes a `.glb` file if the path has the `glb` extension. Otherwise, writes a glTF JSON
+    /// file, with the buffer written to a `.bin` file of the same name.
+    pub fn write_path(&self, path: impl AsRef<Path>) -> Result<(), ModelExportError> {
+        let path = path.as_ref();
+        let is_glb = path.extension()
+            .map(|extension| extension.eq_ignore_ascii_case("glb"))
+            .unwrap_or(false);
+
+        if is_glb {
+            fs::write(path, self.to_glb()?)?;
+        } else {
+            let buffer_path = path.with_extension("bin");
+            let buffer_uri = buffer_path.file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            if !self.binary.is_empty() {
+                fs::write(&buffer_path, &self.binary[..])?;
+            }
+
+            fs::write(path, self.to_gltf(&buffer_uri)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Exports a model with all of its scenes. The node transformations and morph target weights
+/// are taken from the pose, if provided, otherwise the original ones are kept.
+pub fn export_model(model: &Model, pose: Option<&Pose>) -> Result<ExportedGltf, ModelExportError> {
+    let mut builder = DocumentBuilder::default();
+    let (source, offsets) = builder.add_resources(model);
+    let node_offset = builder.add_nodes(&source, model, &offsets, pose);
+
+    for scene in &source.scenes {
+        let mut scene = scene.clone();
+
+        for node in &mut scene.nodes {
+            *node = offset_index(*node, node_offset);
+        }
+
+        builder.root.scenes.push(scene);
+    }
+
+    builder.root.scene = source.scene;
+    builder.finish()
+}
+
+/// Exports instances of models as a single scene. Each instance is exported as a node with the
+/// instance matrix, parenting the root nodes of the rendered scene of the instance.
+/// The resources of models with multiple instances are shared by the instances.
+pub fn export_scene(world_space_models: &[WorldSpaceModel]) -> Result<ExportedGltf, ModelExportError> {
+    let mut builder = DocumentBuilder::default();
+    let mut model_resources: HashMap<*const Model, (json::Root, ResourceOffsets)> = HashMap::new();
+    let mut scene_nodes = Vec::with_capacity(world_space_models.len());
+
+    for world_space_model in world_space_models {
+        let model = world_space_model.model;
+        let (source, offsets) = model_resources.entry(model as *const Model)
+            .or_insert_with(|| builder.add_resources(model));
+        let node_offset = builder.add_nodes(source, model, offsets, world_space_model.pose);
+        let children = world_space_model.scene_index()
+            .and_then(|scene_index| source.scenes.get(scene_index))
+            .map(|scene| {
+                scene.nodes.iter()
+                    .map(|node| offset_index(*node, node_offset))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new);
+
+        scene_nodes.push(json::Index::new(builder.root.nodes.len() as u32));
+        builder.root.nodes.push(json::Node {
+            camera: None,
+            children: Some(children),
+            extensions: Default::default(),
+            extras: Default::default(),
+            matrix: Some(*world_space_model.matrix.as_flat_ref()),
+            mesh: None,
+            name: None,
+            rotation: None,
+            scale: None,
+            translation: None,
+            skin: None,
+            weights: None,
+        });
+    }
+
+    builder.root.scenes.push(json::Scene {
+        extensions: Default::default(),
+        extras: Default::default(),
+        name: None,
+        nodes: scene_nodes,
+    });
+    builder.root.scene = Some(json::Index::new(0));
+    builder.finish()
+}
@@ -1,12 +1,16 @@
 use std::sync::{Arc, RwLock};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::borrow::Cow;
 use std::mem;
+use std::fs;
 use core::num::NonZeroU32;
 use arr_macro::arr;
 use vulkano::sampler::SamplerAddressMode;
 use vulkano::device::Device;
 use vulkano::instance::QueueFamily;
+use vulkano::instance::PhysicalDevice;
 use vulkano::format::*;
+use vulkano::buffer::BufferAccess;
 use vulkano::buffer::TypedBufferAccess;
 use vulkano::buffer::BufferSlice;
 use vulkano::buffer::BufferUsage;
@@ -15,6 +19,7 @@ use vulkano::buffer::immutable::ImmutableBuffer;
 use vulkano::sampler::Sampler;
 use vulkano::image::SyncImage;
 use vulkano::image::Swizzle;
+use vulkano::image::ComponentSwizzle;
 use vulkano::image::ImageDimensions;
 use vulkano::image::ImageUsage;
 use vulkano::image::layout::RequiredLayouts;
@@ -24,11 +29,17 @@ use vulkano::image::traits::ImageViewAccess;
 use vulkano::image::layout::typesafety;
 use vulkano::image::sync::locker;
 use byteorder::NativeEndian;
+use byteorder::LittleEndian;
+use byteorder::ByteOrder;
 use byteorder::WriteBytesExt;
 use gltf::{self, Document};
-use gltf::mesh::Semantic;
+use gltf::mesh::{Semantic, Mode};
 use gltf::Node;
-use gltf::accessor::DataType;
+use gltf::accessor::{DataType, Dimensions};
+use gltf::json;
+use gltf::json::accessor::ComponentType;
+use gltf::json::validation::Checked;
+use gltf::animation::{Interpolation, Property};
 use gltf::image::Format as GltfFormat;
 use gltf::texture::MagFilter;
 use gltf::texture::MinFilter;
@@ -36,16 +47,23 @@ use failure::Error;
 use ammolite_math::*;
 use crate::NodeUBO;
 use crate::MaterialUBO;
+use crate::shaders::TextureTransform;
 use crate::iter::ArrayIterator;
 use crate::iter::ForcedExactSizeIterator;
 use crate::iter::ByteBufferIterator;
-use crate::vertex::{GltfVertexPosition, GltfVertexNormal, GltfVertexTangent, GltfVertexTexCoord};
+use crate::vertex::{GltfVertexNormal, GltfVertexTangent, GltfVertexJoints, GltfVertexWeights};
+use crate::vertex::{get_vertex_attribute_format, is_vertex_buffer_format_supported, unconverted_vertex_attribute_semantics};
 use crate::sampler::IntoVulkanEquivalent;
 use crate::pipeline::DescriptorSetMap;
 use crate::pipeline::GltfGraphicsPipeline;
 use crate::pipeline::GraphicsPipelineSetCache;
-use crate::model::{Model, HelperResources, AccessorDetails};
+use crate::pipeline::GraphicsPipelineProperties;
+use crate::model::{Model, HelperResources, AccessorDetails, compute_joint_matrices, default_morph_weights, get_primitive_triangles, get_line_loop_strip_indices, read_primitive_vertex_indices, get_accessor_byte_len};
 use crate::model::resource::*;
+use crate::model::error::ModelImportError;
+use crate::model::animation::import_animations;
+use crate::model::pose::NodeTransform;
+use crate::model::bounds::compute_mesh_primitive_bounds;
 
 enum ColorSpace {
     Srgb,
@@ -60,6 +78,71 @@ fn convert_double_channel_to_triple_channel<'a>(data_slice: &'a [u8]) -> Box<dyn
     Box::new(ForcedExactSizeIterator::new(unsized_iterator, iterator_len))
 }
 
+/// Decodes an sRGB encoded value in the range `[0; 1]`.
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts native endian 16-bit texels with the given number of channels, RGB texels are
+/// expanded to RGBA texels with an opaque alpha channel. Vulkan has no 16-bit sRGB formats, the
+/// color channels of sRGB texels are thus decoded to linear values. The last channel of texels
+/// with 2 or 4 channels is alpha, which is always linear.
+fn convert_u16_texels<'a>(data_slice: &'a [u8], channels: usize, srgb: bool) -> Box<dyn ExactSizeIterator<Item=u8> + 'a> {
+    let output_channels = if channels == 3 { 4 } else { channels };
+    let alpha_channel = if channels % 2 == 0 { Some(channels - 1) } else { None };
+    let unsized_iterator = data_slice.chunks(channels * 2).flat_map(move |texel| {
+        let mut output = [0u8; 8];
+
+        for channel in 0..output_channels {
+            let value = if channel >= channels {
+                std::u16::MAX
+            } else {
+                let value = NativeEndian::read_u16(&texel[(channel * 2)..]);
+
+                if srgb && Some(channel) != alpha_channel {
+                    let max = std::u16::MAX as f32;
+
+                    (srgb_to_linear(value as f32 / max) * max).round() as u16
+                } else {
+                    value
+                }
+            };
+
+            NativeEndian::write_u16(&mut output[(channel * 2)..], value);
+        }
+
+        ArrayIterator::new(output).take(output_channels * 2)
+    });
+    let iterator_len = data_slice.len() / (channels * 2) * output_channels * 2;
+    Box::new(ForcedExactSizeIterator::new(unsized_iterator, iterator_len))
+}
+
+/// Single channel images are grayscale and two channel images are grayscale with alpha, as decoded
+/// by the `gltf` crate. Their channels are swizzled so that they are sampled as such.
+fn get_image_swizzle(format: GltfFormat) -> Swizzle {
+    let (r, g, b, a) = match format {
+        GltfFormat::R8 | GltfFormat::R16 => {
+            (ComponentSwizzle::Red, ComponentSwizzle::Red, ComponentSwizzle::Red, ComponentSwizzle::One)
+        },
+        GltfFormat::R8G8 | GltfFormat::R16G16 => {
+            (ComponentSwizzle::Red, ComponentSwizzle::Red, ComponentSwizzle::Red, ComponentSwizzle::Green)
+        },
+        _ => return Swizzle::identity(),
+    };
+
+    Swizzle { r, g, b, a }
+}
+
+/// Ensures the texel conversion closure is inferred to accept slices of any lifetime.
+fn texel_conversion<F>(conversion: F) -> Box<dyn for<'a> Fn(&'a [u8]) -> Box<dyn ExactSizeIterator<Item=u8> + 'a>>
+        where F: for<'a> Fn(&'a [u8]) -> Box<dyn ExactSizeIterator<Item=u8> + 'a> + 'static {
+    Box::new(conversion)
+}
+
 pub fn import_index_buffers_by_accessor_index<'a, I>(device: &Arc<Device>,
                                                      queue_families: &I,
                                                      document: &Document,
@@ -108,6 +191,52 @@ pub fn import_index_buffers_by_accessor_index<'a, I>(device: &Arc<Device>,
     Ok(converted_index_buffers_by_accessor_index)
 }
 
+pub fn precompute_line_loop_index_buffers<'a, I>(device: &Arc<Device>,
+                                                 queue_families: &I,
+                                                 document: &Document,
+                                                 buffer_data_array: &[gltf::buffer::Data],
+                                                 initialization_tasks: &mut Vec<InitializationTask>)
+        -> Result<Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u32]> + Send + Sync>>>>, Error>
+        where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+    let mut line_loop_index_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u32]> + Send + Sync>>>> = vec![Vec::new(); document.meshes().len()];
+
+    for (mesh_index, mesh) in document.meshes().enumerate() {
+        line_loop_index_buffers[mesh_index] = vec![None; mesh.primitives().len()];
+
+        for (primitive_index, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != Mode::LineLoop {
+                continue;
+            }
+
+            let buffer_data = get_line_loop_strip_indices(read_primitive_vertex_indices(buffer_data_array, &primitive));
+
+            let converted_byte_len = mem::size_of::<u32>() * buffer_data.len();
+            let (device_index_buffer, index_buffer_initialization) = unsafe {
+                ImmutableBuffer::<[u32]>::raw(
+                    device.clone(),
+                    converted_byte_len,
+                    BufferUsage {
+                        transfer_destination: true,
+                        index_buffer: true,
+                        ..BufferUsage::none()
+                    },
+                    queue_families.clone(),
+                )
+            }?;
+            let index_buffer_initialization: BufferSlice<[u8], _> = unsafe {
+                BufferSlice::from_typed_buffer_access(index_buffer_initialization).reinterpret::<[u8]>()
+            };
+            initialization_tasks.push(InitializationTask::Buffer {
+                data: safe_transmute::guarded_transmute_to_bytes_pod_vec(buffer_data),
+                initialization_buffer: Arc::new(index_buffer_initialization),
+            });
+            line_loop_index_buffers[mesh_index][primitive_index] = Some(device_index_buffer);
+        }
+    }
+
+    Ok(line_loop_index_buffers)
+}
+
 pub fn precompute_missing_normal_buffers<'a, I>(device: &Arc<Device>,
                                                 queue_families: &I,
                                                 document: &Document,
@@ -141,37 +270,19 @@ pub fn precompute_missing_normal_buffers<'a, I>(device: &Arc<Device>,
             let mut normals_data: Vec<GltfVertexNormal> = vec![GltfVertexNormal([0.0; 3]); vertex_count];
             let mut normals_count: Vec<u8> = vec![0; vertex_count];
 
-            let mut index_iter: Box<dyn Iterator<Item=usize>> = if let Some(index_accessor) = primitive.indices() {
-                match index_accessor.data_type() {
-                    DataType::U8 => Box::new(
-                        ByteBufferIterator::<u8>::from_accessor(buffer_data_array, &index_accessor)
-                            .map(|index| index as usize)
-                    ),
-                    DataType::U16 => Box::new(
-                        ByteBufferIterator::<u16>::from_accessor(buffer_data_array, &index_accessor)
-                            .map(|index| index as usize)
-                    ),
-                    DataType::U32 => Box::new(
-                        ByteBufferIterator::<u32>::from_accessor(buffer_data_array, &index_accessor)
-                            .map(|index| index as usize)
-                    ),
-                    _ => unreachable!(),
-                }
-            } else {
-                Box::new(0..vertex_count)
-            };
+            let indices = read_primitive_vertex_indices(buffer_data_array, &primitive);
 
             // Sum normals
-            while let (Some(index_a), Some(index_b), Some(index_c)) = (index_iter.next(), index_iter.next(), index_iter.next()) {
-                let a = Vec3(unsafe {
-                    Model::index_slice_transmute::<GltfVertexPosition>(&position_accessor_details, index_a).0
-                });
-                let b = Vec3(unsafe {
-                    Model::index_slice_transmute::<GltfVertexPosition>(&position_accessor_details, index_b).0
-                });
-                let c = Vec3(unsafe {
-                    Model::index_slice_transmute::<GltfVertexPosition>(&position_accessor_details, index_c).0
-                });
+            for [a, b, c] in get_primitive_triangles(primitive.mode(), indices.len()) {
+                let (index_a, index_b, index_c) = (indices[a], indices[b], indices[c]);
+                let mut a = Vec3::ZERO;
+                let mut b = Vec3::ZERO;
+                let mut c = Vec3::ZERO;
+
+                position_accessor_details.read_element_f32(index_a, &mut a.0[..]);
+                position_accessor_details.read_element_f32(index_b, &mut b.0[..]);
+                position_accessor_details.read_element_f32(index_c, &mut c.0[..]);
+
                 let u = b - &a;
                 let v = c - &a;
                 let normal = u.cross(&v);
@@ -186,8 +297,13 @@ pub fn precompute_missing_normal_buffers<'a, I>(device: &Arc<Device>,
                 }
             }
 
-            // Normalize normals
-            for normal in &mut normals_data {
+            // Normalize normals, vertices which are not part of any triangle (e.g. those of line and
+            // point primitives) are left with zero normals
+            for (normal, &normal_count) in normals_data.iter_mut().zip(normals_count.iter()) {
+                if normal_count == 0 {
+                    continue;
+                }
+
                 let mut normal_vec = Vec3(normal.0);
                 normal_vec.normalize_mut();
                 *normal = GltfVertexNormal(normal_vec.0);
@@ -240,9 +356,6 @@ pub fn precompute_missing_tangent_buffers<'a, I>(device: &Arc<Device>,
             }
 
             let vertex_count = primitive.get(&Semantic::Positions).unwrap().count();
-            let index_count = primitive.indices()
-                .map(|index_accessor| index_accessor.count())
-                .unwrap_or(vertex_count);
 
             let converted_byte_len = mem::size_of::<GltfVertexTangent>() * vertex_count;
             let (device_tangent_buffer, tangent_buffer_initialization) = unsafe {
@@ -261,43 +374,13 @@ pub fn precompute_missing_tangent_buffers<'a, I>(device: &Arc<Device>,
                 BufferSlice::from_typed_buffer_access(tangent_buffer_initialization).reinterpret::<[u8]>()
             };
             let mut buffer_data: Vec<GltfVertexTangent> = vec![GltfVertexTangent([0.0; 4]); vertex_count];
-            // TODO
             let vertices_per_face = 3;
-            let face_count = index_count / vertices_per_face;
-            let index_accessor_details = primitive.indices()
-                .map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor));
-
-            let get_semantic_index: Box<dyn Fn(usize, usize) -> usize> = if let Some(index_accessor_details) = index_accessor_details {
-                match index_accessor_details.accessor.data_type() {
-                    DataType::U8 => {
-                        Box::new(move |face_index, vertex_index| *unsafe {
-                            Model::index_slice_transmute::<u8>(
-                                &index_accessor_details,
-                                face_index * vertices_per_face + vertex_index as usize,
-                            )
-                        } as usize)
-                    },
-                    DataType::U16 => {
-                        Box::new(move |face_index, vertex_index| *unsafe {
-                            Model::index_slice_transmute::<u16>(
-                                &index_accessor_details,
-                                face_index * vertices_per_face + vertex_index as usize,
-                            )
-                        } as usize)
-                    },
-                    DataType::U32 => {
-                        Box::new(move |face_index, vertex_index| *unsafe {
-                            Model::index_slice_transmute::<u32>(
-                                &index_accessor_details,
-                                face_index * vertices_per_face + vertex_index as usize,
-                            )
-                        } as usize)
-                    },
-                    _ => unreachable!(),
-                }
-            } else {
-                Box::new(|face_index, vertex_index| { face_index * vertices_per_face + vertex_index })
-            };
+            let indices = read_primitive_vertex_indices(buffer_data_array, &primitive);
+            let faces: Vec<[usize; 3]> = get_primitive_triangles(primitive.mode(), indices.len())
+                .map(|[a, b, c]| [indices[a], indices[b], indices[c]])
+                .collect();
+            let face_count = faces.len();
+            let get_semantic_index = |face_index: usize, vertex_index: usize| faces[face_index][vertex_index];
 
             let position_accessor_details = primitive.get(&Semantic::Positions)
                 .map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor))
@@ -309,42 +392,52 @@ pub fn precompute_missing_tangent_buffers<'a, I>(device: &Arc<Device>,
             } else {
                 None
             };
-            // FIXME: More indices
-            let tex_coord_accessor_details = primitive.get(&Semantic::TexCoords(0))
+            // Tangents are generated for the UV set the normal texture is sampled with
+            let tex_coord_set = primitive.material().normal_texture()
+                .map(|normal_texture| normal_texture.tex_coord())
+                .unwrap_or(0);
+            let tex_coord_accessor_details = primitive.get(&Semantic::TexCoords(tex_coord_set))
                 .map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor));
             let zero_tex_coord: [f32; 2] = Default::default();
 
+            // Attributes may be quantized, convert them to `f32` before generating tangents
+            macro_rules! read_elements {
+                ($accessor_details:expr, $element_type:ty) => {{
+                    let accessor_details = $accessor_details;
+
+                    (0..accessor_details.accessor.count()).map(|item_index| {
+                        let mut element: $element_type = Default::default();
+
+                        accessor_details.read_element_f32(item_index, &mut element[..]);
+                        element
+                    }).collect::<Vec<$element_type>>()
+                }}
+            }
+
+            let positions = read_elements!(&position_accessor_details, [f32; 3]);
+            let normals = normal_accessor_details.as_ref()
+                .map(|normal_accessor_details| read_elements!(normal_accessor_details, [f32; 3]));
+            let tex_coords = tex_coord_accessor_details.as_ref()
+                .map(|tex_coord_accessor_details| read_elements!(tex_coord_accessor_details, [f32; 2]));
+
             mikktspace::generate_tangents(
                 &|| { vertices_per_face }, // vertices_per_face: &'a Fn() -> usize, 
                 &|| { face_count }, // face_count: &'a Fn() -> usize, 
-                &|face_index, vertex_index| unsafe {
-                    &Model::index_slice_transmute::<GltfVertexPosition>(
-                        &position_accessor_details,
-                        get_semantic_index(face_index, vertex_index),
-                    ).0
+                &|face_index, vertex_index| {
+                    &positions[get_semantic_index(face_index, vertex_index)]
                 }, // position: &'a Fn(usize, usize) -> &'a [f32; 3],
                 &|face_index, vertex_index| {
                     let semantic_index = get_semantic_index(face_index, vertex_index);
 
-                    if let &Some(ref normal_accessor_details) = &normal_accessor_details {
-                        unsafe {
-                            &Model::index_slice_transmute::<GltfVertexNormal>(
-                                normal_accessor_details,
-                                semantic_index,
-                            ).0
-                        }
+                    if let &Some(ref normals) = &normals {
+                        &normals[semantic_index]
                     } else {
                         &precomputed_normals.unwrap()[semantic_index].0
                     }
                 }, // normal: &'a Fn(usize, usize) -> &'a [f32; 3],
                 &|face_index, vertex_index| {
-                    if let &Some(ref tex_coord_accessor_details) = &tex_coord_accessor_details {
-                        unsafe {
-                            &Model::index_slice_transmute::<GltfVertexTexCoord>(
-                                &tex_coord_accessor_details,
-                                get_semantic_index(face_index, vertex_index),
-                            ).0
-                        }
+                    if let &Some(ref tex_coords) = &tex_coords {
+                        &tex_coords[get_semantic_index(face_index, vertex_index)]
                     } else {
                         &zero_tex_coord
                     }
@@ -369,14 +462,96 @@ pub fn precompute_missing_tangent_buffers<'a, I>(device: &Arc<Device>,
     Ok(tangent_buffers)
 }
 
+pub fn precompute_skinning_buffers<'a, I>(device: &Arc<Device>,
+                                          queue_families: &I,
+                                          document: &Document,
+                                          buffer_data_array: &[gltf::buffer::Data],
+                                          initialization_tasks: &mut Vec<InitializationTask>)
+        -> Result<(
+               Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>,
+               Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>
+           ), Error>
+        where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+    let mut joint_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>> = vec![Vec::new(); document.meshes().len()];
+    let mut weight_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>> = vec![Vec::new(); document.meshes().len()];
+
+    macro_rules! create_vertex_buffer {
+        ($buffer_data:expr) => {{
+            let buffer_data = safe_transmute::guarded_transmute_to_bytes_pod_vec($buffer_data);
+            let (device_buffer, buffer_initialization) = unsafe {
+                ImmutableBuffer::<[u8]>::raw(
+                    device.clone(),
+                    buffer_data.len(),
+                    BufferUsage {
+                        transfer_destination: true,
+                        vertex_buffer: true,
+                        ..BufferUsage::none()
+                    },
+                    queue_families.clone(),
+                )
+            }?;
+
+            initialization_tasks.push(InitializationTask::Buffer {
+                data: buffer_data,
+                initialization_buffer: Arc::new(buffer_initialization),
+            });
+
+            device_buffer
+        }}
+    }
+
+    for (mesh_index, mesh) in document.meshes().enumerate() {
+        joint_buffers[mesh_index] = vec![None; mesh.primitives().len()];
+        weight_buffers[mesh_index] = vec![None; mesh.primitives().len()];
+
+        for (primitive_index, primitive) in mesh.primitives().enumerate() {
+            if let Some(joints_accessor) = primitive.get(&Semantic::Joints(0)) {
+                let joints_accessor_details = AccessorDetails::from(&buffer_data_array[..], joints_accessor);
+                let joints_data: Vec<GltfVertexJoints> = (0..joints_accessor_details.accessor.count())
+                    .map(|item_index| {
+                        let mut joints = [0; 4];
+
+                        for (component_index, joint) in joints.iter_mut().enumerate() {
+                            *joint = joints_accessor_details.read_component_u32(item_index, component_index);
+                        }
+
+                        GltfVertexJoints(joints)
+                    })
+                    .collect();
+
+                joint_buffers[mesh_index][primitive_index] = Some(create_vertex_buffer!(joints_data));
+            }
+
+            if let Some(weights_accessor) = primitive.get(&Semantic::Weights(0)) {
+                let weights_accessor_details = AccessorDetails::from(&buffer_data_array[..], weights_accessor);
+                let weights_data: Vec<GltfVertexWeights> = (0..weights_accessor_details.accessor.count())
+                    .map(|item_index| {
+                        let mut weights = [0.0; 4];
+
+                        for (component_index, weight) in weights.iter_mut().enumerate() {
+                            *weight = weights_accessor_details.read_component_f32(item_index, component_index);
+                        }
+
+                        GltfVertexWeights(weights)
+                    })
+                    .collect();
+
+                weight_buffers[mesh_index][primitive_index] = Some(create_vertex_buffer!(weights_data));
+            }
+        }
+    }
+
+    Ok((joint_buffers, weight_buffers))
+}
+
 pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
                                     queue_families: &I,
                                     document: &Document,
                                     buffer_data_array: &[gltf::buffer::Data],
                                     initialization_tasks: &mut Vec<InitializationTask>)
-        -> Result<Vec<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>, Error>
+        -> Result<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>, Error>
         where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
-    let mut device_buffers: Vec<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = Vec::with_capacity(buffer_data_array.len());
+    let mut device_buffers: Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>> = Vec::with_capacity(buffer_data_array.len());
     let mut buffer_usage_vec = vec![BufferUsage::none(); buffer_data_array.len()];
 
     // Scan document for buffer usage and optimize
@@ -388,9 +563,9 @@ pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
                         let semantic = Semantic::$($semantic)+(semantic_index);
 
                         if let Some(accessor) = primitive.get(&semantic) {
-                            let buffer_index = accessor.view().buffer().index();
-
-                            buffer_usage_vec[buffer_index].$buffer_usage_field = true;
+                            if let Some(view) = accessor.view() {
+                                buffer_usage_vec[view.buffer().index()].$buffer_usage_field = true;
+                            }
                         } else {
                             break;
                         }
@@ -398,18 +573,14 @@ pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
                 };
 
                 (unindexed [Indices] $buffer_usage_field:ident) => {
-                    if let Some(accessor) = primitive.indices() {
-                        let buffer_index = accessor.view().buffer().index();
-
-                        buffer_usage_vec[buffer_index].$buffer_usage_field = true;
+                    if let Some(view) = primitive.indices().and_then(|accessor| accessor.view()) {
+                        buffer_usage_vec[view.buffer().index()].$buffer_usage_field = true;
                     }
                 };
 
                 (unindexed [$($semantic:tt)+] $buffer_usage_field:ident) => {
-                    if let Some(accessor) = primitive.get(&Semantic::$($semantic)+) {
-                        let buffer_index = accessor.view().buffer().index();
-
-                        buffer_usage_vec[buffer_index].$buffer_usage_field = true;
+                    if let Some(view) = primitive.get(&Semantic::$($semantic)+).and_then(|accessor| accessor.view()) {
+                        buffer_usage_vec[view.buffer().index()].$buffer_usage_field = true;
                     }
                 };
             }
@@ -420,8 +591,7 @@ pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
             check_accessor!(unindexed [Tangents] vertex_buffer);
             check_accessor!(indexed [Colors] vertex_buffer);
             check_accessor!(indexed [TexCoords] vertex_buffer);
-            // check_accessor!(indexed [Joints] ???);
-            // check_accessor!(indexed [Weights] ???);
+            // Joints and weights are converted in `precompute_skinning_buffers`
         }
     }
 
@@ -429,8 +599,10 @@ pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
     for (index, &gltf::buffer::Data(ref buffer_data)) in buffer_data_array.iter().enumerate() {
         let mut buffer_usage = buffer_usage_vec[index];
 
+        // Buffers only accessed on the CPU side are not uploaded
         if buffer_usage == BufferUsage::none() {
-            panic!("Buffers with such usage are not yet implememented.");
+            device_buffers.push(None);
+            continue;
         }
 
         buffer_usage.transfer_destination = true;
@@ -448,21 +620,25 @@ pub fn import_device_buffers<'a, I>(device: &Arc<Device>,
             data: buffer_data.iter().cloned().collect::<Vec<_>>(),
             initialization_buffer: Arc::new(buffer_initialization),
         });
-        device_buffers.push(device_buffer);
+        device_buffers.push(Some(device_buffer));
     }
 
     Ok(device_buffers)
 }
 
+/// Uploads the images used by materials. The `gltf` crate only decodes PNG and JPEG images, with 8
+/// or 16 bits per channel, models with float and HDR images are rejected by
+/// `check_float_images`.
 pub fn import_device_images<'a, I>(device: &Arc<Device>,
                                    _queue_families: &I,
                                    helper_resources: &HelperResources,
                                    document: &Document,
                                    image_data_array: Vec<gltf::image::Data>,
                                    initialization_tasks: &mut Vec<InitializationTask>)
-        -> Result<Vec<Arc<dyn ImageViewAccess + Send + Sync>>, Error>
+        -> Result<(Vec<Arc<dyn ImageViewAccess + Send + Sync>>, Vec<usize>), Error>
         where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
     let mut device_images: Vec<Arc<dyn ImageViewAccess + Send + Sync>> = vec![helper_resources.empty_image.clone(); image_data_array.len()];
+    let mut device_image_byte_lens: Vec<usize> = vec![0; image_data_array.len()];
 
     struct ArcImageData {
         pixels: Arc<Vec<u8>>,
@@ -528,7 +704,7 @@ pub fn import_device_images<'a, I>(device: &Arc<Device>,
                             device_image.clone(),
                             None,
                             None,
-                            Swizzle::identity(),
+                            get_image_swizzle(format),
                             None,
                             required_layouts,
                         )?)
@@ -553,6 +729,11 @@ pub fn import_device_images<'a, I>(device: &Arc<Device>,
                     // )?;
                     ($insert_init_tasks)(device_image);
                     device_images[image.index()] = device_image_view;
+                    device_image_byte_lens[image.index()] = get_mipmapped_image_byte_len(
+                        width,
+                        height,
+                        $($vk_format)+.format().size().unwrap_or(0),
+                    );
                 }}
             }
 
@@ -597,11 +778,38 @@ pub fn import_device_images<'a, I>(device: &Arc<Device>,
                 (GltfFormat::R8G8B8A8, ColorSpace::Srgb) => insert_image_with_format!([R8G8B8A8Srgb]),
                 (GltfFormat::B8G8R8A8, ColorSpace::Linear) => insert_image_with_format!([B8G8R8A8Unorm]),
                 (GltfFormat::B8G8R8A8, ColorSpace::Srgb) => insert_image_with_format!([B8G8R8A8Srgb]),
+                (GltfFormat::R16, ColorSpace::Linear) => insert_image_with_format!([R16Unorm]),
+                (GltfFormat::R16, ColorSpace::Srgb) => insert_image_with_format!([R16Unorm], texel_conversion(|data| convert_u16_texels(data, 1, true))),
+                (GltfFormat::R16G16, ColorSpace::Linear) => insert_image_with_format!([R16G16Unorm]),
+                (GltfFormat::R16G16, ColorSpace::Srgb) => insert_image_with_format!([R16G16Unorm], texel_conversion(|data| convert_u16_texels(data, 2, true))),
+                (GltfFormat::R16G16B16, ColorSpace::Linear) => insert_image_with_format!([R16G16B16A16Unorm], texel_conversion(|data| convert_u16_texels(data, 3, false))),
+                (GltfFormat::R16G16B16, ColorSpace::Srgb) => insert_image_with_format!([R16G16B16A16Unorm], texel_conversion(|data| convert_u16_texels(data, 3, true))),
+                (GltfFormat::R16G16B16A16, ColorSpace::Linear) => insert_image_with_format!([R16G16B16A16Unorm]),
+                (GltfFormat::R16G16B16A16, ColorSpace::Srgb) => insert_image_with_format!([R16G16B16A16Unorm], texel_conversion(|data| convert_u16_texels(data, 4, true))),
             }
         }
     }
 
-    Ok(device_images)
+    Ok((device_images, device_image_byte_lens))
+}
+
+/// The number of bytes occupied by the texels of an image along with its full mip chain
+fn get_mipmapped_image_byte_len(width: u32, height: u32, texel_byte_len: usize) -> usize {
+    let (mut width, mut height) = (width as usize, height as usize);
+    let mut byte_len = 0;
+
+    loop {
+        byte_len += width * height * texel_byte_len;
+
+        if width <= 1 && height <= 1 {
+            break;
+        }
+
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+
+    byte_len
 }
 
 fn get_node_matrices_impl(parent: Option<&Node>, node: &Node, results: &mut Vec<Option<Mat4>>) {
@@ -638,16 +846,258 @@ fn get_node_matrices(document: &Document) -> Vec<Mat4> {
     results.into_iter().map(|option| option.unwrap_or(Mat4::IDENTITY)).collect()
 }
 
-pub fn create_node_descriptor_sets<'a>(device: &Arc<Device>,
-                                       pipelines: impl IntoIterator<Item=&'a GltfGraphicsPipeline>,
-                                       document: &Document,
-                                       initialization_tasks: &mut Vec<InitializationTask>)
-        -> Result<(Vec<Mat4>, Vec<DescriptorSetMap>), Error> {
+pub(crate) const EXT_MESH_GPU_INSTANCING: &str = "EXT_mesh_gpu_instancing";
+
+/// Reads the instance transformations of nodes using `EXT_mesh_gpu_instancing`, `None` for other
+/// nodes. Attributes which are not specified default to the identity transformation.
+fn import_node_instance_matrices(document: &Document,
+                                 buffer_data_array: &[gltf::buffer::Data],
+                                 raw_json: &json::Value)
+        -> Result<Vec<Option<Vec<Mat4>>>, ModelImportError> {
+    document.nodes().map(|node| {
+        let attributes = &raw_json["nodes"][node.index()]["extensions"][EXT_MESH_GPU_INSTANCING]["attributes"];
+        let attributes = match attributes.as_object() {
+            Some(attributes) => attributes,
+            None => return Ok(None),
+        };
+        let invalid = || ModelImportError::InvalidMeshGpuInstancing { node_index: node.index() };
+        let get_accessor_details = |semantic: &str, dimensions: Dimensions| {
+            attributes.get(semantic).map(|accessor_index| -> Result<_, ModelImportError> {
+                let accessor = accessor_index.as_u64()
+                    .and_then(|accessor_index| document.accessors().nth(accessor_index as usize))
+                    .filter(|accessor| accessor.dimensions() == dimensions)
+                    .ok_or_else(invalid)?;
+
+                Ok(AccessorDetails::from(buffer_data_array, accessor))
+            }).transpose()
+        };
+        let translations = get_accessor_details("TRANSLATION", Dimensions::Vec3)?;
+        let rotations = get_accessor_details("ROTATION", Dimensions::Vec4)?;
+        let scales = get_accessor_details("SCALE", Dimensions::Vec3)?;
+        let counts: Vec<usize> = [&translations, &rotations, &scales].iter()
+            .filter_map(|accessor_details| accessor_details.as_ref())
+            .map(|accessor_details| accessor_details.accessor.count())
+            .collect();
+        let instance_count = counts.first().cloned().unwrap_or(0);
+
+        if counts.iter().any(|&count| count != instance_count) {
+            return Err(invalid());
+        }
+
+        let matrices = (0..instance_count).map(|instance_index| {
+            let mut transform = NodeTransform::default();
+
+            if let Some(ref translations) = translations {
+                translations.read_element_f32(instance_index, &mut transform.translation.0[..]);
+            }
+
+            if let Some(ref rotations) = rotations {
+                rotations.read_element_f32(instance_index, &mut transform.rotation.0[..]);
+            }
+
+            if let Some(ref scales) = scales {
+                scales.read_element_f32(instance_index, &mut transform.scale.0[..]);
+            }
+
+            transform.matrix()
+        }).collect();
+
+        Ok(Some(matrices))
+    }).collect()
+}
+
+/// The raw `EXT_mesh_gpu_instancing` extension objects of each node, kept to be exported, as the
+/// extension is not represented by `json::Node`.
+fn import_node_instancing_extensions(document: &Document, raw_json: &json::Value) -> Vec<Option<json::Value>> {
+    document.nodes().map(|node| {
+        let extension = &raw_json["nodes"][node.index()]["extensions"][EXT_MESH_GPU_INSTANCING];
+
+        if extension.is_object() {
+            Some(extension.clone())
+        } else {
+            None
+        }
+    }).collect()
+}
+
+/// Reads the inverse bind matrices of each skin, defaulting to identity matrices if they are
+/// not specified.
+pub fn import_skin_inverse_bind_matrices(document: &Document,
+                                         buffer_data_array: &[gltf::buffer::Data])
+        -> Vec<Vec<Mat4>> {
+    document.skins().map(|skin| {
+        if let Some(accessor) = skin.inverse_bind_matrices() {
+            let accessor_details = AccessorDetails::from(buffer_data_array, accessor);
+
+            (0..accessor_details.accessor.count()).map(|item_index| {
+                let mut matrix = [[0.0; 4]; 4];
+
+                for component_index in 0..16 {
+                    matrix[component_index / 4][component_index % 4]
+                        = accessor_details.read_component_f32(item_index, component_index);
+                }
+
+                Mat4::new(matrix)
+            }).collect()
+        } else {
+            vec![Mat4::IDENTITY; skin.joints().count()]
+        }
+    }).collect()
+}
+
+fn create_storage_buffer<'a, I>(device: &Arc<Device>,
+                                queue_families: &I,
+                                buffer_data: Vec<u8>,
+                                initialization_tasks: &mut Vec<InitializationTask>)
+        -> Result<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>, Error>
+        where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+    let (device_buffer, buffer_initialization) = unsafe {
+        ImmutableBuffer::<[u8]>::raw(
+            device.clone(),
+            buffer_data.len(),
+            BufferUsage {
+                transfer_destination: true,
+                storage_buffer: true,
+                ..BufferUsage::none()
+            },
+            queue_families.clone(),
+        )
+    }?;
+
+    initialization_tasks.push(InitializationTask::Buffer {
+        data: buffer_data,
+        initialization_buffer: Arc::new(buffer_initialization),
+    });
+
+    Ok(device_buffer)
+}
+
+/// Packs the morph target deltas of all primitives of each mesh into a single storage buffer
+/// per mesh. For every vertex of a primitive, the position, normal and tangent deltas of all
+/// morph targets are stored consecutively as `vec4`s. Returns the buffers and the offset of each
+/// primitive within its mesh buffer, in `vec4`s.
+pub fn precompute_morph_target_buffers<'a, I>(device: &Arc<Device>,
+                                              queue_families: &I,
+                                              document: &Document,
+                                              buffer_data_array: &[gltf::buffer::Data],
+                                              initialization_tasks: &mut Vec<InitializationTask>)
+        -> Result<(
+               Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>,
+               Vec<Vec<u32>>
+           ), Error>
+        where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+    let mut morph_target_buffers: Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>> = vec![None; document.meshes().len()];
+    let mut morph_target_offsets: Vec<Vec<u32>> = vec![Vec::new(); document.meshes().len()];
+
+    for (mesh_index, mesh) in document.meshes().enumerate() {
+        let mut deltas: Vec<[f32; 4]> = Vec::new();
+
+        for primitive in mesh.primitives() {
+            morph_target_offsets[mesh_index].push(deltas.len() as u32);
+
+            let vertex_count = primitive.get(&Semantic::Positions).unwrap().count();
+            let morph_target_accessor_details: Vec<[Option<AccessorDetails>; 3]> = primitive.morph_targets()
+                .map(|morph_target| [
+                    morph_target.positions().map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor)),
+                    morph_target.normals().map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor)),
+                    morph_target.tangents().map(|accessor| AccessorDetails::from(&buffer_data_array[..], accessor)),
+                ])
+                .collect();
+
+            for vertex_index in 0..vertex_count {
+                for accessor_details_array in &morph_target_accessor_details {
+                    for accessor_details in accessor_details_array.iter() {
+                        let mut delta = [0.0; 4];
+
+                        if let Some(accessor_details) = accessor_details {
+                            for (component_index, component) in delta[..3].iter_mut().enumerate() {
+                                *component = accessor_details.read_component_f32(vertex_index, component_index);
+                            }
+                        }
+
+                        deltas.push(delta);
+                    }
+                }
+            }
+        }
+
+        if !deltas.is_empty() {
+            let mut buffer_data: Vec<u8> = Vec::with_capacity(deltas.len() * mem::size_of::<[f32; 4]>());
+
+            for component in deltas.iter().flat_map(|delta| delta.iter()) {
+                buffer_data.write_f32::<NativeEndian>(*component).unwrap();
+            }
+
+            morph_target_buffers[mesh_index] = Some(
+                create_storage_buffer(device, queue_families, buffer_data, initialization_tasks)?
+            );
+        }
+    }
+
+    Ok((morph_target_buffers, morph_target_offsets))
+}
+
+pub fn create_node_descriptor_sets<'a, 'b, I>(device: &Arc<Device>,
+                                              queue_families: &I,
+                                              pipelines: impl IntoIterator<Item=&'b GltfGraphicsPipeline>,
+                                              helper_resources: &HelperResources,
+                                              document: &Document,
+                                              skin_inverse_bind_matrices: &[Vec<Mat4>],
+                                              morph_target_buffers: &[Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>],
+                                              initialization_tasks: &mut Vec<InitializationTask>)
+        -> Result<(Vec<Mat4>, Vec<DescriptorSetMap>, usize), Error>
+        where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
     let pipelines: Vec<_> = pipelines.into_iter().map(Clone::clone).collect();
     let mut node_descriptor_set_maps: Vec<DescriptorSetMap> = Vec::with_capacity(document.nodes().len());
     let transform_matrices = get_node_matrices(&document);
+    // The bytes of the buffers created for the descriptor sets, the zero buffer is shared
+    let mut buffer_bytes = 0;
 
     for node in document.nodes() {
+        // Nodes without a skin bind the zero buffer instead of joint matrices
+        let joint_matrix_buffer: Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync> = if let Some(skin) = node.skin() {
+            let joint_matrices = compute_joint_matrices(
+                &skin,
+                &transform_matrices[node.index()],
+                &skin_inverse_bind_matrices[skin.index()][..],
+                &transform_matrices[..],
+            );
+            let mut buffer_data: Vec<u8> = Vec::with_capacity(joint_matrices.len() * mem::size_of::<Mat4>());
+
+            for joint_matrix in &joint_matrices {
+                for component in joint_matrix.as_flat_ref().iter() {
+                    buffer_data.write_f32::<NativeEndian>(*component).unwrap();
+                }
+            }
+
+            let joint_matrix_buffer = create_storage_buffer(device, queue_families, buffer_data, initialization_tasks)?;
+
+            buffer_bytes += joint_matrix_buffer.size();
+            joint_matrix_buffer
+        } else {
+            helper_resources.zero_buffer.clone()
+        };
+
+        // Nodes without morph targets bind the zero buffer instead of deltas and weights
+        let morph_target_buffer: Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync> = node.mesh()
+            .and_then(|mesh| morph_target_buffers[mesh.index()].clone())
+            .unwrap_or_else(|| helper_resources.zero_buffer.clone());
+        let morph_weights = default_morph_weights(&node);
+        let morph_weight_buffer: Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync> = if !morph_weights.is_empty() {
+            let mut buffer_data: Vec<u8> = Vec::with_capacity(morph_weights.len() * mem::size_of::<f32>());
+
+            for weight in &morph_weights {
+                buffer_data.write_f32::<NativeEndian>(*weight).unwrap();
+            }
+
+            let morph_weight_buffer = create_storage_buffer(device, queue_families, buffer_data, initialization_tasks)?;
+
+            buffer_bytes += morph_weight_buffer.size();
+            morph_weight_buffer
+        } else {
+            helper_resources.zero_buffer.clone()
+        };
+
         let node_ubo = NodeUBO::new(transform_matrices[node.index()].clone());
         let (device_buffer, buffer_initialization) = unsafe {
             ImmutableBuffer::<NodeUBO>::uninitialized(
@@ -655,10 +1105,16 @@ pub fn create_node_descriptor_sets<'a>(device: &Arc<Device>,
                 BufferUsage::uniform_buffer_transfer_destination(),
             )
         }?;
+
+        buffer_bytes += device_buffer.size();
+
         let descriptor_set_map = DescriptorSetMap::custom(&pipelines[..], |pipeline|
             Arc::new(
-                PersistentDescriptorSet::start(pipeline.layout.clone(), 2)
+                PersistentDescriptorSet::start(pipeline.layout.clone(), 1)
                     .add_buffer(device_buffer.clone()).unwrap()
+                    .add_buffer(joint_matrix_buffer.clone()).unwrap()
+                    .add_buffer(morph_target_buffer.clone()).unwrap()
+                    .add_buffer(morph_weight_buffer.clone()).unwrap()
                     .build().unwrap()
             )
         );
@@ -670,18 +1126,20 @@ pub fn create_node_descriptor_sets<'a>(device: &Arc<Device>,
         node_descriptor_set_maps.push(descriptor_set_map);
     }
 
-    Ok((transform_matrices, node_descriptor_set_maps))
+    Ok((transform_matrices, node_descriptor_set_maps, buffer_bytes))
 }
 
-pub fn create_samplers(device: &Arc<Device>, document: &Document) -> Result<Vec<Arc<Sampler>>, Error> {
+pub fn create_samplers(device: &Arc<Device>, helper_resources: &HelperResources, document: &Document) -> Result<Vec<Arc<Sampler>>, Error> {
     let mut device_samplers: Vec<Arc<Sampler>> = Vec::with_capacity(document.samplers().len());
 
     for gltf_sampler in document.samplers() {
-        // let (min_filter, mipmap_mode) = gltf_sampler.min_filter()
-        //     .unwrap_or(MinFilter::LinearMipmapLinear)
-        //     .into_vulkan_equivalent();
-        let (min_filter, mipmap_mode) = MinFilter::LinearMipmapLinear
-            .into_vulkan_equivalent();
+        let gltf_min_filter = gltf_sampler.min_filter().unwrap_or(MinFilter::LinearMipmapLinear);
+        let (min_filter, mipmap_mode) = gltf_min_filter.into_vulkan_equivalent();
+        // Vulkan has no filters without mipmapping, restrict sampling to the base level instead
+        let max_lod = match gltf_min_filter {
+            MinFilter::Nearest | MinFilter::Linear => 0.25,
+            _ => std::f32::MAX,
+        };
         let sampler = Sampler::new(
             device.clone(),
             gltf_sampler.mag_filter().unwrap_or(MagFilter::Linear).into_vulkan_equivalent(),
@@ -692,16 +1150,16 @@ pub fn create_samplers(device: &Arc<Device>, document: &Document) -> Result<Vec<
             SamplerAddressMode::Repeat,
             /* These parameters affect how interpolation between Mip levels is calculated.
              *
-             * We don't enforce any bounds on the resulting LOD value, `max_lod` is thus
-             * `std::f32::MAX`.
+             * We don't enforce any bounds on the resulting LOD value of mipmapped filters,
+             * `max_lod` is thus `std::f32::MAX`.
              *
              * See the following link for how LOD is calculated.
              * https://www.khronos.org/registry/vulkan/specs/1.1-extensions/html/vkspec.html#textures-level-of-detail-operation
              */
             0.0, // mip_lod_bias
-            1.0, // max_anisotropy
+            helper_resources.max_anisotropy, // max_anisotropy
             0.0, // min_lod
-            std::f32::MAX, // max_lod
+            max_lod, // max_lod
         )?;
 
         device_samplers.push(sampler);
@@ -710,16 +1168,67 @@ pub fn create_samplers(device: &Arc<Device>, document: &Document) -> Result<Vec<
     Ok(device_samplers)
 }
 
+fn texture_transform_from_info(texture_info: &gltf::texture::Info) -> TextureTransform {
+    let mut result = TextureTransform {
+        tex_coord: texture_info.tex_coord(),
+        ..Default::default()
+    };
+
+    if let Some(texture_transform) = texture_info.texture_transform() {
+        result.offset = texture_transform.offset().into();
+        result.rotation = texture_transform.rotation();
+        result.scale = texture_transform.scale().into();
+
+        if let Some(tex_coord) = texture_transform.tex_coord() {
+            result.tex_coord = tex_coord;
+        }
+    }
+
+    result
+}
+
+const KHR_TEXTURE_TRANSFORM: &str = "KHR_texture_transform";
+
+/// Reads the `KHR_texture_transform` of a texture info from the raw JSON, as the `gltf` crate
+/// only exposes it on `gltf::texture::Info`, not on normal and occlusion textures.
+fn texture_transform_from_raw_json(texture_info: &json::Value) -> TextureTransform {
+    let mut result = TextureTransform {
+        tex_coord: texture_info["texCoord"].as_u64().unwrap_or(0) as u32,
+        ..Default::default()
+    };
+    let texture_transform = &texture_info["extensions"][KHR_TEXTURE_TRANSFORM];
+    let read_vec2 = |value: &json::Value, default: [f32; 2]| {
+        match (value[0].as_f64(), value[1].as_f64()) {
+            (Some(x), Some(y)) => [x as f32, y as f32],
+            _ => default,
+        }
+    };
+
+    if texture_transform.is_object() {
+        result.offset = read_vec2(&texture_transform["offset"], [0.0, 0.0]).into();
+        result.rotation = texture_transform["rotation"].as_f64().unwrap_or(0.0) as f32;
+        result.scale = read_vec2(&texture_transform["scale"], [1.0, 1.0]).into();
+
+        if let Some(tex_coord) = texture_transform["texCoord"].as_u64() {
+            result.tex_coord = tex_coord as u32;
+        }
+    }
+
+    result
+}
+
 pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
                                            pipelines: impl IntoIterator<Item=&'a GltfGraphicsPipeline>,
                                            helper_resources: &HelperResources,
                                            document: &Document,
+                                           raw_json: &json::Value,
                                            device_images: &[Arc<dyn ImageViewAccess + Send + Sync>],
                                            initialization_tasks: &mut Vec<InitializationTask>)
-        -> Result<Vec<DescriptorSetMap>, Error> {
-    let device_samplers = create_samplers(device, &document)?;
+        -> Result<(Vec<DescriptorSetMap>, usize), Error> {
+    let device_samplers = create_samplers(device, helper_resources, &document)?;
     let pipelines: Vec<_> = pipelines.into_iter().map(Clone::clone).collect();
     let mut material_descriptor_set_maps: Vec<DescriptorSetMap> = Vec::with_capacity(document.materials().len());
+    let mut buffer_bytes = 0;
 
     for material in document.materials() {
         let pbr = material.pbr_metallic_roughness();
@@ -774,7 +1283,7 @@ pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
             .and_then(|texture_info| texture_info.texture().sampler().index())
             .map(|sampler_index| device_samplers[sampler_index].clone());
         let material_ubo = MaterialUBO::new(
-            material.alpha_cutoff(),
+            material.alpha_cutoff().unwrap_or(0.5),
             base_color_texture_option.is_some(),
             pbr.base_color_factor().into(),
             metallic_roughness_texture_option.is_some(),
@@ -785,6 +1294,24 @@ pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
             material.occlusion_texture().map(|occlusion_texture| occlusion_texture.strength()).unwrap_or(1.0),
             emissive_texture_option.is_some(),
             material.emissive_factor().into(),
+            pbr.base_color_texture()
+                .map(|texture_info| texture_transform_from_info(&texture_info))
+                .unwrap_or_default(),
+            pbr.metallic_roughness_texture()
+                .map(|texture_info| texture_transform_from_info(&texture_info))
+                .unwrap_or_default(),
+            material.index()
+                .filter(|_| material.normal_texture().is_some())
+                .map(|material_index| texture_transform_from_raw_json(&raw_json["materials"][material_index]["normalTexture"]))
+                .unwrap_or_default(),
+            material.index()
+                .filter(|_| material.occlusion_texture().is_some())
+                .map(|material_index| texture_transform_from_raw_json(&raw_json["materials"][material_index]["occlusionTexture"]))
+                .unwrap_or_default(),
+            material.emissive_texture()
+                .map(|texture_info| texture_transform_from_info(&texture_info))
+                .unwrap_or_default(),
+            material.unlit(),
         );
         let (device_material_ubo_buffer, material_ubo_buffer_initialization) = unsafe {
             ImmutableBuffer::<MaterialUBO>::uninitialized(
@@ -792,6 +1319,9 @@ pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
                 BufferUsage::uniform_buffer_transfer_destination(),
             )
         }?;
+
+        buffer_bytes += device_material_ubo_buffer.size();
+
         let base_color_texture: Arc<dyn ImageViewAccess + Send + Sync> = base_color_texture_option
             .unwrap_or_else(|| helper_resources.empty_image.clone());
         let base_color_sampler: Arc<Sampler> = base_color_sampler_option
@@ -814,7 +1344,7 @@ pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
             .unwrap_or_else(|| helper_resources.default_sampler.clone());
         let descriptor_set_map: DescriptorSetMap = DescriptorSetMap::custom(&pipelines[..], |pipeline|
             Arc::new(
-                PersistentDescriptorSet::start(pipeline.layout.clone(), 3)
+                PersistentDescriptorSet::start(pipeline.layout.clone(), 2)
                     .add_buffer(device_material_ubo_buffer.clone()).unwrap()
                     .add_image(base_color_texture.clone()).unwrap()
                     .add_sampler(base_color_sampler.clone()).unwrap()
@@ -837,57 +1367,864 @@ pub fn create_material_descriptor_sets<'a>(device: &Arc<Device>,
         material_descriptor_set_maps.push(descriptor_set_map);
     }
 
-    Ok(material_descriptor_set_maps)
+    Ok((material_descriptor_set_maps, buffer_bytes))
 }
 
-fn import_model<'a, I>(
-    device: &Arc<Device>,
-    queue_families: I,
-    pipeline_cache: &GraphicsPipelineSetCache,
-    helper_resources: &HelperResources,
-    document: Document,
-    buffer_data_array: Vec<gltf::buffer::Data>,
-    image_data_array: Vec<gltf::image::Data>,
-) -> Result<SimpleUninitializedResource<Model>, Error>
-where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
-    let mut initialization_tasks: Vec<InitializationTask> = Vec::with_capacity(
-        buffer_data_array.len() + image_data_array.len() + document.accessors().len() + document.nodes().len() + document.materials().len()
-    );
-    let pipelines = Model::get_pipelines_layouts(&document, pipeline_cache);
+/// The `len` bytes at `offset` within a buffer view, `None` if they exceed the buffer view.
+fn buffer_view_slice<'a>(root: &json::Root,
+                         buffer_data_array: &'a [gltf::buffer::Data],
+                         view_index: json::Index<json::buffer::View>,
+                         offset: usize,
+                         len: usize)
+        -> Option<&'a [u8]> {
+    let view = root.buffer_views.get(view_index.value())?;
+
+    if offset + len > view.byte_length as usize {
+        return None;
+    }
 
-    let converted_index_buffers_by_accessor_index = import_index_buffers_by_accessor_index(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
-    let (normal_buffers, normals) = precompute_missing_normal_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
-    let tangent_buffers = precompute_missing_tangent_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks, &normals[..])?;
-    let device_buffers = import_device_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
-    let device_images = import_device_images(device, &queue_families, helper_resources, &document, image_data_array, &mut initialization_tasks)?;
-    let (node_transform_matrices, node_descriptor_sets) = create_node_descriptor_sets(device, &pipelines[..], &document, &mut initialization_tasks)?;
-    let material_descriptor_sets = create_material_descriptor_sets(device, &pipelines[..], helper_resources, &document, &device_images[..], &mut initialization_tasks)?;
-    let scene_subpass_context_less_draw_calls = document.scenes().map(|_| arr![RwLock::new(None); 4]).collect();
+    let start = view.byte_offset.unwrap_or(0) as usize + offset;
 
-    Ok(SimpleUninitializedResource::new(Model {
-        document,
-        buffer_data: buffer_data_array,
-        device_buffers,
-        device_images,
-        converted_index_buffers_by_accessor_index,
-        normal_buffers,
-        tangent_buffers,
-        node_transform_matrices,
-        node_descriptor_sets,
-        material_descriptor_sets,
-        scene_subpass_context_less_draw_calls,
-    }, initialization_tasks))
+    buffer_data_array.get(view.buffer.value())?.get(start..(start + len))
 }
 
-pub fn import_model_path<'a, I>(
-    device: &Arc<Device>,
-    queue_families: I,
-    pipeline_cache: &GraphicsPipelineSetCache,
+/// Replaces sparse accessors and accessors without a buffer view with accessors referencing dense
+/// copies of their elements. The copies are stored in a new buffer, appended to
+/// `buffer_data_array`, so that the rest of the import can read all accessors through their
+/// buffer views. Elements of vertex attributes are padded to 4 bytes, as required by vertex
+/// buffers, elements of other accessors are tightly packed.
+fn materialize_sparse_accessors(document: Document, buffer_data_array: &mut Vec<gltf::buffer::Data>) -> Result<Document, Error> {
+    let mut vertex_attribute_accessors = vec![false; document.accessors().len()];
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            for (_, accessor) in primitive.attributes() {
+                vertex_attribute_accessors[accessor.index()] = true;
+            }
+        }
+    }
+
+    let mut root = document.into_json();
+    let materialized_buffer_index = root.buffers.len();
+    let mut materialized_data: Vec<u8> = Vec::new();
+    let mut materialized_accessor_count = 0;
+
+    for accessor_index in 0..root.accessors.len() {
+        let accessor = &root.accessors[accessor_index];
+
+        if accessor.buffer_view.is_some() && accessor.sparse.is_none() {
+            continue;
+        }
+
+        let element_size = match (&accessor.component_type, &accessor.type_) {
+            (Checked::Valid(component_type), Checked::Valid(type_)) => component_type.0.size() * type_.multiplicity(),
+            _ => return Err(ModelImportError::InvalidAccessorType { accessor_index }.into()),
+        };
+        let stride = if vertex_attribute_accessors[accessor_index] {
+            (element_size + 3) / 4 * 4
+        } else {
+            element_size
+        };
+        let element_count = accessor.count as usize;
+        // Elements of accessors without a buffer view are initialized to zeros
+        let mut elements = vec![0u8; stride * element_count];
+
+        if let Some(view_index) = accessor.buffer_view {
+            let view_stride = root.buffer_views.get(view_index.value())
+                .and_then(|view| view.byte_stride)
+                .map(|byte_stride| byte_stride as usize)
+                .unwrap_or(element_size);
+            let data_len = if element_count > 0 {
+                (element_count - 1) * view_stride + element_size
+            } else {
+                0
+            };
+            let data = buffer_view_slice(&root, &buffer_data_array[..], view_index, accessor.byte_offset as usize, data_len)
+                .ok_or(ModelImportError::AccessorOutOfBounds {
+                    accessor_index,
+                    view_index: view_index.value(),
+                })?;
+
+            for element_index in 0..element_count {
+                let source = &data[(element_index * view_stride)..(element_index * view_stride + element_size)];
+
+                elements[(element_index * stride)..(element_index * stride + element_size)].copy_from_slice(source);
+            }
+        }
+
+        if let Some(sparse) = accessor.sparse.as_ref() {
+            let sparse_count = sparse.count as usize;
+            let index_component_type = match sparse.indices.component_type {
+                Checked::Valid(json::accessor::IndexComponentType(component_type @ ComponentType::U8))
+                | Checked::Valid(json::accessor::IndexComponentType(component_type @ ComponentType::U16))
+                | Checked::Valid(json::accessor::IndexComponentType(component_type @ ComponentType::U32)) => component_type,
+                _ => return Err(ModelImportError::InvalidSparseIndexType { accessor_index }.into()),
+            };
+            let index_size = index_component_type.size();
+            let indices_data = buffer_view_slice(&root, &buffer_data_array[..], sparse.indices.buffer_view, sparse.indices.byte_offset as usize, sparse_count * index_size)
+                .ok_or(ModelImportError::AccessorOutOfBounds {
+                    accessor_index,
+                    view_index: sparse.indices.buffer_view.value(),
+                })?;
+            let values_data = buffer_view_slice(&root, &buffer_data_array[..], sparse.values.buffer_view, sparse.values.byte_offset as usize, sparse_count * element_size)
+                .ok_or(ModelImportError::AccessorOutOfBounds {
+                    accessor_index,
+                    view_index: sparse.values.buffer_view.value(),
+                })?;
+
+            for sparse_index in 0..sparse_count {
+                let element_index = match index_component_type {
+                    ComponentType::U8 => indices_data[sparse_index] as usize,
+                    ComponentType::U16 => LittleEndian::read_u16(&indices_data[(sparse_index * 2)..]) as usize,
+                    _ => LittleEndian::read_u32(&indices_data[(sparse_index * 4)..]) as usize,
+                };
+
+                if element_index >= element_count {
+                    return Err(ModelImportError::SparseIndexOutOfBounds {
+                        accessor_index,
+                        element_index,
+                        element_count,
+                    }.into());
+                }
+
+                let source = &values_data[(sparse_index * element_size)..((sparse_index + 1) * element_size)];
+
+                elements[(element_index * stride)..(element_index * stride + element_size)].copy_from_slice(source);
+            }
+        }
+
+        while materialized_data.len() % 4 != 0 {
+            materialized_data.push(0);
+        }
+
+        let byte_offset = materialized_data.len();
+
+        materialized_data.extend(elements);
+        root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(materialized_buffer_index as u32),
+            byte_length: (materialized_data.len() - byte_offset) as u32,
+            byte_offset: Some(byte_offset as u32),
+            byte_stride: if stride != element_size { Some(stride as u32) } else { None },
+            name: None,
+            target: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+
+        let view_index = root.buffer_views.len() - 1;
+        let accessor = &mut root.accessors[accessor_index];
+
+        accessor.buffer_view = Some(json::Index::new(view_index as u32));
+        accessor.byte_offset = 0;
+        accessor.sparse = None;
+        materialized_accessor_count += 1;
+    }
+
+    if materialized_accessor_count > 0 {
+        root.buffers.push(json::Buffer {
+            byte_length: materialized_data.len() as u32,
+            name: None,
+            uri: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+        buffer_data_array.push(gltf::buffer::Data(materialized_data));
+    }
+
+    Ok(Document::from_json(root)?)
+}
+
+/// Converts vertex attributes in formats the device cannot read vertex attributes in to `f32`
+/// components on the CPU. Like in `materialize_sparse_accessors`, the converted elements are
+/// stored in a new buffer appended to `buffer_data_array` and the accessors are redirected to
+/// them. Expects matrix accessors to have been rejected by `check_primitives`.
+fn expand_unsupported_vertex_attributes(document: Document,
+                                        buffer_data_array: &mut Vec<gltf::buffer::Data>,
+                                        physical_device: PhysicalDevice)
+        -> Result<Document, Error> {
+    let mut expanded_elements: Vec<Option<Vec<u8>>> = vec![None; document.accessors().len()];
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            for semantic in unconverted_vertex_attribute_semantics() {
+                let accessor = if let Some(accessor) = primitive.get(&semantic) {
+                    accessor
+                } else {
+                    continue;
+                };
+
+                if expanded_elements[accessor.index()].is_some() {
+                    continue;
+                }
+
+                let supported = match accessor.data_type() {
+                    // Integer formats cannot be read as floats by the shaders
+                    DataType::U32 => false,
+                    _ => get_vertex_attribute_format(accessor.data_type(), accessor.dimensions(), accessor.normalized())
+                        .map(|format| is_vertex_buffer_format_supported(physical_device, format))
+                        .unwrap_or(true),
+                };
+
+                if supported {
+                    continue;
+                }
+
+                let accessor_details = AccessorDetails::from(&buffer_data_array[..], accessor.clone());
+                let components = accessor.dimensions().multiplicity();
+                let mut elements = Vec::with_capacity(accessor.count() * components * mem::size_of::<f32>());
+
+                for item_index in 0..accessor.count() {
+                    for component_index in 0..components {
+                        elements.write_f32::<LittleEndian>(accessor_details.read_component_f32(item_index, component_index)).unwrap();
+                    }
+                }
+
+                expanded_elements[accessor.index()] = Some(elements);
+            }
+        }
+    }
+
+    if expanded_elements.iter().all(Option::is_none) {
+        return Ok(document);
+    }
+
+    let mut root = document.into_json();
+    let expanded_buffer_index = root.buffers.len();
+    let mut expanded_data: Vec<u8> = Vec::new();
+
+    for (accessor_index, elements) in expanded_elements.into_iter().enumerate() {
+        let elements = if let Some(elements) = elements {
+            elements
+        } else {
+            continue;
+        };
+        let byte_offset = expanded_data.len();
+
+        expanded_data.extend(elements);
+        root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(expanded_buffer_index as u32),
+            byte_length: (expanded_data.len() - byte_offset) as u32,
+            byte_offset: Some(byte_offset as u32),
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+
+        let view_index = root.buffer_views.len() - 1;
+        let accessor = &mut root.accessors[accessor_index];
+        let component_type = accessor.component_type.unwrap().0;
+        let normalized = accessor.normalized;
+        // The bounds of normalized accessors are specified in the integer range
+        let dequantize = |value: f64| match component_type {
+            ComponentType::I8 if normalized => (value / 127.0).max(-1.0),
+            ComponentType::U8 if normalized => value / 255.0,
+            ComponentType::I16 if normalized => (value / 32767.0).max(-1.0),
+            ComponentType::U16 if normalized => value / 65535.0,
+            _ => value,
+        };
+
+        for bounds in accessor.min.iter_mut().chain(accessor.max.iter_mut()) {
+            if let json::Value::Array(ref mut values) = *bounds {
+                for value in values.iter_mut() {
+                    if let Some(number) = value.as_f64() {
+                        *value = json::Value::from(dequantize(number));
+                    }
+                }
+            }
+        }
+
+        accessor.buffer_view = Some(json::Index::new(view_index as u32));
+        accessor.byte_offset = 0;
+        accessor.component_type = Checked::Valid(json::accessor::GenericComponentType(ComponentType::F32));
+        accessor.normalized = false;
+    }
+
+    root.buffers.push(json::Buffer {
+        byte_length: expanded_data.len() as u32,
+        name: None,
+        uri: None,
+        extensions: Default::default(),
+        extras: Default::default(),
+    });
+    buffer_data_array.push(gltf::buffer::Data(expanded_data));
+
+    Ok(Document::from_json(root)?)
+}
+
+/// Extensions which change how buffer or image data is interpreted. Models requiring these cannot
+/// be rendered correctly without decoding the data first.
+/// Models which only use `KHR_texture_basisu` optionally fall back to the texture's regular
+/// `source` image.
+// TODO: Decode compressed buffer views and primitives before they are uploaded
+// TODO: Transcode KTX2 images to a supported BCn/ASTC format and upload their mip chains
+const UNSUPPORTED_REQUIRED_EXTENSIONS: [&str; 3] = [
+    "EXT_meshopt_compression",
+    "KHR_draco_mesh_compression",
+    "KHR_texture_basisu",
+];
+
+/// Reads `extensionsRequired` from the unvalidated JSON, because textures requiring
+/// `KHR_texture_basisu` may omit their `source`, which fails the validation of the document.
+fn check_required_extensions(raw_json: &json::Value) -> Result<(), ModelImportError> {
+    let extensions_required = raw_json["extensionsRequired"].as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(json::Value::as_str);
+
+    for extension in extensions_required {
+        if UNSUPPORTED_REQUIRED_EXTENSIONS.contains(&extension) {
+            return Err(ModelImportError::UnsupportedExtension {
+                name: extension.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// MIME types and file extensions of images with float texels, which the `gltf` crate cannot
+/// decode.
+// TODO: Decode float images and upload them as R32G32B32A32Sfloat textures
+const FLOAT_IMAGE_ENCODINGS: [(&str, &str); 2] = [
+    ("image/vnd.radiance", "hdr"),
+    ("image/x-exr", "exr"),
+];
+
+/// Rejects models with float images up front, as the `gltf` crate would fail to decode them
+/// without reporting which image is affected. The encoding is read from the unvalidated JSON, from
+/// the MIME type of the image, of its data URI or from the extension of its file URI.
+fn check_float_images(raw_json: &json::Value) -> Result<(), ModelImportError> {
+    let images = raw_json["images"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+
+    for (image_index, image) in images.iter().enumerate() {
+        let mime_type = image["mimeType"].as_str();
+        let uri = image["uri"].as_str().unwrap_or("");
+        let (data_uri_mime_type, extension) = if uri.starts_with("data:") {
+            (uri["data:".len()..].split(&[';', ','][..]).next(), None)
+        } else {
+            (None, uri.rsplit('.').next().map(str::to_lowercase))
+        };
+        let float_encoding = FLOAT_IMAGE_ENCODINGS.iter().find(|&&(float_mime_type, float_extension)| {
+            mime_type == Some(float_mime_type)
+                || data_uri_mime_type == Some(float_mime_type)
+                || extension.as_deref() == Some(float_extension)
+        });
+
+        if let Some(&(float_mime_type, _)) = float_encoding {
+            return Err(ModelImportError::UnsupportedFloatImage {
+                image_index,
+                mime_type: float_mime_type.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_buffer_views(document: &Document, buffer_data_array: &[gltf::buffer::Data]) -> Result<(), ModelImportError> {
+    for view in document.views() {
+        let buffer_index = view.buffer().index();
+        let buffer_len = buffer_data_array.get(buffer_index)
+            .map(|buffer_data| buffer_data.len())
+            .unwrap_or(0);
+
+        if view.offset() + view.length() > buffer_len {
+            return Err(ModelImportError::BufferViewOutOfBounds {
+                view_index: view.index(),
+                buffer_index,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Accessors without a buffer view are skipped, their elements are zeros.
+fn check_accessors(document: &Document) -> Result<(), ModelImportError> {
+    for accessor in document.accessors() {
+        let view = if let Some(view) = accessor.view() {
+            view
+        } else {
+            continue;
+        };
+        let stride = view.stride().unwrap_or_else(|| accessor.size());
+
+        if accessor.offset() + get_accessor_byte_len(&accessor, stride) > view.length() {
+            return Err(ModelImportError::AccessorOutOfBounds {
+                accessor_index: accessor.index(),
+                view_index: view.index(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_primitives(document: &Document) -> Result<(), ModelImportError> {
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            let vertex_count = if let Some(position_accessor) = primitive.get(&Semantic::Positions) {
+                position_accessor.count()
+            } else {
+                return Err(ModelImportError::MissingPositions {
+                    mesh_index: mesh.index(),
+                    primitive_index: primitive.index(),
+                });
+            };
+            let vertex_accessors = primitive.attributes()
+                .map(|(_, accessor)| accessor)
+                .chain(primitive.morph_targets().flat_map(|target| {
+                    target.positions().into_iter()
+                        .chain(target.normals())
+                        .chain(target.tangents())
+                }));
+
+            for accessor in vertex_accessors {
+                if accessor.count() != vertex_count {
+                    return Err(ModelImportError::VertexCountMismatch {
+                        mesh_index: mesh.index(),
+                        primitive_index: primitive.index(),
+                        accessor_index: accessor.index(),
+                        count: accessor.count(),
+                        vertex_count,
+                    });
+                }
+            }
+
+            for semantic in unconverted_vertex_attribute_semantics() {
+                if let Some(accessor) = primitive.get(&semantic) {
+                    if let Dimensions::Mat2 | Dimensions::Mat3 | Dimensions::Mat4 = accessor.dimensions() {
+                        return Err(ModelImportError::UnsupportedVertexAttribute {
+                            mesh_index: mesh.index(),
+                            primitive_index: primitive.index(),
+                            accessor_index: accessor.index(),
+                            dimensions: accessor.dimensions(),
+                        });
+                    }
+                }
+            }
+
+            if let Some(index_accessor) = primitive.indices() {
+                match index_accessor.data_type() {
+                    DataType::U8 | DataType::U16 | DataType::U32 => (),
+                    data_type => return Err(ModelImportError::UnsupportedIndexType {
+                        mesh_index: mesh.index(),
+                        primitive_index: primitive.index(),
+                        accessor_index: index_accessor.index(),
+                        data_type,
+                    }),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensures each keyframe has an output value for each animated component.
+fn check_animations(document: &Document) -> Result<(), ModelImportError> {
+    for animation in document.animations() {
+        for (channel_index, channel) in animation.channels().enumerate() {
+            let sampler = channel.sampler();
+            let keyframe_count = sampler.input().count();
+            let output_value_count = sampler.output().count() * sampler.output().dimensions().multiplicity();
+            let values_per_keyframe = match sampler.interpolation() {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            let valid = match channel.target().property() {
+                Property::Translation | Property::Scale => output_value_count == keyframe_count * values_per_keyframe * 3,
+                Property::Rotation => output_value_count == keyframe_count * values_per_keyframe * 4,
+                // The number of morph targets is not known from the sampler alone
+                Property::MorphTargetWeights => output_value_count % (keyframe_count * values_per_keyframe).max(1) == 0,
+            };
+
+            if !valid {
+                return Err(ModelImportError::AnimationOutputCountMismatch {
+                    animation_index: animation.index(),
+                    channel_index,
+                    keyframe_count,
+                    output_value_count,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensures each joint of a skin has an inverse bind matrix, if they are specified.
+fn check_skins(document: &Document) -> Result<(), ModelImportError> {
+    for skin in document.skins() {
+        if let Some(accessor) = skin.inverse_bind_matrices() {
+            let joint_count = skin.joints().count();
+
+            if accessor.count() != joint_count {
+                return Err(ModelImportError::InverseBindMatrixCountMismatch {
+                    skin_index: skin.index(),
+                    count: accessor.count(),
+                    joint_count,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensures the joints of skinned primitives reference existing joints of each skin their mesh is
+/// used with.
+fn check_joint_indices(document: &Document, buffer_data_array: &[gltf::buffer::Data]) -> Result<(), ModelImportError> {
+    for node in document.nodes() {
+        let (mesh, skin) = match (node.mesh(), node.skin()) {
+            (Some(mesh), Some(skin)) => (mesh, skin),
+            _ => continue,
+        };
+        let joint_count = skin.joints().count();
+
+        for primitive in mesh.primitives() {
+            let joints_accessor = if let Some(joints_accessor) = primitive.get(&Semantic::Joints(0)) {
+                joints_accessor
+            } else {
+                continue;
+            };
+            let joints_accessor_details = AccessorDetails::from(buffer_data_array, joints_accessor);
+            let component_count = joints_accessor_details.accessor.dimensions().multiplicity();
+            let joint_indices = (0..joints_accessor_details.accessor.count())
+                .flat_map(|item_index| (0..component_count).map(move |component_index| (item_index, component_index)))
+                .map(|(item_index, component_index)| joints_accessor_details.read_component_u32(item_index, component_index) as usize);
+
+            for joint_index in joint_indices {
+                if joint_index >= joint_count {
+                    return Err(ModelImportError::JointIndexOutOfBounds {
+                        mesh_index: mesh.index(),
+                        primitive_index: primitive.index(),
+                        skin_index: skin.index(),
+                        joint_index,
+                        joint_count,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensures the indices of indexed primitives reference existing vertices.
+fn check_vertex_indices(document: &Document, buffer_data_array: &[gltf::buffer::Data]) -> Result<(), ModelImportError> {
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            if primitive.indices().is_none() {
+                continue;
+            }
+
+            let vertex_count = primitive.get(&Semantic::Positions)
+                .map(|position_accessor| position_accessor.count())
+                .unwrap_or(0);
+
+            if let Some(&vertex_index) = read_primitive_vertex_indices(buffer_data_array, &primitive).iter()
+                    .find(|&&vertex_index| vertex_index >= vertex_count) {
+                return Err(ModelImportError::VertexIndexOutOfBounds {
+                    mesh_index: mesh.index(),
+                    primitive_index: primitive.index(),
+                    vertex_index,
+                    vertex_count,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_images(image_data_array: &[gltf::image::Data]) -> Result<(), ModelImportError> {
+    for (image_index, image_data) in image_data_array.iter().enumerate() {
+        if image_data.width == 0 || image_data.height == 0 {
+            return Err(ModelImportError::InvalidImageDimensions {
+                image_index,
+                width: image_data.width,
+                height: image_data.height,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the JSON of a glTF or GLB file. Used to read extensions which are not supported by the
+/// `gltf` crate.
+fn parse_raw_json(slice: &[u8]) -> Result<json::Value, ModelImportError> {
+    let json_slice = if slice.starts_with(b"glTF") {
+        gltf::Glb::from_slice(slice)?.json
+    } else {
+        Cow::Borrowed(slice)
+    };
+
+    json::deserialize::from_slice(&json_slice)
+        .map_err(|error| gltf::Error::Deserialize(error).into())
+}
+
+/// The MIME type of an encoded image, guessed from its signature or from the extension of its URI.
+fn guess_image_mime_type(encoded_image: &[u8], uri: &str) -> Option<&'static str> {
+    if encoded_image.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if encoded_image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else {
+        match uri.rsplit('.').next() {
+            Some("png") => Some("image/png"),
+            Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+            _ => None,
+        }
+    }
+}
+
+/// Moves the images referenced by file URIs into a new buffer, so that the model does not
+/// depend on the files once imported, e.g. when exported. Images in data URIs are
+/// self-contained and kept as they are, images with unsupported URIs are left for the `gltf`
+/// crate to report.
+fn embed_image_files(document: Document, base: &Path, buffer_data_array: &mut Vec<gltf::buffer::Data>) -> Result<Document, ModelImportError> {
+    let mut root = document.into_json();
+    let embedded_buffer_index = root.buffers.len();
+    let mut embedded_data: Vec<u8> = Vec::new();
+
+    for image_index in 0..root.images.len() {
+        let uri = match root.images[image_index].uri {
+            Some(ref uri) => uri.clone(),
+            None => continue,
+        };
+        let path = if uri.starts_with("file://") {
+            PathBuf::from(&uri["file://".len()..])
+        } else if uri.starts_with("file:") {
+            PathBuf::from(&uri["file:".len()..])
+        } else if !uri.contains(':') {
+            base.join(&uri)
+        } else {
+            continue;
+        };
+        let encoded_image = fs::read(&path).map_err(gltf::Error::Io)?;
+        let mime_type = match root.images[image_index].mime_type {
+            Some(ref mime_type) => mime_type.0.clone(),
+            None => match guess_image_mime_type(&encoded_image[..], &uri) {
+                Some(mime_type) => mime_type.to_string(),
+                None => continue,
+            },
+        };
+
+        while embedded_data.len() % 4 != 0 {
+            embedded_data.push(0);
+        }
+
+        let byte_offset = embedded_data.len();
+
+        embedded_data.extend(encoded_image);
+        root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(embedded_buffer_index as u32),
+            byte_length: (embedded_data.len() - byte_offset) as u32,
+            byte_offset: Some(byte_offset as u32),
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+
+        let image = &mut root.images[image_index];
+
+        image.buffer_view = Some(json::Index::new((root.buffer_views.len() - 1) as u32));
+        image.mime_type = Some(json::image::MimeType(mime_type));
+        image.uri = None;
+    }
+
+    if embedded_data.is_empty() {
+        return Ok(Document::from_json(root)?);
+    }
+
+    while embedded_data.len() % 4 != 0 {
+        embedded_data.push(0);
+    }
+
+    root.buffers.push(json::Buffer {
+        byte_length: embedded_data.len() as u32,
+        name: None,
+        uri: None,
+        extensions: Default::default(),
+        extras: Default::default(),
+    });
+    buffer_data_array.push(gltf::buffer::Data(embedded_data));
+
+    Ok(Document::from_json(root)?)
+}
+
+/// Reads a glTF or GLB slice along with the buffers and images it references, and its raw JSON,
+/// see `parse_raw_json`. The buffers and images are read by `import`. When the relative URIs of
+/// the slice can be resolved against `base`, the image files they reference are embedded, see
+/// `embed_image_files`. Models requiring unsupported extensions or with float images are rejected
+/// before any of their data is read, see `check_required_extensions` and `check_float_images`.
+/// `progress` is called with the fraction of the work done after each step.
+fn import_gltf(
+    slice: &[u8],
+    base: Option<&Path>,
+    import: impl FnOnce() -> Result<(Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>), gltf::Error>,
+    progress: &mut dyn FnMut(f32),
+) -> Result<(Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>, json::Value), ModelImportError> {
+    let raw_json = parse_raw_json(slice)?;
+
+    check_required_extensions(&raw_json)?;
+    check_float_images(&raw_json)?;
+
+    progress(0.1);
+
+    let (document, mut buffer_data_array, image_data_array) = import()?;
+
+    progress(0.9);
+
+    let document = if let Some(base) = base {
+        embed_image_files(document, base, &mut buffer_data_array)?
+    } else {
+        document
+    };
+
+    progress(1.0);
+
+    Ok((document, buffer_data_array, image_data_array, raw_json))
+}
+
+/// Reads a glTF or GLB file, see `import_gltf`.
+pub(crate) fn import_gltf_path(path: &Path, progress: &mut dyn FnMut(f32))
+        -> Result<(Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>, json::Value), ModelImportError> {
+    let slice = fs::read(path).map_err(gltf::Error::Io)?;
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+
+    import_gltf(&slice[..], Some(base), || gltf::import(path), progress)
+}
+
+/// Reads a glTF or GLB slice, see `import_gltf`. Only buffers in data URIs or the binary chunk and
+/// images in buffer views can be read.
+pub(crate) fn import_gltf_slice(slice: &[u8], progress: &mut dyn FnMut(f32))
+        -> Result<(Document, Vec<gltf::buffer::Data>, Vec<gltf::image::Data>, json::Value), ModelImportError> {
+    import_gltf(slice, None, || gltf::import_slice(slice), progress)
+}
+
+/// Validates the model and creates its device resources. `progress` is called with the fraction
+/// of the work done after each step.
+pub(crate) fn import_model<'a, I>(
+    device: &Arc<Device>,
+    queue_families: I,
+    pipeline_cache: &GraphicsPipelineSetCache,
+    helper_resources: &HelperResources,
+    document: Document,
+    mut buffer_data_array: Vec<gltf::buffer::Data>,
+    image_data_array: Vec<gltf::image::Data>,
+    raw_json: &json::Value,
+    progress: &mut dyn FnMut(f32),
+) -> Result<SimpleUninitializedResource<Model>, ModelImportError>
+where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+    check_buffer_views(&document, &buffer_data_array[..])?;
+    check_accessors(&document)?;
+    check_primitives(&document)?;
+    check_animations(&document)?;
+    check_skins(&document)?;
+    check_images(&image_data_array[..])?;
+
+    let document = materialize_sparse_accessors(document, &mut buffer_data_array)?;
+
+    // Index accessors may be sparse, so their indices are checked once materialized
+    check_vertex_indices(&document, &buffer_data_array[..])?;
+    check_joint_indices(&document, &buffer_data_array[..])?;
+
+    let document = expand_unsupported_vertex_attributes(document, &mut buffer_data_array, device.physical_device())?;
+
+    progress(0.1);
+
+    let mut initialization_tasks: Vec<InitializationTask> = Vec::with_capacity(
+        buffer_data_array.len() + image_data_array.len() + document.accessors().len() + document.nodes().len() + document.materials().len()
+    );
+    // Keeps the pipelines from being evicted from the cache while the model exists
+    let pipeline_usage = pipeline_cache.use_pipelines(document.meshes().flat_map(|mesh| {
+        mesh.primitives()
+            .map(|primitive| GraphicsPipelineProperties::from(&primitive, &primitive.material()))
+            .collect::<Vec<_>>()
+    }));
+    let pipelines = Model::get_pipelines_layouts(&document, pipeline_cache);
+
+    let converted_index_buffers_by_accessor_index = import_index_buffers_by_accessor_index(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+    let line_loop_index_buffers = precompute_line_loop_index_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+    let (normal_buffers, normals) = precompute_missing_normal_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+    let tangent_buffers = precompute_missing_tangent_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks, &normals[..])?;
+
+    progress(0.4);
+
+    let (joint_buffers, weight_buffers) = precompute_skinning_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+    let skin_inverse_bind_matrices = import_skin_inverse_bind_matrices(&document, &buffer_data_array[..]);
+    let animations = import_animations(&document, &buffer_data_array[..]);
+    let node_instance_matrices = import_node_instance_matrices(&document, &buffer_data_array[..], raw_json)?;
+    let node_instancing_extensions = import_node_instancing_extensions(&document, raw_json);
+    let primitive_bounds = compute_mesh_primitive_bounds(&document, &buffer_data_array[..]);
+    let primitive_bvhs = document.meshes()
+        .map(|mesh| mesh.primitives().map(|_| RwLock::new(None)).collect())
+        .collect();
+    let scene_mesh_instance_bvhs = document.scenes().map(|_| RwLock::new(None)).collect();
+
+    progress(0.5);
+
+    let (morph_target_buffers, morph_target_offsets) = precompute_morph_target_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+    let device_buffers = import_device_buffers(device, &queue_families, &document, &buffer_data_array[..], &mut initialization_tasks)?;
+
+    progress(0.6);
+
+    let (device_images, device_image_byte_lens) = import_device_images(device, &queue_families, helper_resources, &document, image_data_array, &mut initialization_tasks)?;
+
+    progress(0.9);
+
+    let (node_transform_matrices, node_descriptor_sets, node_buffer_bytes) = create_node_descriptor_sets(device, &queue_families, &pipelines[..], helper_resources, &document, &skin_inverse_bind_matrices[..], &morph_target_buffers[..], &mut initialization_tasks)?;
+    let (material_descriptor_sets, material_buffer_bytes) = create_material_descriptor_sets(device, &pipelines[..], helper_resources, &document, raw_json, &device_images[..], &mut initialization_tasks)?;
+    let scene_subpass_context_less_draw_calls = document.scenes().map(|_| arr![RwLock::new(None); 4]).collect();
+
+    progress(1.0);
+
+    Ok(SimpleUninitializedResource::new(Model {
+        document,
+        buffer_data: buffer_data_array,
+        device_buffers,
+        device_images,
+        device_image_byte_lens,
+        converted_index_buffers_by_accessor_index,
+        line_loop_index_buffers,
+        normal_buffers,
+        tangent_buffers,
+        joint_buffers,
+        weight_buffers,
+        skin_inverse_bind_matrices,
+        animations,
+        node_instance_matrices,
+        node_instancing_extensions,
+        primitive_bounds,
+        primitive_bvhs,
+        scene_mesh_instance_bvhs,
+        morph_target_buffers,
+        morph_target_offsets,
+        node_transform_matrices,
+        node_descriptor_sets,
+        material_descriptor_sets,
+        descriptor_set_buffer_bytes: node_buffer_bytes + material_buffer_bytes,
+        _pipeline_usage: pipeline_usage,
+        scene_subpass_context_less_draw_calls,
+    }, initialization_tasks))
+}
+
+pub fn import_model_path<'a, I>(
+    device: &Arc<Device>,
+    queue_families: I,
+    pipeline_cache: &GraphicsPipelineSetCache,
     helper_resources: &HelperResources,
     path: impl AsRef<Path>,
-) -> Result<SimpleUninitializedResource<Model>, Error>
+) -> Result<SimpleUninitializedResource<Model>, ModelImportError>
 where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
-    let (document, buffer_data_array, image_data_array) = gltf::import(path)?;
+    let (document, buffer_data_array, image_data_array, raw_json) = import_gltf_path(path.as_ref(), &mut |_| ())?;
     import_model::<I>(
         device,
         queue_families,
@@ -896,6 +2233,8 @@ where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
         document,
         buffer_data_array,
         image_data_array,
+        &raw_json,
+        &mut |_| (),
     )
 }
 
@@ -905,9 +2244,9 @@ pub fn import_model_slice<'a, I>(
     pipeline_cache: &GraphicsPipelineSetCache,
     helper_resources: &HelperResources,
     slice: impl AsRef<[u8]>,
-) -> Result<SimpleUninitializedResource<Model>, Error>
+) -> Result<SimpleUninitializedResource<Model>, ModelImportError>
 where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
-    let (document, buffer_data_array, image_data_array) = gltf::import_slice(slice)?;
+    let (document, buffer_data_array, image_data_array, raw_json) = import_gltf_slice(slice.as_ref(), &mut |_| ())?;
     import_model::<I>(
         device,
         queue_families,
@@ -916,5 +2255,377 @@ where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
         document,
         buffer_data_array,
         image_data_array,
+        &raw_json,
+        &mut |_| (),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_texels(values: &[u16]) -> Vec<u8> {
+        let mut result = vec![0; values.len() * 2];
+
+        NativeEndian::write_u16_into(values, &mut result[..]);
+        result
+    }
+
+    fn convert_u16(values: &[u16], channels: usize, srgb: bool) -> Vec<u16> {
+        let converted: Vec<u8> = convert_u16_texels(&u16_texels(values)[..], channels, srgb).collect();
+        let mut result = vec![0; converted.len() / 2];
+
+        NativeEndian::read_u16_into(&converted[..], &mut result[..]);
+        result
+    }
+
+    #[test]
+    fn convert_u16_texels_rgb_to_rgba() {
+        assert_eq!(convert_u16(&[1, 2, 3, 4, 5, 6], 3, false), vec![1, 2, 3, 65535, 4, 5, 6, 65535]);
+        assert_eq!(convert_u16_texels(&u16_texels(&[1, 2, 3])[..], 3, false).len(), 8);
+    }
+
+    #[test]
+    fn convert_u16_texels_srgb() {
+        let half = 32768;
+        let half_linear = (srgb_to_linear(half as f32 / 65535.0) * 65535.0).round() as u16;
+
+        // The alpha channel of grayscale-alpha and RGBA texels is linear
+        assert_eq!(convert_u16(&[half, half], 2, true), vec![half_linear, half]);
+        assert_eq!(convert_u16(&[0, half, 65535, half], 4, true), vec![0, half_linear, 65535, half]);
+        assert_eq!(convert_u16(&[half], 1, true), vec![half_linear]);
+        assert_eq!(convert_u16(&[half, half, half], 3, true), vec![half_linear, half_linear, half_linear, 65535]);
+        assert!(half_linear > 14000 && half_linear < 14100);
+    }
+
+    #[test]
+    fn convert_u8_texels_rgb_to_rgba() {
+        let converted: Vec<u8> = convert_double_channel_to_triple_channel(&[1, 2, 3, 4, 5, 6]).collect();
+
+        assert_eq!(converted, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+
+    /// Two buffer views over a single buffer of 16 bytes, followed by the accessors, which
+    /// reference them by index. The first accessor is used as the positions of a mesh, if
+    /// `with_mesh` is set.
+    fn document(accessors: &str, with_mesh: bool) -> Document {
+        let meshes = if with_mesh {
+            r#", "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }]"#
+        } else {
+            ""
+        };
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "byteLength": 16 }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 8 }},
+                {{ "buffer": 0, "byteOffset": 8, "byteLength": 8 }}
+            ],
+            "accessors": [{}]{}
+        }}"#, accessors, meshes);
+
+        Document::from_json(json::deserialize::from_str(&json).unwrap()).unwrap()
+    }
+
+    fn buffer_data() -> Vec<gltf::buffer::Data> {
+        vec![gltf::buffer::Data(vec![
+            // View 0: sparse indices, `u16`s
+            1, 0, 3, 0, 0, 0, 0, 0,
+            // View 1: sparse values, or base elements
+            10, 20, 30, 40, 50, 60, 70, 80,
+        ])]
+    }
+
+    /// The stride and the elements of a materialized accessor
+    fn materialized_elements(document: &Document, buffer_data_array: &[gltf::buffer::Data], accessor_index: usize) -> (usize, Vec<u8>) {
+        let accessor = document.accessors().nth(accessor_index).unwrap();
+        let view = accessor.view().unwrap();
+        let stride = view.stride().unwrap_or_else(|| accessor.size());
+        let offset = view.offset() + accessor.offset();
+        let data = &buffer_data_array[view.buffer().index()][offset..(offset + stride * accessor.count())];
+
+        (stride, data.to_vec())
+    }
+
+    #[test]
+    fn materialize_sparse_with_base_view() {
+        let document = document(r#"{
+            "bufferView": 1, "componentType": 5121, "count": 4, "type": "SCALAR",
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5123 },
+                "values": { "bufferView": 1, "byteOffset": 6 }
+            }
+        }"#, false);
+        let mut buffer_data_array = buffer_data();
+        let document = materialize_sparse_accessors(document, &mut buffer_data_array).unwrap();
+
+        assert!(document.accessors().next().unwrap().sparse().is_none());
+        assert_eq!(materialized_elements(&document, &buffer_data_array[..], 0), (1, vec![10, 70, 30, 80]));
+    }
+
+    #[test]
+    fn materialize_without_view() {
+        let document = document(r#"{
+            "componentType": 5121, "count": 4, "type": "SCALAR",
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5123 },
+                "values": { "bufferView": 1 }
+            }
+        }"#, false);
+        let mut buffer_data_array = buffer_data();
+        let document = materialize_sparse_accessors(document, &mut buffer_data_array).unwrap();
+
+        assert_eq!(materialized_elements(&document, &buffer_data_array[..], 0), (1, vec![0, 10, 0, 20]));
+    }
+
+    #[test]
+    fn materialize_pads_vertex_attributes() {
+        let document = document(r#"{
+            "componentType": 5121, "normalized": true, "count": 4, "type": "VEC3",
+            "min": [0, 0, 0], "max": [255, 255, 255],
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5123 },
+                "values": { "bufferView": 1 }
+            }
+        }"#, true);
+        let mut buffer_data_array = buffer_data();
+        let document = materialize_sparse_accessors(document, &mut buffer_data_array).unwrap();
+
+        assert_eq!(materialized_elements(&document, &buffer_data_array[..], 0), (4, vec![
+            0, 0, 0, 0,
+            10, 20, 30, 0,
+            0, 0, 0, 0,
+            40, 50, 60, 0,
+        ]));
+    }
+
+    #[test]
+    fn materialize_sparse_index_out_of_bounds() {
+        let document = document(r#"{
+            "componentType": 5121, "count": 2, "type": "SCALAR",
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5123 },
+                "values": { "bufferView": 1 }
+            }
+        }"#, false);
+        let error = materialize_sparse_accessors(document, &mut buffer_data()).unwrap_err();
+
+        match ModelImportError::from(error) {
+            ModelImportError::SparseIndexOutOfBounds { accessor_index: 0, element_index: 3, element_count: 2 } => (),
+            error => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn materialize_sparse_values_out_of_bounds() {
+        let document = document(r#"{
+            "componentType": 5126, "count": 4, "type": "VEC2",
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5123 },
+                "values": { "bufferView": 1 }
+            }
+        }"#, false);
+        let error = materialize_sparse_accessors(document, &mut buffer_data()).unwrap_err();
+
+        match ModelImportError::from(error) {
+            ModelImportError::AccessorOutOfBounds { accessor_index: 0, view_index: 1 } => (),
+            error => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    /// A translation animated by linear keyframes, with the output accessor of the given count
+    fn animation_document(output_count: usize) -> Document {
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "nodes": [{{}}],
+            "buffers": [{{ "byteLength": 40 }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 16 }},
+                {{ "buffer": 0, "byteOffset": 16, "byteLength": 24 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR" }},
+                {{ "bufferView": 1, "componentType": 5126, "count": {}, "type": "VEC3" }}
+            ],
+            "animations": [{{
+                "channels": [{{ "sampler": 0, "target": {{ "node": 0, "path": "translation" }} }}],
+                "samplers": [{{ "input": 0, "output": 1 }}]
+            }}]
+        }}"#, output_count);
+
+        Document::from_json(json::deserialize::from_str(&json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn check_animation_output_counts() {
+        assert!(check_animations(&animation_document(2)).is_ok());
+
+        match check_animations(&animation_document(1)) {
+            Err(ModelImportError::AnimationOutputCountMismatch { animation_index: 0, channel_index: 0, keyframe_count: 2, output_value_count: 3 }) => (),
+            result => panic!("Unexpected result: {:?}", result.err()),
+        }
+    }
+
+    /// A skinned node with two joints, whose mesh has four vertices. Their joints are the `u8`s
+    /// at bytes 48 to 64 of the buffer. The skin has `inverse_bind_matrix_count` inverse bind
+    /// matrices.
+    fn skin_document(inverse_bind_matrix_count: usize) -> Document {
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "nodes": [{{ "mesh": 0, "skin": 0 }}, {{}}, {{}}],
+            "skins": [{{ "joints": [1, 2], "inverseBindMatrices": 2 }}],
+            "buffers": [{{ "byteLength": 192 }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+                {{ "buffer": 0, "byteOffset": 48, "byteLength": 16 }},
+                {{ "buffer": 0, "byteOffset": 64, "byteLength": 128 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [0, 0, 0] }},
+                {{ "bufferView": 1, "componentType": 5121, "count": 4, "type": "VEC4" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": {}, "type": "MAT4" }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "JOINTS_0": 1 }} }}] }}]
+        }}"#, inverse_bind_matrix_count);
+
+        Document::from_json(json::deserialize::from_str(&json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn check_inverse_bind_matrix_counts() {
+        assert!(check_skins(&skin_document(2)).is_ok());
+
+        match check_skins(&skin_document(1)) {
+            Err(ModelImportError::InverseBindMatrixCountMismatch { skin_index: 0, count: 1, joint_count: 2 }) => (),
+            result => panic!("Unexpected result: {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn check_joint_indices_out_of_bounds() {
+        let mut buffer = vec![0; 192];
+
+        buffer[48..64].copy_from_slice(&[0, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+
+        assert!(check_joint_indices(&skin_document(2), &[gltf::buffer::Data(buffer.clone())]).is_ok());
+
+        buffer[48 + 9] = 2;
+
+        match check_joint_indices(&skin_document(2), &[gltf::buffer::Data(buffer)]) {
+            Err(ModelImportError::JointIndexOutOfBounds { mesh_index: 0, primitive_index: 0, skin_index: 0, joint_index: 2, joint_count: 2 }) => (),
+            result => panic!("Unexpected result: {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn embed_image_files_into_buffer() {
+        let base = std::env::temp_dir().join("ammolite_embed_image_files");
+        let encoded_image = b"\x89PNG\r\n\x1a\nimage";
+
+        fs::create_dir_all(&base).unwrap();
+        fs::write(base.join("image.png"), &encoded_image[..]).unwrap();
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4 }],
+            "images": [
+                { "uri": "image.png" },
+                { "uri": "data:image/png;base64,iVBORw0KGgo=" }
+            ]
+        }"#;
+        let document = Document::from_json(json::deserialize::from_str(json).unwrap()).unwrap();
+        let mut buffer_data_array = vec![gltf::buffer::Data(vec![0; 4])];
+        let document = embed_image_files(document, &base, &mut buffer_data_array).unwrap();
+        let images: Vec<_> = document.images().collect();
+
+        match images[0].source() {
+            gltf::image::Source::View { view, mime_type } => {
+                assert_eq!(mime_type, "image/png");
+                assert_eq!(view.buffer().index(), 1);
+                assert_eq!(&buffer_data_array[1][view.offset()..(view.offset() + view.length())], &encoded_image[..]);
+            },
+            _ => panic!("The image file was not embedded."),
+        }
+
+        match images[1].source() {
+            gltf::image::Source::Uri { uri, .. } => assert!(uri.starts_with("data:")),
+            _ => panic!("The data URI image was embedded."),
+        }
+
+        assert_eq!(buffer_data_array[1].len() % 4, 0);
+    }
+
+    fn check_image_json(image: &str) -> Result<(), ModelImportError> {
+        let json = format!(r#"{{ "images": [{{ "uri": "image.png" }}, {}] }}"#, image);
+
+        check_float_images(&json::deserialize::from_str(&json).unwrap())
+    }
+
+    #[test]
+    fn reject_float_images() {
+        let float_images = [
+            r#"{ "uri": "sky.hdr" }"#,
+            r#"{ "uri": "textures/SKY.HDR" }"#,
+            r#"{ "uri": "data:image/vnd.radiance;base64,AAAA" }"#,
+            r#"{ "bufferView": 0, "mimeType": "image/vnd.radiance" }"#,
+        ];
+
+        for image in &float_images {
+            match check_image_json(image) {
+                Err(ModelImportError::UnsupportedFloatImage { image_index: 1, ref mime_type }) if mime_type == "image/vnd.radiance" => (),
+                result => panic!("The float image {} was not rejected: {:?}", image, result.err()),
+            }
+        }
+
+        match check_image_json(r#"{ "uri": "depth.exr" }"#) {
+            Err(ModelImportError::UnsupportedFloatImage { image_index: 1, ref mime_type }) if mime_type == "image/x-exr" => (),
+            result => panic!("The float image was not rejected: {:?}", result.err()),
+        }
+
+        assert!(check_image_json(r#"{ "uri": "data:image/png;base64,AAAA" }"#).is_ok());
+        assert!(check_image_json(r#"{ "bufferView": 0, "mimeType": "image/jpeg" }"#).is_ok());
+    }
+
+    #[test]
+    fn reject_unsupported_required_extensions() {
+        for extension in &UNSUPPORTED_REQUIRED_EXTENSIONS {
+            let json = format!(r#"{{
+                "asset": {{ "version": "2.0" }},
+                "extensionsUsed": ["{0}"],
+                "extensionsRequired": ["{0}"]
+            }}"#, extension);
+
+            match import_gltf_slice(json.as_bytes(), &mut |_| ()) {
+                Err(ModelImportError::UnsupportedExtension { name }) => assert_eq!(name, *extension),
+                _ => panic!("The required extension {} was not rejected.", extension),
+            }
+        }
+
+        // Models which only use the extensions provide uncompressed fallback data
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["EXT_meshopt_compression", "KHR_draco_mesh_compression", "KHR_texture_basisu"]
+        }"#;
+
+        assert!(import_gltf_slice(json.as_bytes(), &mut |_| ()).is_ok());
+
+        // Textures requiring KTX2 images have no fallback `source`
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_texture_basisu"],
+            "extensionsRequired": ["KHR_texture_basisu"],
+            "textures": [{ "extensions": { "KHR_texture_basisu": { "source": 0 } } }],
+            "images": [{ "uri": "texture.ktx2" }]
+        }"#;
+
+        match import_gltf_slice(json.as_bytes(), &mut |_| ()) {
+            Err(ModelImportError::UnsupportedExtension { name }) => assert_eq!(name, "KHR_texture_basisu"),
+            _ => panic!("The model with a KTX2 texture was not rejected."),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::mem;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::sync::{self, GpuFuture};
+use failure::Error;
+use crate::ChosenQueues;
+use crate::pipeline::GraphicsPipelineSetCache;
+use crate::model::{Model, HelperResources};
+use crate::model::import;
+use crate::model::resource::UninitializedResource;
+use crate::model::error::ModelImportError;
+
+/// Where to load a model from
+pub enum ModelSource {
+    Path(PathBuf),
+    Slice(Vec<u8>),
+}
+
+/// The stages a model goes through while being loaded in the background, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelLoadingStage {
+    /// Reading and parsing the glTF document along with its buffers and images
+    Parsing,
+    /// Preprocessing the model data on the CPU and recording the device resource initialization
+    Preprocessing,
+    /// Waiting for the device to finish initializing the resources
+    Uploading,
+    /// The model is loaded and can be taken from the handle
+    Ready,
+    /// Loading failed, the error can be taken from the handle
+    Failed,
+}
+
+impl ModelLoadingStage {
+    /// The range of the overall loading progress covered by the stage.
+    fn progress_range(&self) -> (f32, f32) {
+        match self {
+            ModelLoadingStage::Parsing => (0.0, 0.3),
+            ModelLoadingStage::Preprocessing => (0.3, 0.8),
+            ModelLoadingStage::Uploading => (0.8, 1.0),
+            ModelLoadingStage::Ready | ModelLoadingStage::Failed => (1.0, 1.0),
+        }
+    }
+}
+
+enum ModelLoadingState {
+    /// The stage being worked on, along with the fraction of the stage done
+    Loading(ModelLoadingStage, f32),
+    Finished(Result<Model, ModelImportError>),
+    Taken(ModelLoadingStage),
+}
+
+/// A model being loaded on a background thread, created by `Ammolite::load_model_path_async`
+/// or `Ammolite::load_model_slice_async`.
+pub struct ModelLoadingHandle {
+    state: Arc<Mutex<ModelLoadingState>>,
+}
+
+impl ModelLoadingHandle {
+    pub(crate) fn spawn(
+        device: Arc<Device>,
+        queues: ChosenQueues,
+        pipeline_cache: GraphicsPipelineSetCache,
+        helper_resources: HelperResources,
+        source: ModelSource,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(ModelLoadingState::Loading(ModelLoadingStage::Parsing, 0.0)));
+        let thread_state = state.clone();
+
+        thread::spawn(move || {
+            // Report panics as failures, rather than leaving the handle loading forever
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                load_model(&device, &queues, &pipeline_cache, &helper_resources, source, &thread_state)
+                    .map_err(ModelImportError::from)
+            })).unwrap_or_else(|payload| Err(ModelImportError::Panicked {
+                message: panic_message(&*payload),
+            }));
+
+            *thread_state.lock().unwrap_or_else(|error| error.into_inner()) = ModelLoadingState::Finished(result);
+        });
+
+        Self { state }
+    }
+
+    pub fn stage(&self) -> ModelLoadingStage {
+        match *self.state.lock().expect("The model loading state became poisoned.") {
+            ModelLoadingState::Loading(stage, _) => stage,
+            ModelLoadingState::Finished(Ok(_)) => ModelLoadingStage::Ready,
+            ModelLoadingState::Finished(Err(_)) => ModelLoadingStage::Failed,
+            ModelLoadingState::Taken(stage) => stage,
+        }
+    }
+
+    /// The fraction of the loading work done, in the range `[0; 1]`.
+    pub fn progress(&self) -> f32 {
+        match *self.state.lock().expect("The model loading state became poisoned.") {
+            ModelLoadingState::Loading(stage, stage_progress) => {
+                let (start, end) = stage.progress_range();
+
+                start + (end - start) * stage_progress.max(0.0).min(1.0)
+            },
+            _ => 1.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.stage() {
+            ModelLoadingStage::Ready | ModelLoadingStage::Failed => true,
+            _ => false,
+        }
+    }
+
+    /// Takes the loaded model, or the error loading failed with. Returns `None` while the model
+    /// is still loading, or after the result has been taken.
+    pub fn take(&self) -> Option<Result<Model, ModelImportError>> {
+        let mut state = self.state.lock().expect("The model loading state became poisoned.");
+        let stage = match *state {
+            ModelLoadingState::Finished(Ok(_)) => ModelLoadingStage::Ready,
+            ModelLoadingState::Finished(Err(_)) => ModelLoadingStage::Failed,
+            _ => return None,
+        };
+
+        match mem::replace(&mut *state, ModelLoadingState::Taken(stage)) {
+            ModelLoadingState::Finished(result) => Some(result),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn load_model(
+    device: &Arc<Device>,
+    queues: &ChosenQueues,
+    pipeline_cache: &GraphicsPipelineSetCache,
+    helper_resources: &HelperResources,
+    source: ModelSource,
+    state: &Mutex<ModelLoadingState>,
+) -> Result<Model, Error> {
+    let set_progress = |stage, stage_progress| {
+        *state.lock().expect("The model loading state became poisoned.") = ModelLoadingState::Loading(stage, stage_progress);
+    };
+
+    let (document, buffer_data_array, image_data_array, raw_json) = {
+        let mut progress = |stage_progress| set_progress(ModelLoadingStage::Parsing, stage_progress);
+
+        match source {
+            ModelSource::Path(path) => import::import_gltf_path(&path, &mut progress)?,
+            ModelSource::Slice(slice) => import::import_gltf_slice(&slice[..], &mut progress)?,
+        }
+    };
+
+    set_progress(ModelLoadingStage::Preprocessing, 0.0);
+
+    // Mipmaps are generated by blitting, which is not supported by dedicated transfer queues
+    let queue = &queues.graphics;
+    let command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())?;
+    let (command_buffer_builder, model) = import::import_model(
+        device,
+        queues.families(),
+        pipeline_cache,
+        helper_resources,
+        document,
+        buffer_data_array,
+        image_data_array,
+        &raw_json,
+        // Recording the resource initialization takes the rest of the stage
+        &mut |stage_progress| set_progress(ModelLoadingStage::Preprocessing, stage_progress * 0.8),
+    )?.initialize_resource(device, queue.family(), command_buffer_builder)?;
+    let command_buffer = command_buffer_builder.build()?;
+
+    set_progress(ModelLoadingStage::Uploading, 0.0);
+
+    sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    Ok(model)
+}
+
+/// The message a thread panicked with, as passed to `panic!`.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic payload".to_string()
+    }
+}
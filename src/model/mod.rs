@@ -1,13 +1,17 @@
 pub mod error;
 pub mod resource;
 pub mod import;
+pub mod animation;
+pub mod pose;
+pub mod loading;
+pub mod export;
+pub mod bounds;
+pub mod bvh;
 
 use std::marker::PhantomData;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::path::Path;
-use std::mem;
 use std::collections::HashMap;
-use itertools::Itertools;
 use core::num::NonZeroU32;
 use vulkano::buffer::BufferAccess;
 use vulkano::buffer::BufferSlice;
@@ -43,23 +47,32 @@ use vulkano::sampler::SamplerAddressMode;
 use gltf::accessor::Accessor;
 use gltf::{self, Document};
 use gltf::material::AlphaMode;
-use gltf::mesh::{Primitive, Mesh, Semantic};
-use gltf::Node;
+use gltf::mesh::{Primitive, Mesh, Semantic, Mode};
+use gltf::{Node, Skin};
 use gltf::accessor::DataType;
+use byteorder::{ByteOrder, LittleEndian};
 use failure::Error;
-use safe_transmute::PodTransmutable;
 use crate::ChosenQueues;
 use crate::ViewSwapchain;
-use crate::shaders::{PushConstants, InstanceUBO};
+use crate::shaders::{PushConstants, NodeUBO};
 use crate::vertex::*;
 use crate::pipeline::GraphicsPipelineProperties;
 use crate::pipeline::GraphicsPipelineSetCache;
+use crate::pipeline::GraphicsPipelineSetUsage;
 use crate::pipeline::GltfGraphicsPipeline;
 use crate::pipeline::DescriptorSetMap;
 use crate::iter::ArrayIterator;
-use ammolite_math::{Vec3, Mat4};
+use crate::iter::ZERO_ELEMENT;
+use crate::light::Light;
+use crate::camera::GltfCamera;
+use ammolite_math::{Vec3, Mat4, Matrix};
 use self::error::*;
 use self::resource::*;
+use self::animation::Animation;
+use self::pose::Pose;
+use self::export::ExportedGltf;
+use self::bounds::Bounds;
+use self::bvh::{Bvh, MeshInstance, PrimitiveTriangle, build_mesh_instance_bvh, build_triangle_bvh};
 
 // TODO: Figure out a better way to provide the clear values, as they shouldn't need to be
 // specified by the end user
@@ -70,7 +83,13 @@ impl<C, F> FramebufferWithClearValues<C> for F where F: FramebufferAbstract + Re
 #[derive(Clone)]
 pub struct InstanceDrawContext<'a> {
     pub draw_context: &'a DrawContext<'a>,
-    pub descriptor_set_map_instance: &'a DescriptorSetMap,
+    /// The transformations of the instances to draw, see `Model::create_instance_buffers`
+    pub instance_buffers: &'a InstanceBuffers,
+    /// Node descriptor sets of a posed instance, the rest pose is used if `None`
+    pub descriptor_set_maps_node: Option<&'a [DescriptorSetMap]>,
+    /// Visibility of the nodes of a posed instance, see `Pose::world_visibility`.
+    /// All nodes are visible if `None`.
+    pub node_visibility: Option<&'a [bool]>,
 }
 
 #[derive(Clone)]
@@ -83,7 +102,26 @@ pub struct DrawContext<'a> {
     pub view_swapchain_index: usize,
     pub view_swapchain: &'a ViewSwapchain,
     pub vk_queues: &'a ChosenQueues,
-    pub buffer_pool_uniform_instance: &'a CpuBufferPool<InstanceUBO>,
+    pub buffer_pool_vertex_instance_matrices: &'a CpuBufferPool<GltfInstanceMatrix>,
+    pub buffer_pool_uniform_node: &'a CpuBufferPool<NodeUBO>,
+    pub buffer_pool_storage_joint_matrices: &'a CpuBufferPool<[[f32; 4]; 4]>,
+    pub buffer_pool_storage_morph_weights: &'a CpuBufferPool<f32>,
+}
+
+/// The transformations of a group of instances of a model in the same pose, bound as
+/// instance-rate vertex buffers.
+#[derive(Clone)]
+pub struct InstanceBuffers {
+    instances: Arc<dyn BufferAccess + Send + Sync>,
+    /// The instance transformations multiplied with the `EXT_mesh_gpu_instancing` transformations
+    /// of each node, `None` for nodes without the extension
+    node_instances: Vec<Option<Arc<dyn BufferAccess + Send + Sync>>>,
+}
+
+impl InstanceBuffers {
+    fn get(&self, node_index: usize) -> &Arc<dyn BufferAccess + Send + Sync> {
+        self.node_instances[node_index].as_ref().unwrap_or(&self.instances)
+    }
 }
 
 #[derive(Clone)]
@@ -92,12 +130,22 @@ pub struct HelperResources {
     pub zero_buffer: Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>,
     pub cheapest_sampler: Arc<Sampler>,
     pub default_sampler: Arc<Sampler>,
+    /// The maximum anisotropy of texture samplers, clamped to what the device supports
+    pub max_anisotropy: f32,
 }
 
 impl HelperResources {
-    pub fn new<'a, I>(device: &Arc<Device>, queue_families: I)
+    pub fn new<'a, I>(device: &Arc<Device>, queue_families: I, max_anisotropy: f32)
             -> Result<SimpleUninitializedResource<HelperResources>, Error>
             where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
+        let max_anisotropy = if device.enabled_features().sampler_anisotropy {
+            max_anisotropy
+                .min(device.physical_device().limits().max_sampler_anisotropy())
+                .max(1.0)
+        } else {
+            1.0
+        };
+
         let empty_device_image_view = {
             let usage = ImageUsage {
                 input_attachment: true,
@@ -142,6 +190,7 @@ impl HelperResources {
                 BufferUsage {
                     transfer_destination: true,
                     vertex_buffer: true,
+                    storage_buffer: true,
                     ..BufferUsage::none()
                 },
                 queue_families.clone(),
@@ -173,9 +222,9 @@ impl HelperResources {
             SamplerAddressMode::Repeat,
             SamplerAddressMode::Repeat,
             0.0,
-            1.0,
-            0.0,
+            max_anisotropy,
             0.0,
+            std::f32::MAX,
         )?;
 
         let tasks = vec![
@@ -195,6 +244,7 @@ impl HelperResources {
             zero_buffer: zero_device_buffer,
             cheapest_sampler,
             default_sampler,
+            max_anisotropy,
         };
 
         Ok(SimpleUninitializedResource::new(output, tasks))
@@ -259,9 +309,9 @@ pub trait DrawCallIssuer {
 #[derive(Clone)]
 pub struct GltfContextLessDescriptorSets {
     descriptor_set_scene: Arc<dyn DescriptorSet + Send + Sync>,
-    /// The instance descriptor set is left out to be filled in by the `DrawCallIssuer`.
-    descriptor_set_instance: (),
-    descriptor_set_node: Arc<dyn DescriptorSet + Send + Sync>,
+    /// The node descriptor set depends on the pose of the instance, it is left out to be filled
+    /// in by the `DrawCallIssuer`.
+    descriptor_set_node: (),
     descriptor_set_material: Arc<dyn DescriptorSet + Send + Sync>,
     /// The blend descriptor set is only specified in the last subpass
     descriptor_set_blend: Option<Arc<dyn DescriptorSet + Send + Sync>>,
@@ -271,6 +321,7 @@ pub struct GltfContextLessDescriptorSets {
 pub struct GltfContextLessDrawCallCustomData {
     incomplete_descriptor_sets: GltfContextLessDescriptorSets,
     push_constants: PushConstants,
+    node_index: usize,
 }
 
 pub type GltfContextLessDrawCall = ContextLessDrawCall<
@@ -280,7 +331,7 @@ pub type GltfContextLessDrawCall = ContextLessDrawCall<
 >;
 
 pub struct GltfDrawCallContext<'a> {
-    pub descriptor_set_map_instance: &'a DescriptorSetMap,
+    pub descriptor_set_maps_node: &'a [DescriptorSetMap],
 }
 
 pub struct GltfDrawCallIssuer<'a> {
@@ -306,15 +357,15 @@ impl<'a> DrawCallIssuer for GltfDrawCallIssuer<'a> {
         let GltfContextLessDrawCallCustomData {
             incomplete_descriptor_sets,
             push_constants: constants,
+            node_index,
         } = custom_data;
         let GltfContextLessDescriptorSets {
             descriptor_set_scene,
-            descriptor_set_node,
             descriptor_set_material,
             descriptor_set_blend,
             ..
         } = incomplete_descriptor_sets;
-        let descriptor_set_instance = context.descriptor_set_map_instance.map
+        let descriptor_set_node = context.descriptor_set_maps_node[node_index].map
             .get(pipeline_layout.desc())
             .expect("A descriptor set has not been generated for one of the required pipelines.")
             .clone();
@@ -322,7 +373,6 @@ impl<'a> DrawCallIssuer for GltfDrawCallIssuer<'a> {
         if let Some(descriptor_set_blend) = descriptor_set_blend {
             let sets = (
                 descriptor_set_scene,
-                descriptor_set_instance,
                 descriptor_set_node,
                 descriptor_set_material,
                 descriptor_set_blend,
@@ -402,7 +452,6 @@ impl<'a> DrawCallIssuer for GltfDrawCallIssuer<'a> {
         } else {
             let sets = (
                 descriptor_set_scene,
-                descriptor_set_instance,
                 descriptor_set_node,
                 descriptor_set_material,
             );
@@ -484,26 +533,87 @@ impl<'a> DrawCallIssuer for GltfDrawCallIssuer<'a> {
 pub struct Model {
     document: Document,
     buffer_data: Vec<gltf::buffer::Data>,
-    device_buffers: Vec<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>,
+    /// Buffers which are only accessed on the CPU side (e.g. inverse bind matrices) are not
+    /// uploaded to the device.
+    device_buffers: Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>,
     #[allow(dead_code)]
     device_images: Vec<Arc<dyn ImageViewAccess + Send + Sync>>,
+    /// The number of bytes occupied by each image in `device_images`, including mipmaps
+    device_image_byte_lens: Vec<usize>,
     /// In case indexes are specified as u8 values, convert and store them as u16 values in this
     /// field. This conversion is needed, because Vulkan doesn't support 8-bit indices.
     converted_index_buffers_by_accessor_index: Vec<Option<Arc<dyn TypedBufferAccess<Content=[u16]> + Send + Sync>>>,
+    /// Indices of `LINE_LOOP` primitives, closed by repeating the first vertex, so that they can
+    /// be drawn as line strips. Vulkan has no line loop topology.
+    line_loop_index_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u32]> + Send + Sync>>>>,
     /// Precomputed normal buffers, in case they were not specified in the glTF document
     // FIXME: Should probably be of type `GltfVertexNormal` instead of `u8`
     normal_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>,
     /// Precomputed tangent buffers, in case they were not specified in the glTF document
     // FIXME: Should probably be of type `GltfVertexTangent` instead of `u8`
     tangent_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>,
+    /// Joint indices converted to `GltfVertexJoints`, as Vulkan vertex attributes cannot be
+    /// widened from 8-bit or 16-bit unsigned integers to 32-bit ones by the input assembler.
+    joint_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>,
+    /// Joint weights converted to `GltfVertexWeights`, with normalized integers mapped to floats
+    weight_buffers: Vec<Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>>,
+    /// Inverse bind matrices of each skin, one for each joint
+    skin_inverse_bind_matrices: Vec<Vec<Mat4>>,
+    animations: Vec<Animation>,
+    /// Instance transformations of each node using `EXT_mesh_gpu_instancing`, relative to the node
+    node_instance_matrices: Vec<Option<Vec<Mat4>>>,
+    /// The `EXT_mesh_gpu_instancing` extension objects of each node, see `export`
+    node_instancing_extensions: Vec<Option<gltf::json::Value>>,
+    /// Bounds of each primitive of each mesh, in the coordinate space of the mesh
+    primitive_bounds: Vec<Vec<Option<Bounds>>>,
+    /// Triangle BVHs of each primitive of each mesh, built on the first ray query
+    primitive_bvhs: Vec<Vec<RwLock<Option<Arc<Bvh<PrimitiveTriangle>>>>>>,
+    /// Mesh instance BVHs of each scene in the rest pose, in the coordinate space of the model,
+    /// built on the first ray query
+    scene_mesh_instance_bvhs: Vec<RwLock<Option<Arc<Bvh<MeshInstance>>>>>,
+    /// Morph target deltas of all primitives of each mesh, see `precompute_morph_target_buffers`
+    morph_target_buffers: Vec<Option<Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>>,
+    /// Offsets of the morph target deltas of each primitive within the buffer of its mesh
+    morph_target_offsets: Vec<Vec<u32>>,
     node_transform_matrices: Vec<Mat4>,
     // Note: Do not ever try to express the descriptor set explicitly.
     node_descriptor_sets: Vec<DescriptorSetMap>,
     material_descriptor_sets: Vec<DescriptorSetMap>,
+    /// The bytes of the uniform and storage buffers created for the node and material descriptor
+    /// sets
+    descriptor_set_buffer_bytes: usize,
+    /// Keeps the pipelines of the model from being evicted from the pipeline cache
+    _pipeline_usage: GraphicsPipelineSetUsage,
     /// A `Vec` of lazily created `ContextLessDrawCall`s for each scene and subpass.
     scene_subpass_context_less_draw_calls: Vec<[RwLock<Option<Vec<GltfContextLessDrawCall>>>; 4]>,
 }
 
+/// Device memory held by a `Model`. Resources shared with other models, such as pipelines and
+/// the helper resources, are not included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelMemoryStatistics {
+    /// Bytes of vertex, index, storage and uniform buffers
+    pub buffer_bytes: usize,
+    /// Bytes of images, including their mipmaps
+    pub image_bytes: usize,
+    pub descriptor_set_count: usize,
+}
+
+impl ModelMemoryStatistics {
+    pub fn total_bytes(&self) -> usize {
+        self.buffer_bytes + self.image_bytes
+    }
+}
+
+fn get_buffers_byte_len<'a, T: ?Sized + 'static>(
+    buffers: impl IntoIterator<Item=&'a Option<Arc<dyn TypedBufferAccess<Content=T> + Send + Sync>>>,
+) -> usize {
+    buffers.into_iter()
+        .filter_map(Option::as_ref)
+        .map(|buffer| buffer.size())
+        .sum()
+}
+
 pub struct AccessorDetails<'a> {
     pub accessor: Accessor<'a>,
     pub stride: usize,
@@ -512,10 +622,20 @@ pub struct AccessorDetails<'a> {
 
 impl<'a> AccessorDetails<'a> {
     pub fn from(buffer_data: &'a [gltf::buffer::Data], accessor: Accessor<'a>) -> Self {
-        let view = accessor.view();
+        let view = if let Some(view) = accessor.view() {
+            view
+        } else {
+            // The elements of accessors without a buffer view are zeros, every element is read
+            // from the same zeroed slice
+            return AccessorDetails {
+                byte_slice: &ZERO_ELEMENT[..accessor.size()],
+                stride: 0,
+                accessor,
+            };
+        };
         let stride = view.stride().unwrap_or_else(|| accessor.size());
         let slice_offset = view.offset() + accessor.offset();
-        let slice_len = stride * accessor.count();
+        let slice_len = get_accessor_byte_len(&accessor, stride);
         let byte_slice: &[u8] = &buffer_data[view.buffer().index()][slice_offset..(slice_offset + slice_len)];
 
         AccessorDetails {
@@ -524,6 +644,181 @@ impl<'a> AccessorDetails<'a> {
             byte_slice,
         }
     }
+
+    fn component_slice(&self, item_index: usize, component_index: usize) -> &'a [u8] {
+        let component_size = self.accessor.data_type().size();
+        let offset = item_index * self.stride + component_index * component_size;
+
+        &self.byte_slice[offset..(offset + component_size)]
+    }
+
+    /// Reads a single component of an element and converts it to `f32`. Components of
+    /// normalized integer accessors are mapped to the `[0; 1]` or `[-1; 1]` range.
+    pub fn read_component_f32(&self, item_index: usize, component_index: usize) -> f32 {
+        let slice = self.component_slice(item_index, component_index);
+        let normalized = self.accessor.normalized();
+
+        match self.accessor.data_type() {
+            DataType::I8 if normalized => (slice[0] as i8 as f32 / 127.0).max(-1.0),
+            DataType::I8 => slice[0] as i8 as f32,
+            DataType::U8 if normalized => slice[0] as f32 / 255.0,
+            DataType::U8 => slice[0] as f32,
+            DataType::I16 if normalized => (LittleEndian::read_i16(slice) as f32 / 32767.0).max(-1.0),
+            DataType::I16 => LittleEndian::read_i16(slice) as f32,
+            DataType::U16 if normalized => LittleEndian::read_u16(slice) as f32 / 65535.0,
+            DataType::U16 => LittleEndian::read_u16(slice) as f32,
+            DataType::U32 => LittleEndian::read_u32(slice) as f32,
+            DataType::F32 => LittleEndian::read_f32(slice),
+        }
+    }
+
+    /// Reads the first `output.len()` components of an element, see
+    /// `AccessorDetails::read_component_f32`.
+    pub fn read_element_f32(&self, item_index: usize, output: &mut [f32]) {
+        for (component_index, component) in output.iter_mut().enumerate() {
+            *component = self.read_component_f32(item_index, component_index);
+        }
+    }
+
+    /// Reads a single component of an element of an integer accessor and converts it to `u32`.
+    pub fn read_component_u32(&self, item_index: usize, component_index: usize) -> u32 {
+        let slice = self.component_slice(item_index, component_index);
+
+        match self.accessor.data_type() {
+            DataType::I8 => slice[0] as i8 as u32,
+            DataType::U8 => slice[0] as u32,
+            DataType::I16 => LittleEndian::read_i16(slice) as u32,
+            DataType::U16 => LittleEndian::read_u16(slice) as u32,
+            DataType::U32 => LittleEndian::read_u32(slice),
+            DataType::F32 => LittleEndian::read_f32(slice) as u32,
+        }
+    }
+}
+
+/// The index of the vertex of each element of a primitive, in the order of its index accessor, if
+/// any. Non-indexed primitives use their vertices in the order they are stored in.
+pub(crate) fn read_primitive_vertex_indices(buffer_data_array: &[gltf::buffer::Data], primitive: &Primitive) -> Vec<usize> {
+    if let Some(index_accessor) = primitive.indices() {
+        let index_accessor_details = AccessorDetails::from(buffer_data_array, index_accessor);
+
+        (0..index_accessor_details.accessor.count())
+            .map(|item_index| index_accessor_details.read_component_u32(item_index, 0) as usize)
+            .collect()
+    } else {
+        let vertex_count = primitive.get(&Semantic::Positions)
+            .map(|position_accessor| position_accessor.count())
+            .unwrap_or(0);
+
+        (0..vertex_count).collect()
+    }
+}
+
+/// The BVH stored in `bvh`, built and stored first if there is none.
+fn get_or_build_bvh<T>(bvh: &RwLock<Option<Arc<Bvh<T>>>>, build: impl FnOnce() -> Bvh<T>) -> Arc<Bvh<T>> {
+    {
+        let read_lock = bvh.read().unwrap();
+
+        if let Some(ref bvh) = *read_lock {
+            return bvh.clone();
+        }
+    }
+
+    let mut write_lock = bvh.write().unwrap();
+
+    // Another thread may have built the BVH before the write lock was acquired
+    if let Some(ref bvh) = *write_lock {
+        return bvh.clone();
+    }
+
+    let result = Arc::new(build());
+
+    *write_lock = Some(result.clone());
+
+    result
+}
+
+/// Computes the joint matrices of a skinned node. A joint matrix transforms a vertex from the
+/// bind pose to the coordinate space of the skinned node.
+pub(crate) fn compute_joint_matrices(
+    skin: &Skin,
+    node_world_matrix: &Mat4,
+    inverse_bind_matrices: &[Mat4],
+    world_matrices: &[Mat4],
+) -> Vec<Mat4> {
+    let inverse_node_world_matrix = if node_world_matrix.determinant() != 0.0 {
+        node_world_matrix.inverse()
+    } else {
+        Mat4::IDENTITY
+    };
+
+    skin.joints()
+        .zip(inverse_bind_matrices.iter())
+        .map(|(joint, inverse_bind_matrix)| {
+            &inverse_node_world_matrix * &world_matrices[joint.index()] * inverse_bind_matrix
+        })
+        .collect()
+}
+
+/// The number of bytes spanned by the elements of an accessor with the given stride. The last
+/// element is not required to be padded to the full stride.
+pub(crate) fn get_accessor_byte_len(accessor: &Accessor, stride: usize) -> usize {
+    if accessor.count() == 0 {
+        0
+    } else {
+        stride * (accessor.count() - 1) + accessor.size()
+    }
+}
+
+/// Splits `vertex_count` vertices, in the order given by the indices of a primitive, into the
+/// triangles rasterized for the given mode. Triangles are returned as positions within that order,
+/// with the winding of triangle strips corrected. Point and line modes do not produce triangles.
+pub(crate) fn get_primitive_triangles(mode: Mode, vertex_count: usize) -> Box<dyn Iterator<Item=[usize; 3]>> {
+    match mode {
+        Mode::Triangles => Box::new(
+            (0..vertex_count / 3).map(|triangle| [3 * triangle, 3 * triangle + 1, 3 * triangle + 2])
+        ),
+        Mode::TriangleStrip => Box::new(
+            (0..vertex_count.saturating_sub(2)).map(|triangle| {
+                [triangle, triangle + 1 + triangle % 2, triangle + 2 - triangle % 2]
+            })
+        ),
+        Mode::TriangleFan => Box::new(
+            (1..vertex_count.saturating_sub(1)).map(|triangle| [triangle, triangle + 1, 0])
+        ),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => Box::new(std::iter::empty()),
+    }
+}
+
+/// The indices of a line loop primitive drawn as a line strip, closed by repeating the first index.
+pub(crate) fn get_line_loop_strip_indices(indices: impl IntoIterator<Item=usize>) -> Vec<u32> {
+    let mut result: Vec<u32> = indices.into_iter().map(|index| index as u32).collect();
+
+    if let Some(&first_index) = result.first() {
+        result.push(first_index);
+    }
+
+    result
+}
+
+/// The rest morph target weights of a node, one for each morph target of its mesh.
+/// Empty for nodes without a mesh or morph targets.
+pub(crate) fn default_morph_weights(node: &Node) -> Vec<f32> {
+    node.mesh().map(|mesh| {
+        let morph_target_count = mesh.primitives()
+            .map(|primitive| primitive.morph_targets().count())
+            .max()
+            .unwrap_or(0);
+        let mut weights = vec![0.0; morph_target_count];
+        let default_weights = node.weights().or_else(|| mesh.weights());
+
+        if let Some(default_weights) = default_weights {
+            let len = weights.len().min(default_weights.len());
+
+            weights[..len].copy_from_slice(&default_weights[..len]);
+        }
+
+        weights
+    }).unwrap_or_else(Vec::new)
 }
 
 impl Model {
@@ -535,14 +830,150 @@ impl Model {
         &self.node_transform_matrices[..]
     }
 
-    pub(crate) unsafe fn index_slice_transmute<'a, T: PodTransmutable>(index_accessor_details: &'a AccessorDetails<'a>, item_index: usize) -> &'a T {
-        let item_slice_start_index = item_index * index_accessor_details.stride;
-        let item_slice_range = item_slice_start_index..(item_slice_start_index + mem::size_of::<T>());
-        let item_slice = &index_accessor_details.byte_slice[item_slice_range];
-        let item_ptr = item_slice.as_ptr();
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations[..]
+    }
+
+    /// The device memory held by this model. Buffers allocated per frame for posed instances
+    /// are not included.
+    pub fn memory_statistics(&self) -> ModelMemoryStatistics {
+        let buffer_bytes = get_buffers_byte_len(&self.device_buffers)
+            + get_buffers_byte_len(&self.converted_index_buffers_by_accessor_index)
+            + get_buffers_byte_len(self.line_loop_index_buffers.iter().flatten())
+            + get_buffers_byte_len(self.normal_buffers.iter().flatten())
+            + get_buffers_byte_len(self.tangent_buffers.iter().flatten())
+            + get_buffers_byte_len(self.joint_buffers.iter().flatten())
+            + get_buffers_byte_len(self.weight_buffers.iter().flatten())
+            + get_buffers_byte_len(&self.morph_target_buffers)
+            + self.descriptor_set_buffer_bytes;
+        let descriptor_set_count = self.node_descriptor_sets.iter()
+            .chain(self.material_descriptor_sets.iter())
+            .map(|descriptor_set_map| descriptor_set_map.map.len())
+            .sum();
+
+        ModelMemoryStatistics {
+            buffer_bytes,
+            image_bytes: self.device_image_byte_lens.iter().sum(),
+            descriptor_set_count,
+        }
+    }
+
+    pub fn find_node_index(&self, name: &str) -> Option<usize> {
+        self.document.nodes().position(|node| node.name() == Some(name))
+    }
 
-        // safe_transmute::guarded_transmute_pod::<&'a T>(item_slice).unwrap()
-        unsafe { &*(item_ptr as *const T) }
+    pub fn find_animation_index(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|animation| animation.name() == Some(name))
+    }
+
+    pub fn find_camera_index(&self, name: &str) -> Option<usize> {
+        self.document.cameras().position(|camera| camera.name() == Some(name))
+    }
+
+    /// Creates the camera with the given index, as seen from an instance of this model. The
+    /// camera is placed at the first node it is attached to, `None` is returned if there is no
+    /// such node.
+    pub fn camera(&self, camera_index: usize, instance_matrix: &Mat4, pose: Option<&Pose>) -> Option<GltfCamera> {
+        let world_matrices = pose.map(Pose::world_matrices)
+            .unwrap_or(&self.node_transform_matrices[..]);
+
+        self.document.nodes()
+            .filter_map(|node| node.camera().map(|camera| (node, camera)))
+            .find(|(_, camera)| camera.index() == camera_index)
+            .map(|(node, camera)| {
+                GltfCamera::from_gltf(&camera, instance_matrix * &world_matrices[node.index()])
+            })
+    }
+
+    /// The `KHR_lights_punctual` lights of an instance of this model, in world space.
+    /// Lights attached to hidden nodes are omitted.
+    pub fn lights(&self, instance_matrix: &Mat4, pose: Option<&Pose>) -> Vec<Light> {
+        let world_matrices = pose.map(Pose::world_matrices)
+            .unwrap_or(&self.node_transform_matrices[..]);
+
+        self.document.nodes()
+            .filter(|node| pose.map(|pose| pose.world_visibility()[node.index()]).unwrap_or(true))
+            .filter_map(|node| node.light().map(|light| {
+                Light::from_gltf(&light, &(instance_matrix * &world_matrices[node.index()]))
+            }))
+            .collect()
+    }
+
+    /// The bounds of a primitive, in the coordinate space of its mesh, `None` if the primitive
+    /// has no positions
+    pub fn primitive_bounds(&self, mesh_index: usize, primitive_index: usize) -> Option<&Bounds> {
+        self.primitive_bounds.get(mesh_index)?.get(primitive_index)?.as_ref()
+    }
+
+    /// The bounds of all primitives of a mesh, in the coordinate space of the mesh
+    pub fn mesh_bounds(&self, mesh_index: usize) -> Option<Bounds> {
+        self.primitive_bounds.get(mesh_index)?.iter()
+            .filter_map(Option::as_ref)
+            .fold(None, |result: Option<Bounds>, bounds| {
+                Some(result.map(|result| result.union(bounds)).unwrap_or_else(|| bounds.clone()))
+            })
+    }
+
+    /// The bounds of the mesh of a node of an instance of this model, including all of its
+    /// `EXT_mesh_gpu_instancing` instances. Pass the identity matrix as `instance_matrix` to get
+    /// the bounds in the coordinate space of the model.
+    /// Skinning is not taken into account.
+    pub fn node_bounds(&self, node_index: usize, instance_matrix: &Mat4, pose: Option<&Pose>) -> Option<Bounds> {
+        let world_matrices = pose.map(Pose::world_matrices)
+            .unwrap_or(&self.node_transform_matrices[..]);
+        let mesh_bounds = self.mesh_bounds(self.document.nodes().nth(node_index)?.mesh()?.index())?;
+        let node_matrix = instance_matrix * &world_matrices[node_index];
+
+        if let Some(ref node_instance_matrices) = self.node_instance_matrices[node_index] {
+            node_instance_matrices.iter()
+                .map(|node_instance_matrix| mesh_bounds.transform(&(&node_matrix * node_instance_matrix)))
+                .fold(None, |result: Option<Bounds>, bounds| {
+                    Some(result.map(|result| result.union(&bounds)).unwrap_or(bounds))
+                })
+        } else {
+            Some(mesh_bounds.transform(&node_matrix))
+        }
+    }
+
+    /// The bounds of all visible nodes of a scene of an instance of this model, see
+    /// `Model::node_bounds`.
+    pub fn scene_bounds(&self, scene_index: usize, instance_matrix: &Mat4, pose: Option<&Pose>) -> Option<Bounds> {
+        let scene = self.document.scenes().nth(scene_index)?;
+        let mut result: Option<Bounds> = None;
+        let mut node_stack: Vec<Node> = scene.nodes().collect();
+
+        while let Some(node) = node_stack.pop() {
+            if !pose.map(|pose| pose.world_visibility()[node.index()]).unwrap_or(true) {
+                continue;
+            }
+
+            if let Some(bounds) = self.node_bounds(node.index(), instance_matrix, pose) {
+                result = Some(result.map(|result| result.union(&bounds)).unwrap_or(bounds));
+            }
+
+            node_stack.extend(node.children());
+        }
+
+        result
+    }
+
+    /// The triangle BVH of a primitive, built on first use
+    pub(crate) fn primitive_bvh(&self, mesh_index: usize, primitive_index: usize) -> Arc<Bvh<PrimitiveTriangle>> {
+        get_or_build_bvh(&self.primitive_bvhs[mesh_index][primitive_index], || {
+            let mesh = self.document.meshes().nth(mesh_index).unwrap();
+            let primitive = mesh.primitives().nth(primitive_index).unwrap();
+
+            build_triangle_bvh(self.primitive_triangles_iter(&primitive))
+        })
+    }
+
+    /// The mesh instance BVH of a scene in the rest pose, in the coordinate space of the model,
+    /// built on first use. Instances in other poses need a BVH of their own, see
+    /// `build_mesh_instance_bvh`.
+    pub(crate) fn scene_mesh_instance_bvh(&self, scene_index: usize) -> Arc<Bvh<MeshInstance>> {
+        get_or_build_bvh(&self.scene_mesh_instance_bvhs[scene_index], || {
+            build_mesh_instance_bvh(self, scene_index, &Mat4::IDENTITY, None).unwrap()
+        })
     }
 
     pub(crate) fn primitive_positions_iter<'a>(&'a self, primitive: &'a Primitive<'a>) -> impl Iterator<Item=Vec3> + 'a {
@@ -578,47 +1009,16 @@ impl Model {
 
                 let index_index = index_index.unwrap();
                 let position_index = if let &Some(ref index_accessor_details) = &self.index_accessor_details {
-                    // TODO: Get rid of the match arm within this loop, use dynamic dispatch
-                    // instead
-                    match index_accessor_details.accessor.data_type() {
-                        DataType::U8 => {
-                            unsafe {
-                                *Model::index_slice_transmute::<u8>(
-                                    &index_accessor_details,
-                                    index_index,
-                                ) as usize
-                            }
-                        },
-                        DataType::U16 => {
-                            unsafe {
-                                *Model::index_slice_transmute::<u16>(
-                                    &index_accessor_details,
-                                    index_index,
-                                ) as usize
-                            }
-                        },
-                        DataType::U32 => {
-                            unsafe {
-                                *Model::index_slice_transmute::<u32>(
-                                    &index_accessor_details,
-                                    index_index,
-                                ) as usize
-                            }
-                        },
-                        _ => unreachable!(),
-                    }
+                    index_accessor_details.read_component_u32(index_index, 0) as usize
                 } else {
                     index_index as usize
                 };
 
-                let vertex = unsafe {
-                    Model::index_slice_transmute::<GltfVertexPosition>(
-                        &self.position_accessor_details,
-                        position_index,
-                    )
-                };
+                let mut vertex = Vec3::ZERO;
+
+                self.position_accessor_details.read_element_f32(position_index, &mut vertex.0[..]);
 
-                Some(Vec3(vertex.0))
+                Some(vertex)
             }
         }
 
@@ -629,26 +1029,72 @@ impl Model {
         }
     }
 
-    pub(crate) fn primitive_faces_iter<'a>(&'a self, primitive: &'a Primitive<'a>) -> impl Iterator<Item=[Vec3; 3]> + 'a {
-        self.primitive_positions_iter(primitive)
-            .tuples()
-            .map(|(x, y, z)| [x, y, z])
+    /// The index of the vertex of each element of a primitive, see `read_primitive_vertex_indices`.
+    pub(crate) fn primitive_vertex_indices(&self, primitive: &Primitive) -> Vec<usize> {
+        read_primitive_vertex_indices(&self.buffer_data[..], primitive)
     }
 
-    pub(crate) fn get_semantic_buffer_view<T>(&self, accessor: &Accessor) -> BufferSlice<[T], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> {
-        let buffer_view = accessor.view();
+    /// The triangles of a primitive, see `get_primitive_triangles`.
+    pub(crate) fn primitive_triangles_iter<'a>(&'a self, primitive: &'a Primitive<'a>) -> impl Iterator<Item=PrimitiveTriangle> + 'a {
+        let positions: Vec<Vec3> = self.primitive_positions_iter(primitive).collect();
+        let vertex_indices = self.primitive_vertex_indices(primitive);
+
+        get_primitive_triangles(primitive.mode(), positions.len())
+            .enumerate()
+            .map(move |(index, [a, b, c])| PrimitiveTriangle {
+                index,
+                vertex_indices: [vertex_indices[a], vertex_indices[b], vertex_indices[c]],
+                positions: [positions[a].clone(), positions[b].clone(), positions[c].clone()],
+            })
+    }
+
+    /// Interpolates a vertex attribute of a primitive within a triangle, writing the first
+    /// `output.len()` components. Returns `false`, if the primitive has no such attribute.
+    pub(crate) fn interpolate_vertex_attribute(
+        &self,
+        primitive: &Primitive,
+        semantic: &Semantic,
+        vertex_indices: &[usize; 3],
+        barycentric_coordinates: &Vec3,
+        output: &mut [f32],
+    ) -> bool {
+        let accessor_details = if let Some(accessor) = primitive.get(semantic) {
+            AccessorDetails::from(&self.buffer_data[..], accessor)
+        } else {
+            return false;
+        };
+
+        for (component_index, component) in output.iter_mut().enumerate() {
+            *component = vertex_indices.iter()
+                .zip(barycentric_coordinates.iter())
+                .map(|(vertex_index, weight)| weight * accessor_details.read_component_f32(*vertex_index, component_index))
+                .sum();
+        }
+
+        true
+    }
+
+    pub(crate) fn get_semantic_buffer_view<T>(&self, accessor: &Accessor) -> Result<BufferSlice<[T], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>, Error> {
+        // Accessors without a buffer view have no device buffer to draw from
+        let buffer_view = accessor.view()
+            .ok_or(ModelDrawError::MissingBufferView { accessor_index: accessor.index() })?;
         let buffer_index = buffer_view.buffer().index();
         let buffer_offset = accessor.offset() + buffer_view.offset();
-        let buffer_bytes = accessor.size() * accessor.count();
+        let buffer_stride = buffer_view.stride().unwrap_or_else(|| accessor.size());
+        let buffer_bytes = get_accessor_byte_len(accessor, buffer_stride);
 
-        let buffer = self.device_buffers[buffer_index].clone();
+        let buffer = self.device_buffers[buffer_index].clone()
+            .ok_or(ModelDrawError::MissingDeviceBuffer { buffer_index })?;
         let slice: BufferSlice<[u8], _> = BufferSlice::from_typed_buffer_access(buffer)
             .slice(buffer_offset..(buffer_offset + buffer_bytes))
-            .unwrap();
+            .ok_or(ModelImportError::AccessorOutOfBounds {
+                accessor_index: accessor.index(),
+                view_index: buffer_view.index(),
+            })?;
 
         // println!("buffer_view: [{}] offset: {}; len: {}", unsafe { std::intrinsics::type_name::<T>() }, buffer_offset, buffer_bytes);
 
-        unsafe { slice.reinterpret::<[T]>() }
+        Ok(unsafe { slice.reinterpret::<[T]>() })
     }
 
     pub fn import_path<'a, I>(
@@ -657,7 +1103,7 @@ impl Model {
         pipeline_cache: &GraphicsPipelineSetCache,
         helper_resources: &HelperResources,
         path: impl AsRef<Path>,
-    ) -> Result<SimpleUninitializedResource<Model>, Error>
+    ) -> Result<SimpleUninitializedResource<Model>, ModelImportError>
             where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
         import::import_model_path(device, queue_families, pipeline_cache, helper_resources, path)
     }
@@ -668,11 +1114,17 @@ impl Model {
         pipeline_cache: &GraphicsPipelineSetCache,
         helper_resources: &HelperResources,
         slice: impl AsRef<[u8]>,
-    ) -> Result<SimpleUninitializedResource<Model>, Error>
+    ) -> Result<SimpleUninitializedResource<Model>, ModelImportError>
             where I: IntoIterator<Item = QueueFamily<'a>> + Clone {
         import::import_model_slice(device, queue_families, pipeline_cache, helper_resources, slice)
     }
 
+    /// Exports this model, with the node transformations and morph target weights of the given
+    /// pose, see `export::export_model`.
+    pub fn export(&self, pose: Option<&Pose>) -> Result<ExportedGltf, ModelExportError> {
+        export::export_model(self, pose)
+    }
+
     pub fn get_subpass_alpha_modes() -> impl Iterator<Item=AlphaMode> {
         ArrayIterator::new([
             AlphaMode::Opaque,
@@ -719,6 +1171,112 @@ impl Model {
             .collect()
     }
 
+    /// Creates the node descriptor sets of an instance in the given pose. The sets are meant to
+    /// be used for a single frame.
+    pub fn create_pose_descriptor_set_maps(&self, draw_context: &DrawContext, pose: &Pose) -> Vec<DescriptorSetMap> {
+        let used_layouts = self.get_used_pipelines_layouts(&draw_context.pipeline_cache);
+        let world_matrices = pose.world_matrices();
+
+        self.document.nodes().map(|node| {
+            // Only nodes with meshes are ever bound
+            if node.mesh().is_none() {
+                return DescriptorSetMap { map: Default::default() };
+            }
+
+            let node_ubo = NodeUBO::new(world_matrices[node.index()].clone());
+            let node_buffer = Arc::new(draw_context.buffer_pool_uniform_node.next(node_ubo).unwrap());
+            let joint_matrix_buffer: Arc<dyn BufferAccess + Send + Sync> = if let Some(skin) = node.skin() {
+                let joint_matrices = compute_joint_matrices(
+                    &skin,
+                    &world_matrices[node.index()],
+                    &self.skin_inverse_bind_matrices[skin.index()][..],
+                    world_matrices,
+                );
+
+                Arc::new(draw_context.buffer_pool_storage_joint_matrices.chunk(
+                    joint_matrices.into_iter().map(Mat4::into_inner)
+                ).unwrap())
+            } else {
+                Arc::new(draw_context.helper_resources.zero_buffer.clone())
+            };
+            let morph_target_buffer: Arc<dyn BufferAccess + Send + Sync> = Arc::new(
+                node.mesh()
+                    .and_then(|mesh| self.morph_target_buffers[mesh.index()].clone())
+                    .unwrap_or_else(|| draw_context.helper_resources.zero_buffer.clone())
+            );
+            let morph_weights = pose.morph_weights(node.index());
+            let morph_weight_buffer: Arc<dyn BufferAccess + Send + Sync> = if !morph_weights.is_empty() {
+                Arc::new(draw_context.buffer_pool_storage_morph_weights.chunk(
+                    morph_weights.iter().cloned()
+                ).unwrap())
+            } else {
+                Arc::new(draw_context.helper_resources.zero_buffer.clone())
+            };
+
+            DescriptorSetMap::new(
+                &used_layouts[..],
+                |layout_dependent_resources| {
+                    layout_dependent_resources.descriptor_set_pool_node.clone()
+                },
+                |set_builder| {
+                    Arc::new(set_builder.add_buffer(node_buffer.clone()).unwrap()
+                             .add_buffer(joint_matrix_buffer.clone()).unwrap()
+                             .add_buffer(morph_target_buffer.clone()).unwrap()
+                             .add_buffer(morph_weight_buffer.clone()).unwrap()
+                             .build().unwrap())
+                },
+            )
+        }).collect()
+    }
+
+    /// Creates the instance buffers of a group of instances of this model, all in the given pose.
+    /// The buffers are meant to be used for a single frame.
+    pub fn create_instance_buffers(&self, draw_context: &DrawContext, instance_matrices: &[Mat4], pose: Option<&Pose>) -> InstanceBuffers {
+        let create_buffer = |matrices: Vec<Mat4>| -> Arc<dyn BufferAccess + Send + Sync> {
+            Arc::new(draw_context.buffer_pool_vertex_instance_matrices.chunk(
+                matrices.into_iter().map(|matrix| GltfInstanceMatrix(matrix.into_inner()))
+            ).unwrap())
+        };
+        let world_matrices = pose.map(Pose::world_matrices)
+            .unwrap_or(&self.node_transform_matrices[..]);
+        let node_instances = self.node_instance_matrices.iter()
+            .enumerate()
+            .map(|(node_index, node_instance_matrices)| {
+                let node_instance_matrices = node_instance_matrices.as_ref()
+                    .filter(|node_instance_matrices| !node_instance_matrices.is_empty())?;
+                let node_matrix = &world_matrices[node_index];
+                let inverse_node_matrix = if node_matrix.determinant() != 0.0 {
+                    node_matrix.inverse()
+                } else {
+                    Mat4::IDENTITY
+                };
+                // The node matrix is applied by the vertex shader, after the instance matrix,
+                // so it has to be cancelled out
+                let matrices = instance_matrices.iter()
+                    .flat_map(|instance_matrix| {
+                        node_instance_matrices.iter().map(move |node_instance_matrix| {
+                            instance_matrix * node_matrix * node_instance_matrix * &inverse_node_matrix
+                        })
+                    })
+                    .collect();
+
+                Some(create_buffer(matrices))
+            })
+            .collect();
+
+        InstanceBuffers {
+            instances: create_buffer(instance_matrices.to_vec()),
+            node_instances,
+        }
+    }
+
+    /// The index of the default scene, or of the first scene if no default scene is specified
+    pub fn default_scene_index(&self) -> usize {
+        self.document.default_scene()
+            .map(|default_scene| default_scene.index())
+            .unwrap_or(0)
+    }
+
     pub fn draw_main_scene(
         &self,
         command_buffer: AutoCommandBufferBuilder,
@@ -753,15 +1311,32 @@ impl Model {
         )?;
 
         let context = GltfDrawCallContext {
-            descriptor_set_map_instance: instance_context.descriptor_set_map_instance,
+            descriptor_set_maps_node: instance_context.descriptor_set_maps_node
+                .unwrap_or(&self.node_descriptor_sets[..]),
         };
 
         if let Some(ref draw_calls) = *draw_call_read_guard {
             for draw_call in draw_calls {
+                let node_index = draw_call.custom_data.node_index;
+
+                if let Some(node_visibility) = instance_context.node_visibility {
+                    if !node_visibility[node_index] {
+                        continue;
+                    }
+                }
+
+                if self.node_instance_matrices[node_index].as_ref().map(Vec::is_empty).unwrap_or(false) {
+                    continue;
+                }
+
+                let mut draw_call = draw_call.clone();
+
+                draw_call.vertex_source.push(instance_context.instance_buffers.get(node_index).clone());
+
                 command_buffer = GltfDrawCallIssuer::issue_draw_call(
                     command_buffer,
                     &instance_context.draw_context.dynamic,
-                    draw_call.clone(),
+                    draw_call,
                     &context,
                 );
             }
@@ -822,7 +1397,7 @@ impl Model {
         let scene = self.document.scenes().nth(scene_index).unwrap();
 
         for node in scene.nodes() {
-            self.create_draw_calls_node(node, draw_context, alpha_mode, subpass, &mut draw_call_accumulator);
+            self.create_draw_calls_node(node, draw_context, alpha_mode, subpass, &mut draw_call_accumulator)?;
         }
 
         Ok(draw_call_accumulator)
@@ -835,7 +1410,7 @@ impl Model {
         alpha_mode: AlphaMode,
         subpass: u8,
         draw_call_accumulator: &mut Vec<GltfContextLessDrawCall>,
-    ) {
+    ) -> Result<(), Error> {
         if let Some(mesh) = node.mesh() {
             for primitive in mesh.primitives() {
                 let material = primitive.material();
@@ -859,26 +1434,31 @@ impl Model {
 
                     let mut incomplete_descriptor_sets = GltfContextLessDescriptorSets {
                         descriptor_set_scene: pipeline.layout_dependent_resources.descriptor_set_scene.clone(),
-                        descriptor_set_instance: (),
-                        descriptor_set_node: self.node_descriptor_sets[node.index()].map[pipeline.layout.desc()].clone(),
+                        descriptor_set_node: (),
                         descriptor_set_material: material_descriptor_set,
                         descriptor_set_blend: None,
                     };
 
                     if let (AlphaMode::Blend, 3) = (alpha_mode, subpass) {
-                        incomplete_descriptor_sets.descriptor_set_blend
-                            = Some(pipeline.layout_dependent_resources.descriptor_sets_blend.as_ref()
-                                   .unwrap()[draw_context.view_swapchain_index].as_ref().unwrap().clone());
+                        let descriptor_set_blend = pipeline.layout_dependent_resources.descriptor_sets_blend
+                            .get(draw_context.view_swapchain_index)
+                            .and_then(Option::as_ref)
+                            .ok_or(ModelDrawError::MissingBlendDescriptorSet {
+                                view_swapchain_index: draw_context.view_swapchain_index,
+                            })?;
+
+                        incomplete_descriptor_sets.descriptor_set_blend = Some(descriptor_set_blend.clone());
                     }
 
                     let draw_call = self.create_draw_call_primitive(
+                        &node,
                         &mesh,
                         &primitive,
                         draw_context,
                         incomplete_descriptor_sets,
                         &pipeline.pipeline,
                         &pipeline.layout_dependent_resources.layout,
-                    );
+                    )?;
 
                     draw_call_accumulator.push(draw_call);
                 }
@@ -886,60 +1466,74 @@ impl Model {
         }
 
         for child in node.children() {
-            self.create_draw_calls_node(child, draw_context, alpha_mode, subpass, draw_call_accumulator);
+            self.create_draw_calls_node(child, draw_context, alpha_mode, subpass, draw_call_accumulator)?;
         }
+
+        Ok(())
     }
 
     fn create_draw_call_primitive<'a>(
         &self,
+        node: &Node<'a>,
         mesh: &Mesh<'a>,
         primitive: &Primitive<'a>,
         draw_context: &DrawContext,
         incomplete_descriptor_sets: GltfContextLessDescriptorSets,
         pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
         pipeline_layout: &Arc<PipelineLayout>,
-    ) -> GltfContextLessDrawCall {
+    ) -> Result<GltfContextLessDrawCall, Error> {
         let positions_accessor = primitive.get(&Semantic::Positions).unwrap();
         let normals_accessor = primitive.get(&Semantic::Normals);
         let tangents_accessor = primitive.get(&Semantic::Tangents);
-        // TODO: There may be multiple tex coord buffers per primitive
-        let tex_coords_accessor = primitive.get(&Semantic::TexCoords(0));
+        let tex_coords_0_accessor = primitive.get(&Semantic::TexCoords(0));
+        let tex_coords_1_accessor = primitive.get(&Semantic::TexCoords(1));
         let indices_accessor = primitive.indices();
-        // TODO: There may be multiple color buffers per primitive
+        // Only `COLOR_0` contributes to the material
         let vertex_color_accessor = primitive.get(&Semantic::Colors(0));
 
         let position_slice: BufferSlice<[GltfVertexPosition], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>>
-            = self.get_semantic_buffer_view(&positions_accessor);
+            = self.get_semantic_buffer_view(&positions_accessor)?;
 
         let normal_slice: BufferSlice<[GltfVertexNormal], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
-            let normal_slice: BufferSlice<[u8], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = normals_accessor.map(|normals_accessor| {
-                self.get_semantic_buffer_view(&normals_accessor)
-            }).unwrap_or_else(|| {
+            let normal_slice: BufferSlice<[u8], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = if let Some(normals_accessor) = normals_accessor {
+                self.get_semantic_buffer_view(&normals_accessor)?
+            } else {
                 let buffer = self.normal_buffers[mesh.index()][primitive.index()].as_ref()
                     .expect("No normals provided by the model and no normals were precomputed.");
 
                 BufferSlice::from_typed_buffer_access(buffer.clone())
-            });
+            };
 
             unsafe { normal_slice.reinterpret::<[GltfVertexNormal]>() }
         };
 
         let tangent_slice: BufferSlice<[GltfVertexTangent], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
-            let tangent_slice: BufferSlice<[u8], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = tangents_accessor.map(|tangents_accessor| {
-                self.get_semantic_buffer_view(&tangents_accessor)
-            }).unwrap_or_else(|| {
+            let tangent_slice: BufferSlice<[u8], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = if let Some(tangents_accessor) = tangents_accessor {
+                self.get_semantic_buffer_view(&tangents_accessor)?
+            } else {
                 let buffer = self.tangent_buffers[mesh.index()][primitive.index()].as_ref()
                     .expect("No tangents provided by the model and no tangents were precomputed.");
 
                 BufferSlice::from_typed_buffer_access(buffer.clone())
-            });
+            };
 
             unsafe { tangent_slice.reinterpret::<[GltfVertexTangent]>() }
         };
 
-        let tex_coord_slice: BufferSlice<[GltfVertexTexCoord], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
-            if let &Some(ref tex_coord_accessor) = &tex_coords_accessor {
-                self.get_semantic_buffer_view(tex_coord_accessor)
+        let tex_coord_0_slice: BufferSlice<[GltfVertexTexCoord], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
+            if let &Some(ref tex_coord_accessor) = &tex_coords_0_accessor {
+                self.get_semantic_buffer_view(tex_coord_accessor)?
+            } else {
+                let zero_buffer = draw_context.helper_resources.zero_buffer.clone();
+                let zero_buffer_slice = BufferSlice::from_typed_buffer_access(zero_buffer);
+
+                unsafe { zero_buffer_slice.reinterpret::<[GltfVertexTexCoord]>() }
+            }
+        };
+
+        let tex_coord_1_slice: BufferSlice<[GltfVertexTexCoord], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
+            if let &Some(ref tex_coord_accessor) = &tex_coords_1_accessor {
+                self.get_semantic_buffer_view(tex_coord_accessor)?
             } else {
                 let zero_buffer = draw_context.helper_resources.zero_buffer.clone();
                 let zero_buffer_slice = BufferSlice::from_typed_buffer_access(zero_buffer);
@@ -950,7 +1544,7 @@ impl Model {
 
         let vertex_color_slice: BufferSlice<[GltfVertexColor], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
             if let &Some(ref vertex_color_accessor) = &vertex_color_accessor {
-                self.get_semantic_buffer_view(vertex_color_accessor)
+                self.get_semantic_buffer_view(vertex_color_accessor)?
             } else {
                 let zero_buffer = draw_context.helper_resources.zero_buffer.clone();
                 let zero_buffer_slice = BufferSlice::from_typed_buffer_access(zero_buffer);
@@ -959,32 +1553,75 @@ impl Model {
             }
         };
 
+        let joint_buffer = self.joint_buffers[mesh.index()][primitive.index()].as_ref();
+        let weight_buffer = self.weight_buffers[mesh.index()][primitive.index()].as_ref();
+        let skinning_provided = node.skin().is_some() && joint_buffer.is_some() && weight_buffer.is_some();
+
+        let joints_slice: BufferSlice<[GltfVertexJoints], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
+            let buffer = joint_buffer.cloned()
+                .unwrap_or_else(|| draw_context.helper_resources.zero_buffer.clone());
+
+            unsafe { BufferSlice::from_typed_buffer_access(buffer).reinterpret::<[GltfVertexJoints]>() }
+        };
+
+        let weights_slice: BufferSlice<[GltfVertexWeights], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
+            let buffer = weight_buffer.cloned()
+                .unwrap_or_else(|| draw_context.helper_resources.zero_buffer.clone());
+
+            unsafe { BufferSlice::from_typed_buffer_access(buffer).reinterpret::<[GltfVertexWeights]>() }
+        };
+
         let vertex_buffers = GltfVertexBuffers {
             position_buffer: Some(Arc::new(position_slice)),
             normal_buffer: Some(Arc::new(normal_slice)),
             tangent_buffer: Some(Arc::new(tangent_slice)),
-            tex_coord_buffer: Some(Arc::new(tex_coord_slice)),
+            tex_coord_0_buffer: Some(Arc::new(tex_coord_0_slice)),
+            tex_coord_1_buffer: Some(Arc::new(tex_coord_1_slice)),
             vertex_color_buffer: Some(Arc::new(vertex_color_slice)),
+            joints_buffer: Some(Arc::new(joints_slice)),
+            weights_buffer: Some(Arc::new(weights_slice)),
+            // Provided by `Model::draw_scene`, as it depends on the instances being drawn
+            instance_buffer: None,
+        };
+
+        let morph_target_count = if self.morph_target_buffers[mesh.index()].is_some() {
+            primitive.morph_targets().count() as u32
+        } else {
+            0
         };
 
         let push_constants = PushConstants::new(
             vertex_color_accessor.is_some(),
+            skinning_provided,
+            morph_target_count,
+            self.morph_target_offsets[mesh.index()][primitive.index()],
         );
 
-        let buffers = if let Some(indices_accessor) = indices_accessor {
+        let line_loop_index_buffer = self.line_loop_index_buffers[mesh.index()][primitive.index()].as_ref();
+
+        let buffers = if let Some(line_loop_index_buffer) = line_loop_index_buffer {
+            ContextLessDrawCallBuffers::Indexed {
+                index_buffer: DynamicIndexBuffer::U32(line_loop_index_buffer.clone()),
+            }
+        } else if let Some(indices_accessor) = indices_accessor {
             macro_rules! reinterpret_index_buffer_as_dynamic {
                 ($index_type:ty, $index_ident:ident; $indices_accessor:ident) => {{
                     // FIXME: Isn't there a helper function to use?
                     let index_slice: BufferSlice<[$index_type], Arc<dyn TypedBufferAccess<Content=[u8]> + Send + Sync>> = {
-                        let buffer_view = $indices_accessor.view();
+                        let buffer_view = $indices_accessor.view()
+                            .ok_or(ModelDrawError::MissingBufferView { accessor_index: $indices_accessor.index() })?;
                         let buffer_index = buffer_view.buffer().index();
                         let buffer_offset = $indices_accessor.offset() + buffer_view.offset();
                         let buffer_bytes = $indices_accessor.size() * $indices_accessor.count();
 
-                        let index_buffer = self.device_buffers[buffer_index].clone();
+                        let index_buffer = self.device_buffers[buffer_index].clone()
+                            .ok_or(ModelDrawError::MissingDeviceBuffer { buffer_index })?;
                         let index_slice = BufferSlice::from_typed_buffer_access(index_buffer)
                             .slice(buffer_offset..(buffer_offset + buffer_bytes))
-                            .unwrap();
+                            .ok_or(ModelImportError::AccessorOutOfBounds {
+                                accessor_index: $indices_accessor.index(),
+                                view_index: buffer_view.index(),
+                            })?;
 
                         unsafe { index_slice.reinterpret::<[$index_type]>() }
                     };
@@ -1002,9 +1639,8 @@ impl Model {
             let index_buffer = match indices_accessor.data_type() {
                 DataType::U8 => {
                     let index_buffer = self.converted_index_buffers_by_accessor_index[indices_accessor.index()]
-                        .as_ref()
-                        .expect("Could not access a pre-generated `u16` index buffer, maybe it was not generated?")
-                        .clone();
+                        .clone()
+                        .ok_or(ModelDrawError::MissingConvertedIndexBuffer { accessor_index: indices_accessor.index() })?;
 
                     DynamicIndexBuffer::U16(index_buffer)
                 },
@@ -1014,9 +1650,12 @@ impl Model {
                 DataType::U32 => {
                     reinterpret_index_buffer_as_dynamic!(u32, U32; indices_accessor)
                 },
-                _ => {
-                    panic!("Index type not supported.");
-                },
+                data_type => return Err(ModelImportError::UnsupportedIndexType {
+                    mesh_index: mesh.index(),
+                    primitive_index: primitive.index(),
+                    accessor_index: indices_accessor.index(),
+                    data_type,
+                }.into()),
             };
 
             ContextLessDrawCallBuffers::Indexed {
@@ -1026,7 +1665,7 @@ impl Model {
             ContextLessDrawCallBuffers::Simple
         };
 
-        ContextLessDrawCall {
+        Ok(ContextLessDrawCall {
             pipeline: pipeline.clone(),
             pipeline_layout: pipeline_layout.clone(),
             vertex_source: vertex_buffers.get_individual_buffers(),
@@ -1034,8 +1673,9 @@ impl Model {
             custom_data: GltfContextLessDrawCallCustomData {
                 incomplete_descriptor_sets,
                 push_constants,
+                node_index: node.index(),
             }
-        }
+        })
     }
 }
 
@@ -1165,3 +1805,46 @@ impl Model {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(mode: Mode, vertex_count: usize) -> Vec<[usize; 3]> {
+        get_primitive_triangles(mode, vertex_count).collect()
+    }
+
+    #[test]
+    fn primitive_triangles_list() {
+        assert_eq!(triangles(Mode::Triangles, 7), vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(triangles(Mode::Triangles, 2), Vec::<[usize; 3]>::new());
+    }
+
+    #[test]
+    fn primitive_triangles_strip() {
+        // Every other triangle has its winding flipped back
+        assert_eq!(triangles(Mode::TriangleStrip, 5), vec![[0, 1, 2], [1, 3, 2], [2, 3, 4]]);
+        assert_eq!(triangles(Mode::TriangleStrip, 2), Vec::<[usize; 3]>::new());
+        assert_eq!(triangles(Mode::TriangleStrip, 0), Vec::<[usize; 3]>::new());
+    }
+
+    #[test]
+    fn primitive_triangles_fan() {
+        assert_eq!(triangles(Mode::TriangleFan, 5), vec![[1, 2, 0], [2, 3, 0], [3, 4, 0]]);
+        assert_eq!(triangles(Mode::TriangleFan, 2), Vec::<[usize; 3]>::new());
+        assert_eq!(triangles(Mode::TriangleFan, 0), Vec::<[usize; 3]>::new());
+    }
+
+    #[test]
+    fn primitive_triangles_points_and_lines() {
+        for &mode in &[Mode::Points, Mode::Lines, Mode::LineLoop, Mode::LineStrip] {
+            assert_eq!(triangles(mode, 6), Vec::<[usize; 3]>::new());
+        }
+    }
+
+    #[test]
+    fn line_loop_strip_indices() {
+        assert_eq!(get_line_loop_strip_indices(vec![4, 5, 6]), vec![4, 5, 6, 4]);
+        assert_eq!(get_line_loop_strip_indices(vec![]), Vec::<u32>::new());
+    }
+}
@@ -0,0 +1,180 @@
+use gltf::Node;
+use ammolite_math::*;
+use crate::model::{Model, default_morph_weights};
+
+/// The local transformation of a node, decomposed into translation, rotation and scale
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vec3,
+    /// A unit quaternion in the `[x, y, z, w]` order
+    pub rotation: Vec4,
+    pub scale: Vec3,
+}
+
+impl NodeTransform {
+    pub fn from_node(node: &Node) -> Self {
+        let (translation, rotation, scale) = node.transform().decomposed();
+
+        Self {
+            translation: translation.into(),
+            rotation: rotation.into(),
+            scale: scale.into(),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        let rotation = Mat3::from_quaternion(self.rotation.0);
+        let mut matrix = Mat4::IDENTITY;
+
+        for column in 0..3 {
+            for row in 0..3 {
+                matrix[column][row] = rotation[column][row] * self.scale[column];
+            }
+
+            matrix[3][column] = self.translation[column];
+        }
+
+        matrix
+    }
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: [0.0, 0.0, 0.0, 1.0].into(),
+            scale: [1.0, 1.0, 1.0].into(),
+        }
+    }
+}
+
+/// The state of the node hierarchy of a single model instance.
+///
+/// A `Pose` is modified by `AnimationPlayer`s or directly, and is passed to the renderer via
+/// `WorldSpaceModel::pose` to draw the instance in that pose. Nodes can be found by name using
+/// `Model::find_node_index`.
+#[derive(Clone, Debug)]
+pub struct Pose {
+    local_transforms: Vec<NodeTransform>,
+    /// Morph target weights of each node, empty for nodes without a mesh
+    morph_weights: Vec<Vec<f32>>,
+    /// Whether each node is visible, disregarding its ancestors
+    visible: Vec<bool>,
+    world_matrices: Vec<Mat4>,
+    /// Whether each node and all of its ancestors are visible
+    world_visibility: Vec<bool>,
+}
+
+impl Pose {
+    /// Creates a pose with all nodes in their rest transformations.
+    pub fn new(model: &Model) -> Self {
+        let document = model.document();
+        let mut result = Self {
+            local_transforms: vec![NodeTransform::default(); document.nodes().len()],
+            morph_weights: vec![Vec::new(); document.nodes().len()],
+            visible: vec![true; document.nodes().len()],
+            world_matrices: model.node_transform_matrices().to_vec(),
+            world_visibility: vec![true; document.nodes().len()],
+        };
+
+        result.reset(model);
+        result
+    }
+
+    /// Resets all nodes to their rest transformations and makes them visible.
+    pub fn reset(&mut self, model: &Model) {
+        for node in model.document().nodes() {
+            self.local_transforms[node.index()] = NodeTransform::from_node(&node);
+            self.morph_weights[node.index()] = default_morph_weights(&node);
+        }
+
+        for visible in self.visible.iter_mut().chain(self.world_visibility.iter_mut()) {
+            *visible = true;
+        }
+
+        self.world_matrices.clone_from_slice(model.node_transform_matrices());
+    }
+
+    /// Resets the local transformation of a single node to its rest transformation.
+    pub fn reset_local_transform(&mut self, model: &Model, node_index: usize) {
+        let node = model.document().nodes().nth(node_index)
+            .expect("Invalid node index.");
+
+        self.local_transforms[node_index] = NodeTransform::from_node(&node);
+    }
+
+    pub fn local_transforms(&self) -> &[NodeTransform] {
+        &self.local_transforms[..]
+    }
+
+    pub fn local_transform(&self, node_index: usize) -> &NodeTransform {
+        &self.local_transforms[node_index]
+    }
+
+    pub fn local_transform_mut(&mut self, node_index: usize) -> &mut NodeTransform {
+        &mut self.local_transforms[node_index]
+    }
+
+    pub fn set_local_transform(&mut self, node_index: usize, transform: NodeTransform) {
+        self.local_transforms[node_index] = transform;
+    }
+
+    pub fn is_visible(&self, node_index: usize) -> bool {
+        self.visible[node_index]
+    }
+
+    /// Hiding a node hides all of its descendants as well.
+    pub fn set_visible(&mut self, node_index: usize, visible: bool) {
+        self.visible[node_index] = visible;
+    }
+
+    pub fn morph_weights(&self, node_index: usize) -> &[f32] {
+        &self.morph_weights[node_index][..]
+    }
+
+    pub fn morph_weights_mut(&mut self, node_index: usize) -> &mut [f32] {
+        &mut self.morph_weights[node_index][..]
+    }
+
+    /// The world matrices of nodes, as of the last call to `Pose::update_world_matrices`.
+    pub fn world_matrices(&self) -> &[Mat4] {
+        &self.world_matrices[..]
+    }
+
+    /// Whether nodes and all of their ancestors are visible, as of the last call to
+    /// `Pose::update_world_matrices`.
+    pub fn world_visibility(&self) -> &[bool] {
+        &self.world_visibility[..]
+    }
+
+    fn update_world_matrices_impl(&mut self, parent: Option<(&Mat4, bool)>, node: &Node) {
+        let local_matrix = self.local_transforms[node.index()].matrix();
+        let visible = self.visible[node.index()];
+
+        if let Some((parent_matrix, parent_visible)) = parent {
+            self.world_matrices[node.index()] = parent_matrix * local_matrix;
+            self.world_visibility[node.index()] = parent_visible && visible;
+        } else {
+            self.world_matrices[node.index()] = local_matrix;
+            self.world_visibility[node.index()] = visible;
+        }
+
+        let world_matrix = self.world_matrices[node.index()].clone();
+        let world_visible = self.world_visibility[node.index()];
+
+        for child in node.children() {
+            self.update_world_matrices_impl(Some((&world_matrix, world_visible)), &child);
+        }
+    }
+
+    /// Recomputes the world matrices and visibility of all nodes from their local
+    /// transformations and visibility.
+    /// Must be called after modifying the local transformations or visibility, before rendering.
+    pub fn update_world_matrices(&mut self, model: &Model) {
+        for scene in model.document().scenes() {
+            for node in scene.nodes() {
+                self.update_world_matrices_impl(None, &node);
+            }
+        }
+    }
+}
@@ -41,10 +41,11 @@ use vulkano::pipeline::GraphicsPipeline;
 use vulkano::pipeline::depth_stencil::DepthStencil;
 use vulkano::pipeline::depth_stencil::Compare;
 use vulkano::pipeline::depth_stencil::DepthBounds;
+use vulkano::pipeline::input_assembly::PrimitiveTopology;
 
 
 use gltf::material::Material;
-use gltf::mesh::Primitive;
+use gltf::mesh::{Primitive, Mode};
 use failure::Error;
 use fnv::FnvBuildHasher;
 use crate::ViewSwapchain;
@@ -53,6 +54,7 @@ use crate::model::{FramebufferWithClearValues, HelperResources};
 use crate::model::resource::{InitializationTask, UninitializedResource, SimpleUninitializedResource};
 use crate::buffer::StagedBuffer;
 use crate::iter::ArrayIterator;
+use crate::sampler::IntoVulkanEquivalent;
 use crate::shaders::*;
 
 #[derive(PartialEq, Eq)]
@@ -121,9 +123,57 @@ impl Not for GraphicsPipelineFlags {
     }
 }
 
+/// The topology a primitive is assembled with, one for each `gltf::mesh::Mode`.
+/// `LINE_LOOP` primitives are drawn as line strips with their first index repeated.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PrimitiveMode {
+    Points,
+    Lines,
+    LineStrip,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Default for PrimitiveMode {
+    fn default() -> Self {
+        PrimitiveMode::Triangles
+    }
+}
+
+impl From<Mode> for PrimitiveMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Points => PrimitiveMode::Points,
+            Mode::Lines => PrimitiveMode::Lines,
+            Mode::LineLoop => PrimitiveMode::LineStrip,
+            Mode::LineStrip => PrimitiveMode::LineStrip,
+            Mode::Triangles => PrimitiveMode::Triangles,
+            Mode::TriangleStrip => PrimitiveMode::TriangleStrip,
+            Mode::TriangleFan => PrimitiveMode::TriangleFan,
+        }
+    }
+}
+
+impl IntoVulkanEquivalent for PrimitiveMode {
+    type Output = PrimitiveTopology;
+
+    fn into_vulkan_equivalent(self) -> Self::Output {
+        match self {
+            PrimitiveMode::Points => PrimitiveTopology::PointList,
+            PrimitiveMode::Lines => PrimitiveTopology::LineList,
+            PrimitiveMode::LineStrip => PrimitiveTopology::LineStrip,
+            PrimitiveMode::Triangles => PrimitiveTopology::TriangleList,
+            PrimitiveMode::TriangleStrip => PrimitiveTopology::TriangleStrip,
+            PrimitiveMode::TriangleFan => PrimitiveTopology::TriangleFan,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct GraphicsPipelineProperties {
     flags: GraphicsPipelineFlags,
+    primitive_mode: PrimitiveMode,
     vertex_attribute_properties_set: VertexAttributePropertiesSet,
 }
 
@@ -131,6 +181,7 @@ impl GraphicsPipelineProperties {
     pub fn from<'a>(primitive: &Primitive<'a>, material: &Material<'a>) -> Self {
         GraphicsPipelineProperties {
             flags: material.into(),
+            primitive_mode: primitive.mode().into(),
             vertex_attribute_properties_set: primitive.into(),
         }
     }
@@ -148,8 +199,11 @@ pub struct SharedGltfGraphicsPipelineResources {
     device: Arc<Device>,
     pub helper_resources: HelperResources,
     pub scene_ubo_buffer: StagedBuffer<SceneUBO>,
+    pub lights_ubo_buffer: StagedBuffer<LightsUBO>,
     pub default_material_ubo_buffer: Arc<ImmutableBuffer<MaterialUBO>>,
-    pub swapchain_dependent_resources: Vec<Option<SwapchainDependentResources>>,
+    /// The attachments of each view swapchain. Shared by all clones, so that pipelines created on
+    /// other threads, such as by `ModelLoadingHandle`, refer to the current attachments.
+    pub swapchain_dependent_resources: Arc<RwLock<Vec<Option<SwapchainDependentResources>>>>,
 }
 
 impl SharedGltfGraphicsPipelineResources {
@@ -162,6 +216,12 @@ impl SharedGltfGraphicsPipelineResources {
             BufferUsage::uniform_buffer(),
             scene_ubo.clone(),
         );
+        let lights_ubo_buffer = StagedBuffer::from_data(
+            &device,
+            queue_family,
+            BufferUsage::uniform_buffer(),
+            LightsUBO::default(),
+        );
         let (device_default_material_ubo_buffer, default_material_ubo_buffer_initialization) = unsafe {
             ImmutableBuffer::<MaterialUBO>::uninitialized(
                 device.clone(),
@@ -179,8 +239,9 @@ impl SharedGltfGraphicsPipelineResources {
             device,
             helper_resources,
             scene_ubo_buffer,
+            lights_ubo_buffer,
             default_material_ubo_buffer: device_default_material_ubo_buffer,
-            swapchain_dependent_resources: vec![None; view_swapchains.len()],
+            swapchain_dependent_resources: Arc::new(RwLock::new(vec![None; view_swapchains.len()])),
         }, tasks))
     }
 
@@ -190,7 +251,9 @@ impl SharedGltfGraphicsPipelineResources {
                                             view_swapchain: &ViewSwapchain)
             -> Vec<Arc<dyn FramebufferWithClearValues<Vec<ClearValue>>>> {
         let render_pass = &render_pass;
-        let resources = self.swapchain_dependent_resources[view_swapchain_index]
+        let swapchain_dependent_resources = self.swapchain_dependent_resources.read()
+            .expect("Swapchain dependent resources poisoned.");
+        let resources = swapchain_dependent_resources[view_swapchain_index]
             .as_ref().expect("Framebuffer swapchain dependent images not initialized.");
         view_swapchain.swapchain.images().iter().map(|image| {
             Arc::new(Framebuffer::start(render_pass.clone())
@@ -241,7 +304,7 @@ impl SharedGltfGraphicsPipelineResources {
         view_swapchain: &ViewSwapchain,
     ) -> Result<(), Error> {
         let dimensions = view_swapchain.swapchain.dimensions();
-        self.swapchain_dependent_resources[view_swapchain_index] = Some(SwapchainDependentResources {
+        let resources = SwapchainDependentResources {
             depth_image: self.construct_attachment_image_view(
                 dimensions.clone(),
                 D32Sfloat,
@@ -270,7 +333,10 @@ impl SharedGltfGraphicsPipelineResources {
                     .. ImageUsage::none()
                 },
             )?,
-        });
+        };
+
+        self.swapchain_dependent_resources.write()
+            .expect("Swapchain dependent resources poisoned.")[view_swapchain_index] = Some(resources);
 
         Ok(())
     }
@@ -348,8 +414,9 @@ impl DescriptorSetMap {
 pub struct GltfPipelineLayoutDependentResources {
     pub layout: Arc<PipelineLayout>,
     pub descriptor_set_scene: Arc<dyn DescriptorSet + Send + Sync>,
-    pub descriptor_set_pool_instance: Arc<Mutex<FixedSizeDescriptorSetsPool>>,
-    pub descriptor_sets_blend: Option<Vec<Option<Arc<dyn DescriptorSet + Send + Sync>>>>,
+    pub descriptor_set_pool_node: Arc<Mutex<FixedSizeDescriptorSetsPool>>,
+    /// The blend descriptor set of each view swapchain, if the layout has one
+    pub descriptor_sets_blend: Vec<Option<Arc<dyn DescriptorSet + Send + Sync>>>,
     pub default_material_descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
 }
 
@@ -359,13 +426,14 @@ impl GltfPipelineLayoutDependentResources {
         let descriptor_set_scene = Arc::new(
             PersistentDescriptorSet::start(layout.clone(), 0)
                 .add_buffer(shared_resources.scene_ubo_buffer.device_buffer().clone()).unwrap()
+                .add_buffer(shared_resources.lights_ubo_buffer.device_buffer().clone()).unwrap()
                 .build().unwrap()
         );
-        let descriptor_set_pool_instance = Arc::new(Mutex::new(
+        let descriptor_set_pool_node = Arc::new(Mutex::new(
             FixedSizeDescriptorSetsPool::new(layout.clone(), 1)
         ));
         let default_material_descriptor_set: Arc<dyn DescriptorSet + Send + Sync> = Arc::new(
-            PersistentDescriptorSet::start(layout.clone(), 3)
+            PersistentDescriptorSet::start(layout.clone(), 2)
                 .add_buffer(shared_resources.default_material_ubo_buffer.clone()).unwrap()
                 .add_image(shared_resources.helper_resources.empty_image.clone()).unwrap()
                 .add_sampler(shared_resources.helper_resources.cheapest_sampler.clone()).unwrap()
@@ -383,29 +451,49 @@ impl GltfPipelineLayoutDependentResources {
         Self {
             layout,
             descriptor_set_scene,
-            descriptor_set_pool_instance,
-            descriptor_sets_blend: None, // late init with `reconstruct_descriptor_sets`
+            descriptor_set_pool_node,
+            descriptor_sets_blend: Self::construct_descriptor_sets_blend(&layout, shared_resources),
             default_material_descriptor_set,
         }
     }
 
+    /// Creates the blend descriptor set of each view swapchain whose swapchain dependent
+    /// resources were initialized already. The rest are initialized by
+    /// `reconstruct_descriptor_sets`.
+    pub fn construct_descriptor_sets_blend(layout: &Arc<PipelineLayout>, shared_resources: &SharedGltfGraphicsPipelineResources)
+            -> Vec<Option<Arc<dyn DescriptorSet + Send + Sync>>> {
+        let swapchain_dependent_resources = shared_resources.swapchain_dependent_resources.read()
+            .expect("Swapchain dependent resources poisoned.");
+
+        swapchain_dependent_resources.iter().map(|swapchain_resources| {
+            let swapchain_resources = swapchain_resources.as_ref()?;
+
+            layout.descriptor_set_layout(3).map(|_| {
+                Arc::new(PersistentDescriptorSet::start(layout.clone(), 3)
+                    .add_image(swapchain_resources.blend_accumulation_image.clone()).unwrap()
+                    .add_image(swapchain_resources.blend_revealage_image.clone()).unwrap()
+                    .build().unwrap()) as Arc<dyn DescriptorSet + Send + Sync>
+            })
+        }).collect()
+    }
+
     pub fn reconstruct_descriptor_sets(&mut self, shared_resources: &SharedGltfGraphicsPipelineResources, view_swapchains_len: usize, view_swapchain_index: usize, view_swapchain: &ViewSwapchain) {
         self.descriptor_set_scene = Arc::new(
             PersistentDescriptorSet::start(self.layout.clone(), 0)
                 .add_buffer(shared_resources.scene_ubo_buffer.device_buffer().clone()).unwrap()
+                .add_buffer(shared_resources.lights_ubo_buffer.device_buffer().clone()).unwrap()
                 .build().unwrap()
         );
 
-        if self.descriptor_sets_blend.is_none() {
-            self.descriptor_sets_blend = Some(vec![None; view_swapchains_len]);
-        }
+        let swapchain_dependent_resources = shared_resources.swapchain_dependent_resources.read()
+            .expect("Swapchain dependent resources poisoned.");
 
-        self.descriptor_sets_blend.as_mut().unwrap()[view_swapchain_index] = self.layout.descriptor_set_layout(4).map(|_| {
-            let swapchain_resources = shared_resources
-                .swapchain_dependent_resources[view_swapchain_index]
+        self.descriptor_sets_blend.resize(view_swapchains_len, None);
+        self.descriptor_sets_blend[view_swapchain_index] = self.layout.descriptor_set_layout(3).map(|_| {
+            let swapchain_resources = swapchain_dependent_resources[view_swapchain_index]
                 .as_ref().expect("Swapchain dependent resources not initialized.");
 
-            Arc::new(PersistentDescriptorSet::start(self.layout.clone(), 4)
+            Arc::new(PersistentDescriptorSet::start(self.layout.clone(), 3)
                 .add_image(swapchain_resources.blend_accumulation_image.clone()).unwrap()
                 .add_image(swapchain_resources.blend_revealage_image.clone()).unwrap()
                 .build().unwrap()) as Arc<dyn DescriptorSet + Send + Sync>
@@ -433,10 +521,11 @@ impl GraphicsPipelineSet {
 }
 
 // Consider improving the synchronization data type
+#[derive(Clone)]
 pub struct GraphicsPipelineSetCache {
     pub pipeline_map: Arc<RwLock<HashMap<GraphicsPipelineProperties, GraphicsPipelineSet>>>,
     pub shared_resources: SharedGltfGraphicsPipelineResources,
-    // TODO: Since the hashmap no longer has weak keys, the resources don't get freed automatically
+    // Since the hashmap no longer has weak keys, the resources are freed by `evict_unused`
     pub pipeline_layout_dependent_resources: Arc<RwLock<HashMap<
                                                  PipelineLayoutDescAggregation,
                                                  GltfPipelineLayoutDependentResources
@@ -445,9 +534,35 @@ pub struct GraphicsPipelineSetCache {
     //                                              Weak<dyn PipelineLayoutDesc + Send + Sync>,
     //                                              GltfPipelineLayoutDependentResources
     //                                          >>>,
+    /// The number of models using each pipeline set, see `GraphicsPipelineSetUsage`
+    pub pipeline_usage_counts: Arc<Mutex<HashMap<GraphicsPipelineProperties, usize>>>,
     pub device: Arc<Device>,
     pub render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
-    pub vertex_shader: gltf_vert::Shader, // Stored here to avoid unnecessary reloading
+    pub vertex_shader: Arc<gltf_vert::Shader>, // Stored here to avoid unnecessary reloading
+}
+
+/// Marks pipeline sets as used, which keeps `GraphicsPipelineSetCache::evict_unused` from evicting
+/// them until the usage is dropped. Held by the models drawn with the pipelines.
+pub struct GraphicsPipelineSetUsage {
+    properties: Vec<GraphicsPipelineProperties>,
+    usage_counts: Arc<Mutex<HashMap<GraphicsPipelineProperties, usize>>>,
+}
+
+impl Drop for GraphicsPipelineSetUsage {
+    fn drop(&mut self) {
+        let mut usage_counts = self.usage_counts.lock()
+            .expect("The pipeline usage counts became poisoned.");
+
+        for properties in &self.properties {
+            if let Entry::Occupied(mut entry) = usage_counts.entry(properties.clone()) {
+                *entry.get_mut() -= 1;
+
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
 }
 
 macro_rules! cache_layout {
@@ -456,7 +571,16 @@ macro_rules! cache_layout {
             .as_ref().write().expect("Layout dependent resources poisoned.");
         let layout_desc = $builder.construct_layout_desc(&[]).unwrap();
         let (layout, resources) = if let Some(resources) = resources_map.get(&layout_desc) {
-            (resources.layout.clone(), resources.clone())
+            let mut resources = resources.clone();
+
+            // The cached blend descriptor sets may refer to attachments of swapchains which have
+            // been recreated since
+            resources.descriptor_sets_blend = GltfPipelineLayoutDependentResources::construct_descriptor_sets_blend(
+                &resources.layout,
+                &$cache.shared_resources,
+            );
+
+            (resources.layout.clone(), resources)
         } else {
             let layout: Arc<PipelineLayout> = layout_desc.clone()
                 .build($cache.device.clone()).unwrap();
@@ -614,10 +738,11 @@ impl GraphicsPipelineSetCache {
                     shared_resources,
                     pipeline_layout_dependent_resources: Arc::new(RwLock::new(HashMap::new())),
                     // pipeline_layout_dependent_resources: Arc::new(RwLock::new(WeakKeyHashMap::new())),
+                    pipeline_usage_counts: Arc::new(Mutex::new(HashMap::new())),
                     device: device.clone(),
                     render_pass: Self::create_render_pass(&device, swapchain_format),
-                    vertex_shader: gltf_vert::Shader::load(device.clone())
-                        .expect("Failed to create shader module."),
+                    vertex_shader: Arc::new(gltf_vert::Shader::load(device.clone())
+                        .expect("Failed to create shader module.")),
                 };
 
                 // result.create_pipeline(&GraphicsPipelineProperties::default());
@@ -691,6 +816,51 @@ impl GraphicsPipelineSetCache {
         }.expect("Could not create a render pass."))
     }
 
+    /// Marks the pipeline sets with the given properties as used, until the returned usage is
+    /// dropped.
+    pub fn use_pipelines(&self, properties: impl IntoIterator<Item=GraphicsPipelineProperties>) -> GraphicsPipelineSetUsage {
+        let mut usage_counts = self.pipeline_usage_counts.lock()
+            .expect("The pipeline usage counts became poisoned.");
+        let mut used_properties: Vec<GraphicsPipelineProperties> = Vec::new();
+
+        for properties in properties {
+            if !used_properties.contains(&properties) {
+                *usage_counts.entry(properties.clone()).or_insert(0) += 1;
+                used_properties.push(properties);
+            }
+        }
+
+        GraphicsPipelineSetUsage {
+            properties: used_properties,
+            usage_counts: self.pipeline_usage_counts.clone(),
+        }
+    }
+
+    /// Removes pipelines which are not used by any model, see `use_pipelines`, along with the
+    /// layout dependent resources no remaining pipeline uses. Evicted pipelines are recreated
+    /// when needed again.
+    pub fn evict_unused(&self) {
+        let mut pipeline_map = self.pipeline_map
+            .as_ref()
+            .write()
+            .expect("The Graphics Pipeline Cache became poisoned.");
+        let mut resources_map = self.pipeline_layout_dependent_resources
+            .as_ref()
+            .write()
+            .expect("Layout dependent resources poisoned.");
+        let usage_counts = self.pipeline_usage_counts.lock()
+            .expect("The pipeline usage counts became poisoned.");
+
+        pipeline_map.retain(|properties, _| usage_counts.contains_key(properties));
+
+        let used_layout_descs: Vec<&PipelineLayoutDescAggregation> = pipeline_map.values()
+            .flat_map(|pipeline_set| pipeline_set.iter())
+            .map(|pipeline| pipeline.layout.desc())
+            .collect();
+
+        resources_map.retain(|layout_desc, _| used_layout_descs.contains(&layout_desc));
+    }
+
     pub fn get_pipeline(&self, properties: &GraphicsPipelineProperties) -> Option<GraphicsPipelineSet> {
         self.pipeline_map
             .as_ref()
@@ -728,6 +898,9 @@ impl GraphicsPipelineSetCache {
             builder = builder.cull_mode_back();
         }
 
+        // Held while the blend descriptor sets are created. When swapchains are recreated, the
+        // descriptor sets of the pipelines in the map are reconstructed after the attachments, so
+        // the new pipelines either refer to the new attachments or are reconstructed as well.
         let mut pipeline_map = self.pipeline_map
             .as_ref()
             .write()
@@ -738,6 +911,7 @@ impl GraphicsPipelineSetCache {
         };
 
         let builder = builder
+            .primitive_topology(properties.primitive_mode.into_vulkan_equivalent())
             .vertex_input(vertex_input)
             .vertex_shader(self.vertex_shader.main_entry_point(), ())
             // Configures the builder so that we use one viewport, and that the state of this viewport
@@ -39,8 +39,10 @@ impl IntoVulkanEquivalent for MinFilter {
 
     fn into_vulkan_equivalent(self) -> Self::Output {
         match self {
-            MinFilter::Nearest => (Filter::Nearest, MipmapMode::Linear),
-            MinFilter::Linear => (Filter::Linear, MipmapMode::Linear),
+            // Mipmapping of non-mipmapped filters is disabled by clamping the LOD, see
+            // `create_samplers`
+            MinFilter::Nearest => (Filter::Nearest, MipmapMode::Nearest),
+            MinFilter::Linear => (Filter::Linear, MipmapMode::Nearest),
             MinFilter::NearestMipmapNearest => (Filter::Nearest, MipmapMode::Nearest),
             MinFilter::LinearMipmapNearest => (Filter::Linear, MipmapMode::Nearest),
             MinFilter::NearestMipmapLinear => (Filter::Nearest, MipmapMode::Linear),
@@ -38,6 +38,31 @@ use ammolite_math::vector::*;
 
 pub use crate::shaders::gltf_opaque_frag::ty::*;
 
+/// The maximum number of lights passed to the shaders, must match `MAX_LIGHTS` in
+/// `gltf_common_uniforms.h`
+pub const MAX_LIGHTS: usize = 16;
+
+/// The UV set and the `KHR_texture_transform` a texture is sampled with
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub tex_coord: u32,
+    pub offset: Vec2,
+    /// Counter-clockwise rotation, in radians
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            tex_coord: 0,
+            offset: [0.0, 0.0].into(),
+            rotation: 0.0,
+            scale: [1.0, 1.0].into(),
+        }
+    }
+}
+
 impl SceneUBO {
     pub fn new(time_elapsed: f32, dimensions: Vec2, camera_position: Vec3, view: Mat4, projection: Mat4) -> SceneUBO {
         SceneUBO {
@@ -64,22 +89,67 @@ impl Default for SceneUBO {
     }
 }
 
-impl InstanceUBO {
-    pub fn new(model: Mat4) -> InstanceUBO {
-        InstanceUBO {
-            model: model.into_inner(),
+impl LightData {
+    pub fn new(
+        kind: u32,
+        position: Vec3,
+        direction: Vec3,
+        range: f32,
+        color: Vec3,
+        intensity: f32,
+        inner_cone_cos: f32,
+        outer_cone_cos: f32,
+    ) -> LightData {
+        LightData {
+            position: position.0,
+            kind,
+            direction: direction.0,
+            range,
+            color: color.0,
+            intensity,
+            inner_cone_cos,
+            outer_cone_cos,
+            _padding: Default::default(),
         }
     }
 }
 
-impl Default for InstanceUBO {
+impl Default for LightData {
     fn default() -> Self {
         Self::new(
-            Mat4::IDENTITY,
+            0,
+            [0.0, 0.0, 0.0].into(),
+            [0.0, 0.0, -1.0].into(),
+            0.0,
+            [0.0, 0.0, 0.0].into(),
+            0.0,
+            1.0,
+            0.0,
         )
     }
 }
 
+impl LightsUBO {
+    /// Lights exceeding `MAX_LIGHTS` are ignored.
+    pub fn new(lights: &[LightData]) -> LightsUBO {
+        let light_count = lights.len().min(MAX_LIGHTS);
+        let mut result = LightsUBO {
+            light_count: light_count as u32,
+            lights: [LightData::default(); MAX_LIGHTS],
+            _dummy0: Default::default(),
+        };
+
+        result.lights[..light_count].copy_from_slice(&lights[..light_count]);
+        result
+    }
+}
+
+impl Default for LightsUBO {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
 impl NodeUBO {
     pub fn new(matrix: Mat4) -> NodeUBO {
         NodeUBO {
@@ -109,6 +179,12 @@ impl MaterialUBO {
         occlusion_strength: f32,
         emissive_texture_provided: bool,
         emissive_factor: Vec3,
+        base_color_texture_transform: TextureTransform,
+        metallic_roughness_texture_transform: TextureTransform,
+        normal_texture_transform: TextureTransform,
+        occlusion_texture_transform: TextureTransform,
+        emissive_texture_transform: TextureTransform,
+        unlit: bool,
     ) -> Self {
         MaterialUBO {
             alpha_cutoff,
@@ -122,6 +198,27 @@ impl MaterialUBO {
             occlusion_strength,
             emissive_texture_provided: emissive_texture_provided as u32,
             emissive_factor: emissive_factor.0,
+            base_color_texture_tex_coord: base_color_texture_transform.tex_coord,
+            metallic_roughness_texture_tex_coord: metallic_roughness_texture_transform.tex_coord,
+            normal_texture_tex_coord: normal_texture_transform.tex_coord,
+            occlusion_texture_tex_coord: occlusion_texture_transform.tex_coord,
+            emissive_texture_tex_coord: emissive_texture_transform.tex_coord,
+            base_color_texture_uv_offset: base_color_texture_transform.offset.0,
+            metallic_roughness_texture_uv_offset: metallic_roughness_texture_transform.offset.0,
+            normal_texture_uv_offset: normal_texture_transform.offset.0,
+            occlusion_texture_uv_offset: occlusion_texture_transform.offset.0,
+            emissive_texture_uv_offset: emissive_texture_transform.offset.0,
+            base_color_texture_uv_scale: base_color_texture_transform.scale.0,
+            metallic_roughness_texture_uv_scale: metallic_roughness_texture_transform.scale.0,
+            normal_texture_uv_scale: normal_texture_transform.scale.0,
+            occlusion_texture_uv_scale: occlusion_texture_transform.scale.0,
+            emissive_texture_uv_scale: emissive_texture_transform.scale.0,
+            base_color_texture_uv_rotation: base_color_texture_transform.rotation,
+            metallic_roughness_texture_uv_rotation: metallic_roughness_texture_transform.rotation,
+            normal_texture_uv_rotation: normal_texture_transform.rotation,
+            occlusion_texture_uv_rotation: occlusion_texture_transform.rotation,
+            emissive_texture_uv_rotation: emissive_texture_transform.rotation,
+            unlit: unlit as u32,
             _dummy0: Default::default(),
             _dummy1: Default::default(),
             _dummy2: Default::default(),
@@ -143,14 +240,28 @@ impl Default for MaterialUBO {
             1.0,
             false,
             [0.0, 0.0, 0.0].into(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            false,
         )
     }
 }
 
 impl PushConstants {
-    pub fn new(vertex_color_provided: bool) -> Self {
+    pub fn new(
+        vertex_color_provided: bool,
+        skinning_provided: bool,
+        morph_target_count: u32,
+        morph_target_offset: u32,
+    ) -> Self {
         Self {
             vertex_color_provided: vertex_color_provided as u32,
+            skinning_provided: skinning_provided as u32,
+            morph_target_count,
+            morph_target_offset,
         }
     }
 }
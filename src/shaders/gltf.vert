@@ -5,14 +5,20 @@
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec4 tangent;
-layout(location = 3) in vec2 tex_coord;
-layout(location = 4) in vec4 vertex_color;
+layout(location = 3) in vec2 tex_coord_0;
+layout(location = 4) in vec2 tex_coord_1;
+layout(location = 5) in vec4 vertex_color;
+layout(location = 6) in uvec4 joints;
+layout(location = 7) in vec4 weights;
+// The transformation of the instance, occupies the locations 8 to 11
+layout(location = 8) in mat4 instance_matrix;
 
 layout(location = 0) out vec3 f_world_position;
 layout(location = 1) out vec3 f_world_normal;
 layout(location = 2) out vec4 f_world_tangent;
-layout(location = 3) out vec2 f_tex_coord;
-layout(location = 4) out vec4 f_vertex_color;
+layout(location = 3) out vec2 f_tex_coord_0;
+layout(location = 4) out vec2 f_tex_coord_1;
+layout(location = 5) out vec4 f_vertex_color;
 
 const mat4 y_inversion = mat4(
     1.0,  0.0,  0.0,  0.0,
@@ -22,16 +28,41 @@ const mat4 y_inversion = mat4(
 );
 
 void main() {
+    // Blend the morph targets
+    vec3 morphed_position = position;
+    vec3 morphed_normal = normal;
+    vec3 morphed_tangent = tangent.xyz;
+
+    for (uint target = 0; target < morph_target_count; target++) {
+        uint delta_index = morph_target_offset + (uint(gl_VertexIndex) * morph_target_count + target) * 3;
+        float weight = morph_target_weights[target];
+
+        morphed_position += weight * morph_target_deltas[delta_index].xyz;
+        morphed_normal += weight * morph_target_deltas[delta_index + 1].xyz;
+        morphed_tangent += weight * morph_target_deltas[delta_index + 2].xyz;
+    }
+
     // Ensure the normal and tangent are orthonormal
-    vec3 normalized_normal = normalize(normal);
-    vec3 normalized_tangent = normalize(tangent.xyz);
+    vec3 normalized_normal = normalize(morphed_normal);
+    vec3 normalized_tangent = normalize(morphed_tangent);
     vec3 corrected_tangent = GRAM_SCHMIDT(normalized_tangent, normalized_normal);
 
+    // Blend the joint matrices of skinned primitives
+    mat4 node_matrix = matrix;
+
+    if (skinning_provided) {
+        mat4 skin_matrix = weights.x * joint_matrices[joints.x]
+                         + weights.y * joint_matrices[joints.y]
+                         + weights.z * joint_matrices[joints.z]
+                         + weights.w * joint_matrices[joints.w];
+        node_matrix = node_matrix * skin_matrix;
+    }
+
     // Apply the transformation of primitives to view space
-    vec4 world_position = model * matrix * vec4(position, 1.0);
+    vec4 world_position = instance_matrix * node_matrix * vec4(morphed_position, 1.0);
     // Note: trying to invert and transpose the 4x4 matrix results in artifacts
-    vec3 world_normal = normalize(transpose(inverse(mat3(model * matrix))) * normalized_normal);
-    vec3 world_tangent = mat3(model * matrix) * corrected_tangent.xyz;
+    vec3 world_normal = normalize(transpose(inverse(mat3(instance_matrix * node_matrix))) * normalized_normal);
+    vec3 world_tangent = mat3(instance_matrix * node_matrix) * corrected_tangent.xyz;
 
     // Ensure the normal and tangent are orthonormal, again
     vec3 corrected_world_tangent = normalize(GRAM_SCHMIDT(world_tangent, world_normal));
@@ -39,7 +70,10 @@ void main() {
     f_world_position = PROJECT(world_position);
     f_world_normal = world_normal;
     f_world_tangent = vec4(corrected_world_tangent, tangent.w);
-    f_tex_coord = tex_coord;
+    f_tex_coord_0 = tex_coord_0;
+    f_tex_coord_1 = tex_coord_1;
     f_vertex_color = vertex_color;
     gl_Position = y_inversion * projection * view * world_position;
+    // Required when rasterizing `POINTS` primitives
+    gl_PointSize = 1.0;
 }
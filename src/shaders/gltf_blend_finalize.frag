@@ -1,8 +1,8 @@
 #version 450
 #include "gltf_common.frag"
 
-layout(set = 4, binding = 0, input_attachment_index = 0) uniform subpassInput attachment_accumulation;
-layout(set = 4, binding = 1, input_attachment_index = 1) uniform subpassInput attachment_revealage;
+layout(set = 3, binding = 0, input_attachment_index = 0) uniform subpassInput attachment_accumulation;
+layout(set = 3, binding = 1, input_attachment_index = 1) uniform subpassInput attachment_revealage;
 
 layout(location = 0) out vec4 out_color;
 
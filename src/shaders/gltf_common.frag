@@ -9,7 +9,8 @@
         f_world_position,                       \
         f_world_normal,                         \
         f_world_tangent,                        \
-        f_tex_coord,                            \
+        f_tex_coord_0,                          \
+        f_tex_coord_1,                          \
         f_vertex_color,                         \
                                                 \
         base_color_texture_provided,            \
@@ -54,6 +55,31 @@ vec2 get_normalized_frag_coord(in vec2 dimensions) {
     return gl_FragCoord.xy / dimensions;
 }
 
+// Selects the UV set and applies the KHR_texture_transform extension
+vec2 get_tex_coord(in uint tex_coord_index,
+                   in vec2 uv_offset,
+                   in float uv_rotation,
+                   in vec2 uv_scale) {
+    vec2 tex_coord = tex_coord_index == 1 ? f_tex_coord_1 : f_tex_coord_0;
+    mat3 translation = mat3(
+        1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        uv_offset.x, uv_offset.y, 1.0
+    );
+    mat3 rotation = mat3(
+        cos(uv_rotation), -sin(uv_rotation), 0.0,
+        sin(uv_rotation), cos(uv_rotation), 0.0,
+        0.0, 0.0, 1.0
+    );
+    mat3 scale = mat3(
+        uv_scale.x, 0.0, 0.0,
+        0.0, uv_scale.y, 0.0,
+        0.0, 0.0, 1.0
+    );
+
+    return (translation * rotation * scale * vec3(tex_coord, 1.0)).xy;
+}
+
 vec4 sample_base_color(in bool base_color_texture_provided,
                        in vec4 base_color_factor,
                        in texture2D base_color_texture,
@@ -246,6 +272,46 @@ vec3 pbr(in vec4 base_color,
     return f * radiance * brdf_params.NdotL;
 }
 
+// Computes the radiance arriving at the given position from the light, as recommended by
+// KHR_lights_punctual, and the direction pointing towards the light.
+vec3 get_incoming_light_radiance(in LightData light,
+                                 in vec3 world_position,
+                                 out vec3 light_world_direction) {
+    if (light.kind == LIGHT_KIND_DIRECTIONAL) {
+        light_world_direction = -normalize(light.direction);
+
+        return light.color * light.intensity;
+    }
+
+    vec3 to_light = light.position - world_position;
+    float distance_squared = max(dot(to_light, to_light), 0.0001);
+    float distance_attenuation;
+
+    light_world_direction = to_light * inversesqrt(distance_squared);
+
+    if (light.range > 0.0) {
+        float distance_ratio = sqrt(distance_squared) / light.range;
+        float distance_ratio_4 = distance_ratio * distance_ratio * distance_ratio * distance_ratio;
+
+        distance_attenuation = clamp(1.0 - distance_ratio_4, 0.0, 1.0) / distance_squared;
+    } else {
+        distance_attenuation = 1.0 / distance_squared;
+    }
+
+    float angular_attenuation = 1.0;
+
+    if (light.kind == LIGHT_KIND_SPOT) {
+        float cone_cos = dot(normalize(light.direction), -light_world_direction);
+        float cone_scale = 1.0 / max(light.inner_cone_cos - light.outer_cone_cos, 0.001);
+        float cone_offset = -light.outer_cone_cos * cone_scale;
+
+        angular_attenuation = clamp(cone_cos * cone_scale + cone_offset, 0.0, 1.0);
+        angular_attenuation *= angular_attenuation;
+    }
+
+    return light.color * light.intensity * distance_attenuation * angular_attenuation;
+}
+
 // Immediately returns if the current fragment is within the specified region.
 #define VISUALIZE_VECTOR_INVERT(vector, top_left, bottom_right, dimensions) do {  \
     vec2 coord = get_normalized_frag_coord(dimensions);                           \
@@ -290,36 +356,67 @@ vec4 get_final_color() {
         base_color_factor,
         base_color_texture,
         base_color_sampler,
-        f_tex_coord,
+        get_tex_coord(
+            base_color_texture_tex_coord,
+            base_color_texture_uv_offset,
+            base_color_texture_uv_rotation,
+            base_color_texture_uv_scale
+        ),
         f_vertex_color
     );
+
+    // Materials with KHR_materials_unlit skip lighting entirely
+    if (unlit) {
+        return base_color;
+    }
+
     vec2 metallic_roughness = sample_metallic_roughness(
         metallic_roughness_texture_provided,
         metallic_roughness_factor,
         metallic_roughness_texture,
         metallic_roughness_sampler,
-        f_tex_coord
+        get_tex_coord(
+            metallic_roughness_texture_tex_coord,
+            metallic_roughness_texture_uv_offset,
+            metallic_roughness_texture_uv_rotation,
+            metallic_roughness_texture_uv_scale
+        )
     );
     vec3 sampled_normal = sample_normal(
         normal_texture_provided,
         normal_texture_scale,
         normal_texture,
         normal_sampler,
-        f_tex_coord
+        get_tex_coord(
+            normal_texture_tex_coord,
+            normal_texture_uv_offset,
+            normal_texture_uv_rotation,
+            normal_texture_uv_scale
+        )
     );
     float occlusion = sample_occlusion(
         occlusion_texture_provided,
         occlusion_strength,
         occlusion_texture,
         occlusion_sampler,
-        f_tex_coord
+        get_tex_coord(
+            occlusion_texture_tex_coord,
+            occlusion_texture_uv_offset,
+            occlusion_texture_uv_rotation,
+            occlusion_texture_uv_scale
+        )
     );
     vec3 emissive = sample_emissive(
         emissive_texture_provided,
         emissive_factor,
         emissive_texture,
         emissive_sampler,
-        f_tex_coord
+        get_tex_coord(
+            emissive_texture_tex_coord,
+            emissive_texture_uv_offset,
+            emissive_texture_uv_rotation,
+            emissive_texture_uv_scale
+        )
     );
 
     // Construct an orthonormal TBN matrix
@@ -327,12 +424,6 @@ vec4 get_final_color() {
     // We can use `transpose` to invert the matrix as it's orthonormal
     mat3 canonical_to_tangent = transpose(tangent_to_canonical);
 
-    vec3 light_world_position[3] = {
-        vec3(1.0, 1.5, 2.0),
-        vec3(-1.0, -1.5, 2.0),
-        vec3(-1.0, 1.5, -2.0)
-    };
-
     /* if (normalized_frag_coord.x + normalized_frag_coord.y > 1.0) { */
     /*     world_normal = normalize(tangent_to_canonical * sampled_normal); */
     /* } */
@@ -351,9 +442,9 @@ vec4 get_final_color() {
     vec3 eye_direction = normalize(camera_position - world_position);
     vec3 accumulated_radiance = vec3(0.0);
 
-    for (int i = 0; i < 3; i++) {
-        vec3 light_world_direction = normalize(light_world_position[i] - world_position);
-        vec3 incoming_light_radiance = vec3(1.0);
+    for (uint i = 0; i < min(light_count, uint(MAX_LIGHTS)); i++) {
+        vec3 light_world_direction;
+        vec3 incoming_light_radiance = get_incoming_light_radiance(lights[i], world_position, light_world_direction);
 
         vec3 F;
         float G;
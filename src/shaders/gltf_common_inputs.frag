@@ -3,5 +3,6 @@
 layout(location = 0) in vec3 f_world_position;
 layout(location = 1) in vec3 f_world_normal;
 layout(location = 2) in vec4 f_world_tangent;
-layout(location = 3) in vec2 f_tex_coord;
-layout(location = 4) in vec4 f_vertex_color;
+layout(location = 3) in vec2 f_tex_coord_0;
+layout(location = 4) in vec2 f_tex_coord_1;
+layout(location = 5) in vec4 f_vertex_color;
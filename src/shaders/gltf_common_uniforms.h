@@ -1,3 +1,20 @@
+#define MAX_LIGHTS 16
+#define LIGHT_KIND_DIRECTIONAL 0
+#define LIGHT_KIND_POINT 1
+#define LIGHT_KIND_SPOT 2
+
+struct LightData {
+    vec3 position;
+    uint kind;
+    vec3 direction;
+    float range; // 0 for an infinite range
+    vec3 color;
+    float intensity;
+    float inner_cone_cos;
+    float outer_cone_cos;
+    vec2 _padding;
+};
+
 layout(set = 0, binding = 0) uniform SceneUBO {
     float time_elapsed;
     vec2 dimensions;
@@ -5,16 +22,26 @@ layout(set = 0, binding = 0) uniform SceneUBO {
     mat4 view;
     mat4 projection;
 };
-
-layout(set = 1, binding = 0) uniform InstanceUBO {
-    mat4 model;
+layout(set = 0, binding = 1) uniform LightsUBO {
+    uint light_count;
+    LightData lights[MAX_LIGHTS];
 };
 
-layout(set = 2, binding = 0) uniform NodeUBO {
+layout(set = 1, binding = 0) uniform NodeUBO {
     mat4 matrix;
 };
+layout(set = 1, binding = 1) readonly buffer JointMatrices {
+    mat4 joint_matrices[];
+};
+// For each vertex, the position, normal and tangent deltas of each morph target
+layout(set = 1, binding = 2) readonly buffer MorphTargetDeltas {
+    vec4 morph_target_deltas[];
+};
+layout(set = 1, binding = 3) readonly buffer MorphTargetWeights {
+    float morph_target_weights[];
+};
 
-layout(set = 3, binding = 0) uniform MaterialUBO {
+layout(set = 2, binding = 0) uniform MaterialUBO {
     float alpha_cutoff;
 
     bool base_color_texture_provided;
@@ -31,18 +58,48 @@ layout(set = 3, binding = 0) uniform MaterialUBO {
 
     bool emissive_texture_provided;
     vec3 emissive_factor;
+
+    // Indices of the UV sets (TEXCOORD_n) each texture is sampled with
+    uint base_color_texture_tex_coord;
+    uint metallic_roughness_texture_tex_coord;
+    uint normal_texture_tex_coord;
+    uint occlusion_texture_tex_coord;
+    uint emissive_texture_tex_coord;
+
+    // KHR_texture_transform of each texture
+    vec2 base_color_texture_uv_offset;
+    vec2 metallic_roughness_texture_uv_offset;
+    vec2 normal_texture_uv_offset;
+    vec2 occlusion_texture_uv_offset;
+    vec2 emissive_texture_uv_offset;
+    vec2 base_color_texture_uv_scale;
+    vec2 metallic_roughness_texture_uv_scale;
+    vec2 normal_texture_uv_scale;
+    vec2 occlusion_texture_uv_scale;
+    vec2 emissive_texture_uv_scale;
+    float base_color_texture_uv_rotation;
+    float metallic_roughness_texture_uv_rotation;
+    float normal_texture_uv_rotation;
+    float occlusion_texture_uv_rotation;
+    float emissive_texture_uv_rotation;
+
+    // KHR_materials_unlit
+    bool unlit;
 };
-layout(set = 3, binding =  1) uniform texture2D base_color_texture;
-layout(set = 3, binding =  2) uniform sampler base_color_sampler;
-layout(set = 3, binding =  3) uniform texture2D metallic_roughness_texture;
-layout(set = 3, binding =  4) uniform sampler metallic_roughness_sampler;
-layout(set = 3, binding =  5) uniform texture2D normal_texture;
-layout(set = 3, binding =  6) uniform sampler normal_sampler;
-layout(set = 3, binding =  7) uniform texture2D occlusion_texture;
-layout(set = 3, binding =  8) uniform sampler occlusion_sampler;
-layout(set = 3, binding =  9) uniform texture2D emissive_texture;
-layout(set = 3, binding = 10) uniform sampler emissive_sampler;
+layout(set = 2, binding =  1) uniform texture2D base_color_texture;
+layout(set = 2, binding =  2) uniform sampler base_color_sampler;
+layout(set = 2, binding =  3) uniform texture2D metallic_roughness_texture;
+layout(set = 2, binding =  4) uniform sampler metallic_roughness_sampler;
+layout(set = 2, binding =  5) uniform texture2D normal_texture;
+layout(set = 2, binding =  6) uniform sampler normal_sampler;
+layout(set = 2, binding =  7) uniform texture2D occlusion_texture;
+layout(set = 2, binding =  8) uniform sampler occlusion_sampler;
+layout(set = 2, binding =  9) uniform texture2D emissive_texture;
+layout(set = 2, binding = 10) uniform sampler emissive_sampler;
 
 layout(push_constant) uniform PushConstants {
     bool vertex_color_provided;
+    bool skinning_provided;
+    uint morph_target_count;
+    uint morph_target_offset;
 };
@@ -1,6 +1,10 @@
-use std::mem;
 use std::sync::Arc;
+use std::mem;
+use std::os::raw::{c_char, c_void};
 use vulkano;
+use vulkano::VulkanObject;
+use vulkano::instance::PhysicalDevice;
+use vulkano::instance::loader;
 use vulkano::pipeline::vertex::VertexSource;
 use vulkano::buffer::BufferAccess;
 use vulkano::buffer::TypedBufferAccess;
@@ -9,10 +13,12 @@ use vulkano::pipeline::vertex::VertexDefinition;
 use vulkano::pipeline::vertex::InputRate;
 use vulkano::pipeline::vertex::AttributeInfo;
 use vulkano::pipeline::shader::ShaderInterfaceDef;
+use vulkano::format::Format;
 use typenum::*;
 use safe_transmute::PodTransmutable;
 use gltf::mesh::Semantic;
 use gltf::mesh::Primitive;
+use gltf::accessor::{DataType, Dimensions};
 use crate::iter::ArrayIterator;
 use crate::shaders::gltf_vert::MainInput;
 
@@ -41,17 +47,160 @@ unsafe impl PodTransmutable for GltfVertexTexCoord {}
 pub struct GltfVertexColor(pub [f32; 4]);
 unsafe impl PodTransmutable for GltfVertexColor {}
 
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GltfVertexJoints(pub [u32; 4]);
+unsafe impl PodTransmutable for GltfVertexJoints {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GltfVertexWeights(pub [f32; 4]);
+unsafe impl PodTransmutable for GltfVertexWeights {}
+
+/// The transformation of an instance, read by the vertex shader as a per-instance attribute
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GltfInstanceMatrix(pub [[f32; 4]; 4]);
+unsafe impl PodTransmutable for GltfInstanceMatrix {}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct VertexAttributeProperties {
     pub stride: usize,
+    pub format: Format,
+}
+
+/// Maps the component type, the number of components and the `normalized` flag of an accessor to
+/// the Vulkan format the vertex attribute is read with. Non-normalized integers are converted to
+/// floats by the input assembler, as allowed by `KHR_mesh_quantization`.
+/// Returns `None` for matrix accessors, which cannot be used as vertex attributes.
+pub fn get_vertex_attribute_format(data_type: DataType, dimensions: Dimensions, normalized: bool) -> Option<Format> {
+    macro_rules! select_format {
+        ($($dimensions:ident => [
+            $u8_norm:ident, $u8_scaled:ident, $i8_norm:ident, $i8_scaled:ident,
+            $u16_norm:ident, $u16_scaled:ident, $i16_norm:ident, $i16_scaled:ident,
+            $u32:ident, $f32:ident$(,)?
+        ]),+$(,)?) => {
+            match dimensions {
+                $(
+                    Dimensions::$dimensions => Some(match (data_type, normalized) {
+                        (DataType::U8, true) => Format::$u8_norm,
+                        (DataType::U8, false) => Format::$u8_scaled,
+                        (DataType::I8, true) => Format::$i8_norm,
+                        (DataType::I8, false) => Format::$i8_scaled,
+                        (DataType::U16, true) => Format::$u16_norm,
+                        (DataType::U16, false) => Format::$u16_scaled,
+                        (DataType::I16, true) => Format::$i16_norm,
+                        (DataType::I16, false) => Format::$i16_scaled,
+                        (DataType::U32, _) => Format::$u32,
+                        (DataType::F32, _) => Format::$f32,
+                    }),
+                )+
+                _ => None,
+            }
+        }
+    }
+
+    select_format! {
+        Scalar => [
+            R8Unorm, R8Uscaled, R8Snorm, R8Sscaled,
+            R16Unorm, R16Uscaled, R16Snorm, R16Sscaled,
+            R32Uint, R32Sfloat,
+        ],
+        Vec2 => [
+            R8G8Unorm, R8G8Uscaled, R8G8Snorm, R8G8Sscaled,
+            R16G16Unorm, R16G16Uscaled, R16G16Snorm, R16G16Sscaled,
+            R32G32Uint, R32G32Sfloat,
+        ],
+        Vec3 => [
+            R8G8B8Unorm, R8G8B8Uscaled, R8G8B8Snorm, R8G8B8Sscaled,
+            R16G16B16Unorm, R16G16B16Uscaled, R16G16B16Snorm, R16G16B16Sscaled,
+            R32G32B32Uint, R32G32B32Sfloat,
+        ],
+        Vec4 => [
+            R8G8B8A8Unorm, R8G8B8A8Uscaled, R8G8B8A8Snorm, R8G8B8A8Sscaled,
+            R16G16B16A16Unorm, R16G16B16A16Uscaled, R16G16B16A16Snorm, R16G16B16A16Sscaled,
+            R32G32B32A32Uint, R32G32B32A32Sfloat,
+        ],
+    }
+}
+
+/// Whether the physical device supports reading vertex attributes in the format.
+/// Queried through the raw Vulkan API, as neither format properties nor the function pointers of
+/// the instance are exposed by `vulkano`. Formats are reported as unsupported if the function
+/// cannot be loaded.
+pub fn is_vertex_buffer_format_supported(physical_device: PhysicalDevice, format: Format) -> bool {
+    #[repr(C)]
+    struct FormatProperties {
+        linear_tiling_features: u32,
+        optimal_tiling_features: u32,
+        buffer_features: u32,
+    }
+
+    type GetPhysicalDeviceFormatProperties = extern "system" fn(usize, u32, *mut FormatProperties);
+
+    const FORMAT_FEATURE_VERTEX_BUFFER_BIT: u32 = 0x0000_0040;
+    const FUNCTION_NAME: &[u8] = b"vkGetPhysicalDeviceFormatProperties\0";
+
+    // `Instance::new` loads the function pointers of the instance through the lazily initialized
+    // `auto_loader`, this returns the same loader rather than loading Vulkan again
+    let function_pointers = if let Ok(function_pointers) = loader::auto_loader() {
+        function_pointers
+    } else {
+        return false;
+    };
+    // The loader returns a null pointer for functions it cannot find
+    let function = function_pointers.get_instance_proc_addr(
+        physical_device.instance().internal_object(),
+        FUNCTION_NAME.as_ptr() as *const c_char,
+    ) as *const c_void;
+
+    if function.is_null() {
+        return false;
+    }
+
+    let function: GetPhysicalDeviceFormatProperties = unsafe { mem::transmute(function) };
+    let mut properties = FormatProperties {
+        linear_tiling_features: 0,
+        optimal_tiling_features: 0,
+        buffer_features: 0,
+    };
+
+    function(physical_device.internal_object(), format as u32, &mut properties);
+
+    properties.buffer_features & FORMAT_FEATURE_VERTEX_BUFFER_BIT != 0
+}
+
+/// The number of vertices in a vertex buffer, ignoring buffers with a stride of 0 (constant
+/// attributes). The last element of a buffer does not have to be padded to the full stride.
+fn vertex_count(buffer_sizes_and_strides: impl IntoIterator<Item=(usize, usize)>) -> usize {
+    buffer_sizes_and_strides.into_iter()
+        .filter(|&(_, stride)| stride > 0)
+        .map(|(size, stride)| (size + stride - 1) / stride)
+        .min()
+        .unwrap()
+}
+
+/// The attribute of a single column of the instance matrix, as each column occupies a separate
+/// attribute location.
+fn instance_matrix_column_attribute(binding: u32, column: u32) -> (u32, u32, AttributeInfo) {
+    (
+        binding + column,
+        binding,
+        AttributeInfo {
+            offset: column as usize * mem::size_of::<[f32; 4]>(),
+            format: Format::R32G32B32A32Sfloat,
+        },
+    )
 }
 
 macro_rules! impl_buffers {
     {
-        $field_len:expr, $field_len_ty:ty;
+        $field_len:expr; $buffer_len_ty:ty, $attribute_len_ty:ty;
         $([$field_name:ident: $($buffer_type_name:tt)+] of [$attribute_name:ident: $($attribute_type:tt)+] {
             default_stride: $default_stride:expr,
+            default_format: $default_format:ident,
             missing_stride: $missing_stride:expr,
+            converted: $converted:expr,
             semantic: $semantic:expr$(,)?
         }),+$(,)?
     } => {
@@ -67,8 +216,18 @@ macro_rules! impl_buffers {
 
                 $(
                     if let Some(accessor) = primitive.get(&$semantic) {
-                        if let Some(stride) = accessor.view().stride() {
-                            result.$attribute_name.stride = stride;
+                        // Converted attributes are always tightly packed, regardless of the
+                        // layout of the original buffer view.
+                        if !$converted {
+                            result.$attribute_name.stride = accessor.view()
+                                .and_then(|view| view.stride())
+                                .unwrap_or_else(|| accessor.size());
+                            // Matrix accessors are rejected during import
+                            result.$attribute_name.format = get_vertex_attribute_format(
+                                accessor.data_type(),
+                                accessor.dimensions(),
+                                accessor.normalized(),
+                            ).unwrap_or(Format::$default_format);
                         }
                     } else {
                         // For mandatory vertex attributes, set stride to default stride.
@@ -81,12 +240,27 @@ macro_rules! impl_buffers {
             }
         }
 
+        /// The semantics of vertex attributes read from the buffer views of the model, in the
+        /// formats of their accessors. Other attributes are converted during import.
+        pub fn unconverted_vertex_attribute_semantics() -> Vec<Semantic> {
+            let mut result = Vec::new();
+
+            $(
+                if !$converted {
+                    result.push($semantic);
+                }
+            )+
+
+            result
+        }
+
         impl Default for VertexAttributePropertiesSet {
             fn default() -> Self {
                 VertexAttributePropertiesSet {
                     $(
                         $attribute_name: VertexAttributeProperties {
                             stride: $default_stride,
+                            format: Format::$default_format,
                         }
                     ),+
                 }
@@ -96,6 +270,7 @@ macro_rules! impl_buffers {
         pub struct GltfVertexBuffers<$($($buffer_type_name),+),+>
                 where $($($buffer_type_name)+: TypedBufferAccess<Content=[$($attribute_type)+]> + Send + Sync + 'static,)+ {
             $(pub $field_name: Option<Arc<$($buffer_type_name)+>>,)+
+            pub instance_buffer: Option<Arc<dyn TypedBufferAccess<Content=[GltfInstanceMatrix]> + Send + Sync>>,
         }
 
         impl<$($($buffer_type_name),+),+> GltfVertexBuffers<$($($buffer_type_name),+),+>
@@ -109,6 +284,10 @@ macro_rules! impl_buffers {
                     }
                 )+
 
+                if let Some(ref buffer) = self.instance_buffer {
+                    result.push(buffer.clone());
+                }
+
                 result
             }
         }
@@ -130,21 +309,20 @@ macro_rules! impl_buffers {
                     -> (Vec<Box<dyn BufferAccess + Send + Sync>>, usize, usize) {
                 let GltfVertexBuffers {$(
                     $field_name,
-                )+} = buffers;
-                let vertices = [$(
-                    ($field_name).as_ref().map(|buffer| buffer.size() / mem::size_of::<$($attribute_type)+>()),
-                )+].into_iter()
-                   .cloned()
-                   .filter(Option::is_some)
-                   .map(Option::unwrap)
-                   .min()
-                   .unwrap();
-                let instances = 1;
+                )+ instance_buffer} = buffers;
+                let vertices = vertex_count(vec![$(
+                    ($field_name).as_ref().map(|buffer| (buffer.size(), self.properties_set.$attribute_name.stride)),
+                )+].into_iter().filter_map(|size_and_stride| size_and_stride));
+                let instances = instance_buffer.as_ref()
+                    .map(|buffer| buffer.len())
+                    .unwrap_or(1);
 
                 let individual_buffers: Vec<Box<dyn BufferAccess + Send + Sync>> = {
                     vec![$(
                         ($field_name).map(|field| Box::new(field) as Box<dyn BufferAccess + Send + Sync>),
-                    )+].into_iter()
+                    )+
+                        instance_buffer.map(|buffer| Box::new(buffer) as Box<dyn BufferAccess + Send + Sync>),
+                    ].into_iter()
                        .filter(Option::is_some)
                        .map(Option::unwrap)
                        .collect()
@@ -157,13 +335,17 @@ macro_rules! impl_buffers {
         unsafe impl VertexSource<Vec<Arc<dyn BufferAccess + Send + Sync>>> for GltfVertexBufferDefinition {
             fn decode(&self, buffers: Vec<Arc<dyn BufferAccess + Send + Sync>>)
                     -> (Vec<Box<dyn BufferAccess + Send + Sync>>, usize, usize) {
-                let attribute_sizes = [$(
-                    mem::size_of::<$($attribute_type)+>(),
+                let attribute_strides = [$(
+                    self.properties_set.$attribute_name.stride,
                 )+];
-                let vertices = buffers.iter().zip(attribute_sizes.into_iter())
-                    .map(|(buffer, attribute_size)| buffer.size() / attribute_size)
-                    .min().unwrap();
-                let instances = 1;
+                let vertices = vertex_count(
+                    buffers.iter().zip(attribute_strides.iter())
+                        .map(|(buffer, attribute_stride)| (buffer.size(), *attribute_stride))
+                );
+                // The per-vertex buffers are followed by the instance buffer
+                let instances = buffers.get($field_len)
+                    .map(|buffer| buffer.size() / mem::size_of::<GltfInstanceMatrix>())
+                    .unwrap_or(1);
 
                 let individual_buffers: Vec<Box<dyn BufferAccess + Send + Sync>> = {
                     buffers.into_iter()
@@ -176,23 +358,33 @@ macro_rules! impl_buffers {
         }
 
         unsafe impl VertexDefinition<MainInput> for GltfVertexBufferDefinition {
-            type BuffersIter = ArrayIterator<(u32, usize, InputRate), $field_len_ty>;
-            type AttribsIter = ArrayIterator<(u32, u32, AttributeInfo), $field_len_ty>;
+            type BuffersIter = ArrayIterator<(u32, usize, InputRate), $buffer_len_ty>;
+            type AttribsIter = ArrayIterator<(u32, u32, AttributeInfo), $attribute_len_ty>;
 
             #[allow(unused_variables, unused_assignments)]
             fn definition(&self, interface: &MainInput)
                     -> Result<(Self::BuffersIter, Self::AttribsIter), IncompatibleVertexDefinitionError> {
-                let mut buffers: [(u32, usize, InputRate); $field_len] = [$({
+                // The instance matrix is bound after the per-vertex attributes
+                let mut buffers: [(u32, usize, InputRate); $field_len + 1] = [$({
                     let $field_name: ();
                     (0, 0, InputRate::Vertex)
-                },)+];
-                let mut attribs: [(u32, u32, AttributeInfo); $field_len] = [$({
+                },)+ (
+                    $field_len as u32,
+                    mem::size_of::<GltfInstanceMatrix>(),
+                    InputRate::Instance,
+                )];
+                let mut attribs: [(u32, u32, AttributeInfo); $field_len + 4] = [$({
                     let $field_name: ();
                     (0, 0, AttributeInfo {
                         offset: 0,
                         format: vulkano::format::Format::R4G4UnormPack8,
                     })
-                },)+];
+                },)+
+                    instance_matrix_column_attribute($field_len as u32, 0),
+                    instance_matrix_column_attribute($field_len as u32, 1),
+                    instance_matrix_column_attribute($field_len as u32, 2),
+                    instance_matrix_column_attribute($field_len as u32, 3),
+                ];
 
                 // for (index, element) in interface.elements().enumerate() {
                 //     println!("element #{}: {:?}", index, element);
@@ -204,13 +396,23 @@ macro_rules! impl_buffers {
 
                 debug_assert_eq!(
                     interface.elements().len(),
-                    $field_len,
+                    $field_len + 1,
                     "The number of fields in the shader and program code is inconsistent.",
                 );
 
                 for element in interface.elements() {
                     let field_index = element.location.start as usize;
 
+                    if field_index >= $field_len {
+                        debug_assert_eq!(
+                            element.name.expect("Shader input attribute has no name.").as_ref(),
+                            "instance_matrix",
+                            "The field types in the shader and program code are inconsistent",
+                        );
+
+                        continue;
+                    }
+
                     debug_assert_eq!(
                         element.name.expect("Shader input attribute has no name.").as_ref(),
                         attribute_names[field_index],
@@ -228,7 +430,7 @@ macro_rules! impl_buffers {
                         field_index as u32,
                         AttributeInfo {
                             offset: 0,
-                            format: element.format,
+                            format: attribute_properties[field_index].format,
                         }
                     );
                 }
@@ -240,31 +442,65 @@ macro_rules! impl_buffers {
 }
 
 impl_buffers! {
-    5, U5;
+    // The number of per-vertex attributes, followed by the number of buffer bindings and
+    // attribute locations, including those of the instance matrix
+    8; U9, U12;
 
     [position_buffer: PositionBuffer] of [position: GltfVertexPosition] {
         default_stride: 4 * 3,
+        default_format: R32G32B32Sfloat,
         missing_stride: unreachable!(),
+        converted: false,
         semantic: Semantic::Positions,
     },
     [normal_buffer: NormalBuffer] of [normal: GltfVertexNormal] {
         default_stride: 4 * 3,
+        default_format: R32G32B32Sfloat,
         missing_stride: 4 * 3,
+        converted: false,
         semantic: Semantic::Normals,
     },
     [tangent_buffer: TangentBuffer] of [tangent: GltfVertexTangent] {
         default_stride: 4 * 4,
+        default_format: R32G32B32A32Sfloat,
         missing_stride: 4 * 4,
+        converted: false,
         semantic: Semantic::Tangents,
     },
-    [tex_coord_buffer: TexCoordBuffer] of [tex_coord: GltfVertexTexCoord] {
+    [tex_coord_0_buffer: TexCoord0Buffer] of [tex_coord_0: GltfVertexTexCoord] {
+        default_stride: 4 * 2,
+        default_format: R32G32Sfloat,
+        missing_stride: 0,
+        converted: false,
+        semantic: Semantic::TexCoords(0),
+    },
+    [tex_coord_1_buffer: TexCoord1Buffer] of [tex_coord_1: GltfVertexTexCoord] {
         default_stride: 4 * 2,
+        default_format: R32G32Sfloat,
         missing_stride: 0,
-        semantic: Semantic::TexCoords(0), //TODO
+        converted: false,
+        semantic: Semantic::TexCoords(1),
     },
     [vertex_color_buffer: VertexColorBuffer] of [vertex_color: GltfVertexColor] {
         default_stride: 4 * 4,
+        default_format: R32G32B32A32Sfloat,
+        missing_stride: 0,
+        converted: false,
+        // Only `COLOR_0` contributes to the material, additional sets are application-specific
+        semantic: Semantic::Colors(0),
+    },
+    [joints_buffer: JointsBuffer] of [joints: GltfVertexJoints] {
+        default_stride: 4 * 4,
+        default_format: R32G32B32A32Uint,
+        missing_stride: 0,
+        converted: true,
+        semantic: Semantic::Joints(0),
+    },
+    [weights_buffer: WeightsBuffer] of [weights: GltfVertexWeights] {
+        default_stride: 4 * 4,
+        default_format: R32G32B32A32Sfloat,
         missing_stride: 0,
-        semantic: Semantic::Colors(0), //TODO
+        converted: true,
+        semantic: Semantic::Weights(0),
     },
 }